@@ -0,0 +1,610 @@
+//! Assembler for Cowgod style mnemonics, as printed by `chip8-disasm`.
+//!
+//! One instruction or directive per line, with an optional `label:` in
+//! front. Mnemonics, registers and directives are case insensitive, names
+//! are not. `;` and `//` start comments.
+//!
+//! ```text
+//! start:  ld v0, 0x05
+//!         ld i, sprite + 2     ; names, numbers, + - and ( ) make expressions
+//!         drw v0, v1, 5
+//!         jp start
+//! sprite: db 0xF0, 0x90, "text"
+//! ```
+//!
+//! - Instructions are written as in Cowgod's reference, `ld [i], v3`,
+//!   `drw v0, v1, 5` and so on, with the SUPER-CHIP and XO-CHIP additions
+//!   (`scd`, `scr`, `scl`, `exit`, `low`, `high`, `ld hf, vx`, `ld r, vx`,
+//!   `save vx, vy`, `load vx, vy`, `plane n`, `audio`, `pitch vx` and
+//!   `ld i, long ADDR`). A lone 4 digit hex word such as `00E0` is emitted
+//!   as a raw opcode.
+//! - Numbers are decimal, `0x` hex or `0b` binary, `_` separates digits.
+//! - `NAME = value` or `NAME equ value` defines a constant, which must only
+//!   use names defined above it. Labels can be used before they are defined.
+//! - `db` emits bytes and ASCII strings, `dw` emits big endian words. Bytes
+//!   may be written signed, -128 to 255. Strings may use the escapes `\n`,
+//!   `\\` and `\"`.
+//! - `org ADDR` moves on to ADDR, from `PROGRAM_START` up, `align N` pads
+//!   with zeros to a multiple of N.
+//! - `include "file"` reads another file in place, relative to the file
+//!   including it.
+//!
+//! Errors point at the file, line and column they were found at.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::instruction::{encode, Instruction};
+use crate::{MEMORY_SIZE, PROGRAM_START};
+
+// Deep enough for any real program, shallow enough to catch a file including itself
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// An assembly error, pointing at the file, line and column it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Tok {
+    // Mnemonics, registers, names and numbers
+    Word(String),
+    Str(Vec<u8>),
+    Punct(char),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    column: usize,
+}
+
+impl Token {
+    fn is(&self, c: char) -> bool {
+        self.tok == Tok::Punct(c)
+    }
+
+    // The word in lower case, for matching mnemonics and registers
+    fn keyword(&self) -> Option<String> {
+        match &self.tok {
+            Tok::Word(word) => Some(word.to_ascii_lowercase()),
+            _ => None,
+        }
+    }
+}
+
+// One line of source after includes are expanded
+struct Line {
+    file: Rc<str>,
+    number: usize,
+    tokens: Vec<Token>,
+}
+
+impl Line {
+    fn error(&self, column: usize, message: String) -> AsmError {
+        AsmError {
+            file: self.file.to_string(),
+            line: self.number,
+            column,
+            message,
+        }
+    }
+}
+
+fn tokenize(text: &str, error: impl Fn(usize, String) -> AsmError) -> Result<Vec<Token>, AsmError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == ';' || (c == '/' && chars.get(i + 1) == Some(&'/')) {
+            break;
+        } else if c == '"' {
+            let mut bytes = Vec::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(error(column, "unterminated string".to_string())),
+                    Some('"') => break,
+                    Some('\\') => {
+                        let byte = match chars.get(i + 1) {
+                            Some('n') => b'\n',
+                            Some('\\') => b'\\',
+                            Some('"') => b'"',
+                            Some(c) => return Err(error(i + 1, format!("unknown escape '\\{}'", c))),
+                            None => return Err(error(column, "unterminated string".to_string())),
+                        };
+                        bytes.push(byte);
+                        i += 2;
+                    }
+                    Some(&c) if c.is_ascii() => {
+                        bytes.push(c as u8);
+                        i += 1;
+                    }
+                    Some(&c) => return Err(error(i + 1, format!("'{}' is not ASCII", c))),
+                }
+            }
+            i += 1;
+            tokens.push(Token { tok: Tok::Str(bytes), column });
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token { tok: Tok::Word(chars[start..i].iter().collect()), column });
+        } else if ",:[]+-=()".contains(c) {
+            tokens.push(Token { tok: Tok::Punct(c), column });
+            i += 1;
+        } else {
+            return Err(error(column, format!("unexpected '{}'", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+// Read a source file into lines, expanding includes relative to the including file
+fn load(name: &str, text: &str, directory: &Path, depth: usize, lines: &mut Vec<Line>) -> Result<(), AsmError> {
+    let file: Rc<str> = Rc::from(name);
+    for (index, text) in text.lines().enumerate() {
+        let number = index + 1;
+        let error = |column, message| AsmError {
+            file: file.to_string(),
+            line: number,
+            column,
+            message,
+        };
+        let tokens = tokenize(text, error)?;
+
+        if tokens.first().and_then(Token::keyword).as_deref() == Some("include") {
+            let path = match tokens.as_slice() {
+                [_, Token { tok: Tok::Str(path), .. }] => String::from_utf8_lossy(path).into_owned(),
+                _ => return Err(error(tokens[0].column, "include needs a \"file name\" and nothing else".to_string())),
+            };
+            if depth >= MAX_INCLUDE_DEPTH {
+                return Err(error(tokens[0].column, "includes nested too deeply, does a file include itself?".to_string()));
+            }
+            let full_path = directory.join(&path);
+            let included = fs::read_to_string(&full_path).map_err(|err| error(tokens[1].column, format!("can't include {}: {}", path, err)))?;
+            let included_directory = full_path.parent().map(Path::to_path_buf).unwrap_or_default();
+            load(&full_path.to_string_lossy(), &included, &included_directory, depth + 1, lines)?;
+            continue;
+        }
+
+        lines.push(Line { file: file.clone(), number, tokens });
+    }
+    Ok(())
+}
+
+// An instruction operand
+enum Operand<'t> {
+    V(u8),
+    I,
+    // [I]
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    // LONG expression, the XO-CHIP 16 bit address of F000 NNNN
+    Long(&'t [Token]),
+    Expr(&'t [Token]),
+}
+
+fn operand(tokens: &[Token]) -> Operand<'_> {
+    if tokens.len() == 3 && tokens[0].is('[') && tokens[2].is(']') && tokens[1].keyword().as_deref() == Some("i") {
+        return Operand::IndirectI;
+    }
+    if tokens.len() > 1 && tokens[0].keyword().as_deref() == Some("long") {
+        return Operand::Long(&tokens[1..]);
+    }
+    if tokens.len() == 1 {
+        if let Some(word) = tokens[0].keyword() {
+            match word.as_str() {
+                "i" => return Operand::I,
+                "dt" => return Operand::Dt,
+                "st" => return Operand::St,
+                "k" => return Operand::K,
+                "f" => return Operand::F,
+                "hf" => return Operand::Hf,
+                "b" => return Operand::B,
+                "r" => return Operand::R,
+                _ => {}
+            }
+            if word.len() == 2 && word.starts_with('v') {
+                if let Ok(register) = u8::from_str_radix(&word[1..], 16) {
+                    return Operand::V(register);
+                }
+            }
+        }
+    }
+    Operand::Expr(tokens)
+}
+
+// Split tokens into comma separated groups
+fn split_operands(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+    tokens.split(|token| token.is(',')).collect()
+}
+
+fn parse_number(word: &str) -> Option<i64> {
+    let word = word.replace('_', "");
+    if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = word.strip_prefix("0b").or_else(|| word.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        word.parse().ok()
+    }
+}
+
+// A 4 hex digit word on its own is a raw opcode
+fn raw_opcode(word: &str) -> Option<u16> {
+    if word.len() == 4 && word.chars().all(|c| c.is_ascii_hexdigit()) {
+        u16::from_str_radix(word, 16).ok()
+    } else {
+        None
+    }
+}
+
+struct Assembler {
+    symbols: HashMap<String, i64>,
+    address: usize,
+    // 1 finds every label's address, 2 writes the bytes
+    pass: u8,
+    image: Vec<u8>,
+    written: Vec<bool>,
+    // One past the highest address written
+    end: usize,
+}
+
+impl Assembler {
+    // Evaluate an expression. Names not yet known are 0 in the first pass unless `now` is set
+    fn eval(&self, line: &Line, tokens: &[Token], now: bool) -> Result<i64, AsmError> {
+        let column = tokens.first().map_or(1, |token| token.column);
+        let mut position = 0;
+        let value = self.expression(line, tokens, &mut position, now)?;
+        match tokens.get(position) {
+            Some(token) => Err(line.error(token.column, "unexpected text after the value".to_string())),
+            None if tokens.is_empty() => Err(line.error(column, "missing value".to_string())),
+            None => Ok(value),
+        }
+    }
+
+    fn expression(&self, line: &Line, tokens: &[Token], position: &mut usize, now: bool) -> Result<i64, AsmError> {
+        let mut value = self.term(line, tokens, position, now)?;
+        while let Some(token) = tokens.get(*position) {
+            let operation: fn(i64, i64) -> Option<i64> = if token.is('+') {
+                i64::checked_add
+            } else if token.is('-') {
+                i64::checked_sub
+            } else {
+                break;
+            };
+            *position += 1;
+            let right = self.term(line, tokens, position, now)?;
+            value = operation(value, right).ok_or_else(|| line.error(token.column, "value overflows".to_string()))?;
+        }
+        Ok(value)
+    }
+
+    fn term(&self, line: &Line, tokens: &[Token], position: &mut usize, now: bool) -> Result<i64, AsmError> {
+        let token = match tokens.get(*position) {
+            Some(token) => token,
+            None => {
+                let column = tokens.last().map_or(1, |token| token.column + 1);
+                return Err(line.error(column, "missing value".to_string()));
+            }
+        };
+        *position += 1;
+
+        match &token.tok {
+            Tok::Punct('-') => {
+                let value = self.term(line, tokens, position, now)?;
+                value.checked_neg().ok_or_else(|| line.error(token.column, "value overflows".to_string()))
+            }
+            Tok::Punct('(') => {
+                let value = self.expression(line, tokens, position, now)?;
+                match tokens.get(*position) {
+                    Some(close) if close.is(')') => {
+                        *position += 1;
+                        Ok(value)
+                    }
+                    _ => Err(line.error(token.column, "unclosed '('".to_string())),
+                }
+            }
+            Tok::Word(word) if word.starts_with(|c: char| c.is_ascii_digit()) => {
+                parse_number(word).ok_or_else(|| line.error(token.column, format!("invalid number '{}'", word)))
+            }
+            Tok::Word(name) => match self.symbols.get(name) {
+                Some(value) => Ok(*value),
+                None if self.pass == 1 && !now => Ok(0),
+                None if now => Err(line.error(token.column, format!("'{}' must be defined before it is used here", name))),
+                None => Err(line.error(token.column, format!("undefined name '{}'", name))),
+            },
+            _ => Err(line.error(token.column, "expected a number or name".to_string())),
+        }
+    }
+
+    // Evaluate and range check, the check is skipped in the first pass where names may be 0
+    fn value_in(&self, line: &Line, tokens: &[Token], min: i64, max: i64, what: &str) -> Result<i64, AsmError> {
+        let value = self.eval(line, tokens, false)?;
+        if self.pass == 2 && (value < min || value > max) {
+            return Err(line.error(tokens[0].column, format!("{} {} is out of range {} to {}", what, value, min, max)));
+        }
+        Ok(value)
+    }
+
+    fn address_operand(&self, line: &Line, tokens: &[Token]) -> Result<u16, AsmError> {
+        Ok(self.value_in(line, tokens, 0, 0xFFF, "address")? as u16)
+    }
+
+    // Bytes may be written signed, -1 is 0xFF
+    fn byte_operand(&self, line: &Line, tokens: &[Token]) -> Result<u8, AsmError> {
+        Ok(self.value_in(line, tokens, -128, 255, "byte")? as u8)
+    }
+
+    fn nibble_operand(&self, line: &Line, tokens: &[Token]) -> Result<u8, AsmError> {
+        Ok(self.value_in(line, tokens, 0, 15, "value")? as u8)
+    }
+
+    fn emit(&mut self, line: &Line, column: usize, bytes: &[u8]) -> Result<(), AsmError> {
+        if self.address + bytes.len() > MEMORY_SIZE {
+            return Err(line.error(column, format!("past the end of memory at 0x{:X}", MEMORY_SIZE)));
+        }
+        if self.pass == 2 {
+            for (offset, &byte) in bytes.iter().enumerate() {
+                let address = self.address + offset;
+                if self.written[address] {
+                    return Err(line.error(column, format!("overwrites 0x{:03X}, already written", address)));
+                }
+                self.written[address] = true;
+                self.image[address] = byte;
+            }
+        }
+        self.address += bytes.len();
+        self.end = self.end.max(self.address);
+        Ok(())
+    }
+
+    fn line(&mut self, line: &Line) -> Result<(), AsmError> {
+        let mut tokens = line.tokens.as_slice();
+
+        // label:
+        if tokens.len() >= 2 && tokens[1].is(':') {
+            let name = match &tokens[0].tok {
+                Tok::Word(name) if !name.starts_with(|c: char| c.is_ascii_digit()) => name.clone(),
+                _ => return Err(line.error(tokens[0].column, "labels must be names".to_string())),
+            };
+            if self.pass == 1 && self.symbols.insert(name.clone(), self.address as i64).is_some() {
+                return Err(line.error(tokens[0].column, format!("'{}' is already defined", name)));
+            }
+            tokens = &tokens[2..];
+        }
+        let first = match tokens.first() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+
+        // name = value, or name equ value
+        if tokens.len() >= 2 && (tokens[1].is('=') || tokens[1].keyword().as_deref() == Some("equ")) {
+            let name = match &first.tok {
+                Tok::Word(name) => name.clone(),
+                _ => return Err(line.error(first.column, "constants must be names".to_string())),
+            };
+            if self.pass == 1 {
+                let value = self.eval(line, &tokens[2..], true)?;
+                if self.symbols.insert(name.clone(), value).is_some() {
+                    return Err(line.error(first.column, format!("'{}' is already defined", name)));
+                }
+            }
+            return Ok(());
+        }
+
+        let word = match &first.tok {
+            Tok::Word(word) => word.clone(),
+            _ => return Err(line.error(first.column, "expected an instruction or directive".to_string())),
+        };
+        let operands = split_operands(&tokens[1..]);
+        if let Some(empty) = operands.iter().position(|operand| operand.is_empty()) {
+            let column = if empty == 0 { first.column } else { operands[empty - 1].last().map_or(first.column, |token| token.column) };
+            return Err(line.error(column, "missing operand".to_string()));
+        }
+
+        match word.to_ascii_lowercase().as_str() {
+            "db" => {
+                for operand in operands {
+                    match operand {
+                        [Token { tok: Tok::Str(bytes), column }] => self.emit(line, *column, bytes)?,
+                        _ => {
+                            let byte = self.byte_operand(line, operand)?;
+                            self.emit(line, operand[0].column, &[byte])?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            "dw" => {
+                for operand in operands {
+                    let word = self.value_in(line, operand, -32768, 0xFFFF, "word")? as u16;
+                    self.emit(line, operand[0].column, &word.to_be_bytes())?;
+                }
+                Ok(())
+            }
+            "org" => {
+                let value = self.eval(line, &tokens[1..], true)?;
+                if value < PROGRAM_START as i64 || value >= MEMORY_SIZE as i64 {
+                    return Err(line.error(tokens[1].column, format!("org 0x{:X} is outside 0x{:X} to 0x{:X}", value, PROGRAM_START, MEMORY_SIZE - 1)));
+                }
+                self.address = value as usize;
+                Ok(())
+            }
+            "align" => {
+                let value = self.eval(line, &tokens[1..], true)?;
+                if value < 1 {
+                    return Err(line.error(tokens[1].column, "align needs a positive number".to_string()));
+                }
+                let value = value as usize;
+                let padding = (value - self.address % value) % value;
+                if self.address + padding > MEMORY_SIZE {
+                    return Err(line.error(tokens[1].column, format!("align {} pads past the end of memory at 0x{:X}", value, MEMORY_SIZE)));
+                }
+                self.emit(line, first.column, &vec![0; padding])
+            }
+            _ => match raw_opcode(&word) {
+                Some(opcode) if operands.is_empty() => self.emit(line, first.column, &opcode.to_be_bytes()),
+                _ => self.instruction(line, first, &operands),
+            },
+        }
+    }
+
+    fn instruction(&mut self, line: &Line, mnemonic: &Token, operands: &[&[Token]]) -> Result<(), AsmError> {
+        let name = mnemonic.keyword().unwrap_or_default();
+        let parsed: Vec<Operand> = operands.iter().map(|tokens| operand(tokens)).collect();
+        let mut long_address = None;
+
+        let instruction = match (name.as_str(), parsed.as_slice()) {
+            ("cls", []) => Instruction::Cls,
+            ("ret", []) => Instruction::Ret,
+            ("scr", []) => Instruction::ScrollRight,
+            ("scl", []) => Instruction::ScrollLeft,
+            ("exit", []) => Instruction::Exit,
+            ("low", []) => Instruction::Low,
+            ("high", []) => Instruction::High,
+            ("audio", []) => Instruction::Audio,
+            ("scd", [Operand::Expr(n)]) => Instruction::ScrollDown(self.nibble_operand(line, n)?),
+            ("scu", [Operand::Expr(n)]) => Instruction::ScrollUp(self.nibble_operand(line, n)?),
+            ("plane", [Operand::Expr(n)]) => Instruction::Plane(self.nibble_operand(line, n)?),
+            ("sys", [Operand::Expr(address)]) => Instruction::Sys(self.address_operand(line, address)?),
+            ("jp", [Operand::Expr(address)]) => Instruction::Jp(self.address_operand(line, address)?),
+            ("jp", [Operand::V(0), Operand::Expr(address)]) => Instruction::JpV0(self.address_operand(line, address)?),
+            ("call", [Operand::Expr(address)]) => Instruction::Call(self.address_operand(line, address)?),
+            ("se", [Operand::V(x), Operand::V(y)]) => Instruction::SeVxVy { x: *x, y: *y },
+            ("se", [Operand::V(x), Operand::Expr(byte)]) => Instruction::SeVxByte { x: *x, byte: self.byte_operand(line, byte)? },
+            ("sne", [Operand::V(x), Operand::V(y)]) => Instruction::SneVxVy { x: *x, y: *y },
+            ("sne", [Operand::V(x), Operand::Expr(byte)]) => Instruction::SneVxByte { x: *x, byte: self.byte_operand(line, byte)? },
+            ("save", [Operand::V(x), Operand::V(y)]) => Instruction::SaveRange { x: *x, y: *y },
+            ("load", [Operand::V(x), Operand::V(y)]) => Instruction::LoadRange { x: *x, y: *y },
+            ("ld", [Operand::V(x), Operand::V(y)]) => Instruction::LdVxVy { x: *x, y: *y },
+            ("ld", [Operand::V(x), Operand::Expr(byte)]) => Instruction::LdVxByte { x: *x, byte: self.byte_operand(line, byte)? },
+            ("ld", [Operand::I, Operand::Expr(address)]) => Instruction::LdI(self.address_operand(line, address)?),
+            ("ld", [Operand::I, Operand::Long(address)]) => {
+                long_address = Some(self.value_in(line, address, 0, 0xFFFF, "address")? as u16);
+                Instruction::LongI
+            }
+            ("ld", [Operand::V(x), Operand::Dt]) => Instruction::LdVxDt(*x),
+            ("ld", [Operand::V(x), Operand::K]) => Instruction::LdVxK(*x),
+            ("ld", [Operand::Dt, Operand::V(x)]) => Instruction::LdDtVx(*x),
+            ("ld", [Operand::St, Operand::V(x)]) => Instruction::LdStVx(*x),
+            ("ld", [Operand::F, Operand::V(x)]) => Instruction::LdFVx(*x),
+            ("ld", [Operand::Hf, Operand::V(x)]) => Instruction::LdHfVx(*x),
+            ("ld", [Operand::B, Operand::V(x)]) => Instruction::LdBVx(*x),
+            ("ld", [Operand::IndirectI, Operand::V(x)]) => Instruction::LdIVx(*x),
+            ("ld", [Operand::V(x), Operand::IndirectI]) => Instruction::LdVxI(*x),
+            ("ld", [Operand::R, Operand::V(x)]) => Instruction::LdRVx(*x),
+            ("ld", [Operand::V(x), Operand::R]) => Instruction::LdVxR(*x),
+            ("add", [Operand::V(x), Operand::V(y)]) => Instruction::AddVxVy { x: *x, y: *y },
+            ("add", [Operand::V(x), Operand::Expr(byte)]) => Instruction::AddVxByte { x: *x, byte: self.byte_operand(line, byte)? },
+            ("add", [Operand::I, Operand::V(x)]) => Instruction::AddIVx(*x),
+            ("or", [Operand::V(x), Operand::V(y)]) => Instruction::Or { x: *x, y: *y },
+            ("and", [Operand::V(x), Operand::V(y)]) => Instruction::And { x: *x, y: *y },
+            ("xor", [Operand::V(x), Operand::V(y)]) => Instruction::Xor { x: *x, y: *y },
+            ("sub", [Operand::V(x), Operand::V(y)]) => Instruction::Sub { x: *x, y: *y },
+            ("subn", [Operand::V(x), Operand::V(y)]) => Instruction::Subn { x: *x, y: *y },
+            ("shr", [Operand::V(x)]) => Instruction::Shr { x: *x, y: *x },
+            ("shr", [Operand::V(x), Operand::V(y)]) => Instruction::Shr { x: *x, y: *y },
+            ("shl", [Operand::V(x)]) => Instruction::Shl { x: *x, y: *x },
+            ("shl", [Operand::V(x), Operand::V(y)]) => Instruction::Shl { x: *x, y: *y },
+            ("rnd", [Operand::V(x), Operand::Expr(byte)]) => Instruction::Rnd { x: *x, byte: self.byte_operand(line, byte)? },
+            ("drw", [Operand::V(x), Operand::V(y), Operand::Expr(n)]) => Instruction::Drw { x: *x, y: *y, n: self.nibble_operand(line, n)? },
+            ("skp", [Operand::V(x)]) => Instruction::Skp(*x),
+            ("sknp", [Operand::V(x)]) => Instruction::Sknp(*x),
+            ("pitch", [Operand::V(x)]) => Instruction::Pitch(*x),
+            _ if KNOWN_MNEMONICS.contains(&name.as_str()) => {
+                return Err(line.error(mnemonic.column, format!("invalid operands for {}", name.to_uppercase())));
+            }
+            _ => return Err(line.error(mnemonic.column, format!("unknown instruction '{}'", name))),
+        };
+
+        self.emit(line, mnemonic.column, &encode(&instruction).to_be_bytes())?;
+        if let Some(address) = long_address {
+            self.emit(line, mnemonic.column, &address.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+const KNOWN_MNEMONICS: [&str; 30] = [
+    "cls", "ret", "scr", "scl", "exit", "low", "high", "audio", "scd", "scu", "plane", "sys", "jp", "call", "se", "sne", "save",
+    "load", "ld", "add", "or", "and", "xor", "sub", "subn", "shr", "shl", "rnd", "drw", "pitch",
+];
+
+fn assemble_lines(lines: &[Line]) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler {
+        symbols: HashMap::new(),
+        address: PROGRAM_START,
+        pass: 1,
+        image: vec![0; MEMORY_SIZE],
+        written: vec![false; MEMORY_SIZE],
+        end: PROGRAM_START,
+    };
+
+    for pass in 1..=2 {
+        assembler.pass = pass;
+        assembler.address = PROGRAM_START;
+        for line in lines {
+            assembler.line(line)?;
+        }
+    }
+
+    Ok(assembler.image[PROGRAM_START..assembler.end].to_vec())
+}
+
+/// Assemble source text into a ROM image to load at `PROGRAM_START`.
+///
+/// `name` is only used in error messages, includes are found relative to
+/// the current directory.
+pub fn assemble(source: &str, name: &str) -> Result<Vec<u8>, AsmError> {
+    let mut lines = Vec::new();
+    load(name, source, Path::new(""), 0, &mut lines)?;
+    assemble_lines(&lines)
+}
+
+/// Assemble a source file, finding includes relative to it.
+pub fn assemble_file(path: &str) -> Result<Vec<u8>, AsmError> {
+    let source = fs::read_to_string(path).map_err(|err| AsmError {
+        file: path.to_string(),
+        line: 0,
+        column: 0,
+        message: err.to_string(),
+    })?;
+    let directory = Path::new(path).parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let mut lines = Vec::new();
+    load(path, &source, &directory, 0, &mut lines)?;
+    assemble_lines(&lines)
+}
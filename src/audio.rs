@@ -0,0 +1,324 @@
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::str::FromStr;
+
+use crate::timers::TIMER_HZ;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+
+// XO-CHIP pattern playback rate at the default pitch of 64, in bits per second
+const PATTERN_BASE_RATE: f32 = 4000.0;
+pub const DEFAULT_PITCH: u8 = 64;
+const PATTERN_BITS: f32 = 128.0;
+
+/// What the beeper plays for one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoundFrame {
+    // The sound timer is nonzero
+    pub on: bool,
+    // XO-CHIP 1 bit pattern loaded by F002, None plays the configured tone
+    pub pattern: Option<[u8; 16]>,
+    // XO-CHIP FX3A pitch, only used with a pattern
+    pub pitch: u8,
+}
+
+impl SoundFrame {
+    pub fn silent() -> SoundFrame {
+        SoundFrame {
+            on: false,
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+        }
+    }
+
+    // Bits of the pattern played per second
+    pub fn pattern_rate(&self) -> f32 {
+        PATTERN_BASE_RATE * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" => Ok(Waveform::Sawtooth),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!("unknown waveform '{}'", s)),
+        }
+    }
+}
+
+/// How the beeper sounds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneSettings {
+    // Hz
+    pub frequency: f32,
+    // 0.0 is silent, 1.0 is full scale
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for ToneSettings {
+    fn default() -> Self {
+        ToneSettings {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+/// Generates the beeper tone one sample at a time.
+///
+/// Plays the configured waveform, or an XO-CHIP pattern when one is loaded.
+#[derive(Debug, Clone)]
+pub struct ToneGenerator {
+    settings: ToneSettings,
+    sample_rate: u32,
+    // Position within the current wave period, 0.0 to 1.0
+    phase: f32,
+    // Position within the XO-CHIP pattern, in bits
+    pattern_position: f32,
+}
+
+impl ToneGenerator {
+    pub fn new(settings: ToneSettings, sample_rate: u32) -> ToneGenerator {
+        ToneGenerator {
+            settings,
+            sample_rate,
+            phase: 0.0,
+            pattern_position: 0.0,
+        }
+    }
+
+    // Next sample between -volume and volume, silence while the tone is off
+    pub fn next_sample(&mut self, sound: &SoundFrame) -> f32 {
+        if !sound.on {
+            // Restart each beep at the start of a period so they all sound the same
+            self.phase = 0.0;
+            self.pattern_position = 0.0;
+            return 0.0;
+        }
+
+        if let Some(pattern) = &sound.pattern {
+            let bit = self.pattern_position as usize;
+            let value = if (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1 { 1.0 } else { -1.0 };
+            self.pattern_position = (self.pattern_position + sound.pattern_rate() / self.sample_rate as f32) % PATTERN_BITS;
+            return value * self.settings.volume;
+        }
+
+        let value = match self.settings.waveform {
+            Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Triangle => 4.0 * (self.phase - 0.5).abs() - 1.0,
+            Waveform::Sawtooth => 2.0 * self.phase - 1.0,
+            Waveform::Sine => (2.0 * PI * self.phase).sin(),
+        };
+        self.phase = (self.phase + self.settings.frequency / self.sample_rate as f32).fract();
+
+        value * self.settings.volume
+    }
+}
+
+/// Somewhere for the beeper to go.
+///
+/// `frame` is called from `Machine::tick_timers`, once per 60 Hz frame and
+/// just before the sound timer is decremented, with what to play for that
+/// frame.
+pub trait AudioBackend {
+    fn frame(&mut self, sound: &SoundFrame);
+}
+
+/// Discards all sound, the default for a new machine.
+pub struct NullAudio;
+
+impl AudioBackend for NullAudio {
+    fn frame(&mut self, _sound: &SoundFrame) {}
+}
+
+/// Headless backend writing the generated tone to a 16 bit mono WAV file.
+///
+/// Every frame adds exactly `SAMPLE_RATE / 60` samples, silent or not, so
+/// sample `n` belongs to frame `n / 735` and a test can see the frame the
+/// beep started and stopped on.
+pub struct WavAudio<W: Write + Seek> {
+    generator: ToneGenerator,
+    writer: Option<W>,
+    samples_written: u32,
+    // First write error, reported by finish
+    error: Option<io::Error>,
+}
+
+const WAV_HEADER_SIZE: u32 = 44;
+
+impl WavAudio<BufWriter<File>> {
+    pub fn create(path: &str, settings: ToneSettings) -> io::Result<WavAudio<BufWriter<File>>> {
+        WavAudio::new(BufWriter::new(File::create(path)?), settings)
+    }
+}
+
+impl<W: Write + Seek> WavAudio<W> {
+    pub fn new(mut writer: W, settings: ToneSettings) -> io::Result<WavAudio<W>> {
+        // Sizes are filled in by finish once the sample count is known
+        write_wav_header(&mut writer, 0)?;
+
+        Ok(WavAudio {
+            generator: ToneGenerator::new(settings, SAMPLE_RATE),
+            writer: Some(writer),
+            samples_written: 0,
+            error: None,
+        })
+    }
+
+    pub fn samples_written(&self) -> u32 {
+        self.samples_written
+    }
+
+    /// Fix up the header and hand back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finalize()?;
+        Ok(self.writer.take().expect("writer is only taken once"))
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if let Some(writer) = self.writer.as_mut() {
+            writer.seek(SeekFrom::Start(0))?;
+            write_wav_header(writer, self.samples_written * 2)?;
+            writer.seek(SeekFrom::End(0))?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> AudioBackend for WavAudio<W> {
+    fn frame(&mut self, sound: &SoundFrame) {
+        let writer = match (self.writer.as_mut(), &self.error) {
+            (Some(writer), None) => writer,
+            _ => return,
+        };
+
+        for _ in 0..SAMPLE_RATE / TIMER_HZ {
+            let sample = self.generator.next_sample(sound);
+            let value = (sample * i16::MAX as f32) as i16;
+            if let Err(err) = writer.write_all(&value.to_le_bytes()) {
+                self.error = Some(err);
+                return;
+            }
+            self.samples_written += 1;
+        }
+    }
+}
+
+impl<W: Write + Seek> Drop for WavAudio<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.finalize();
+        }
+    }
+}
+
+fn write_wav_header<W: Write>(writer: &mut W, data_size: u32) -> io::Result<()> {
+    let channels: u16 = 1;
+    let bits_per_sample: u16 = 16;
+    let block_align = channels * bits_per_sample / 8;
+    let byte_rate = SAMPLE_RATE * block_align as u32;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(WAV_HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    // PCM
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    writer.write_all(&byte_rate.to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&bits_per_sample.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    Ok(())
+}
+
+/// Real-time backend playing the tone on the default output device.
+#[cfg(feature = "audio")]
+pub struct CpalAudio {
+    sound: std::sync::Arc<std::sync::Mutex<SoundFrame>>,
+    // Playback stops when the stream is dropped
+    _stream: cpal::Stream,
+}
+
+#[cfg(feature = "audio")]
+impl CpalAudio {
+    pub fn new(settings: ToneSettings) -> Result<CpalAudio, Box<dyn std::error::Error>> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+        use std::sync::{Arc, Mutex};
+
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no audio output device")?;
+        let supported = device.default_output_config()?;
+        let sample_format = supported.sample_format();
+        let config: cpal::StreamConfig = supported.into();
+        let channels = config.channels as usize;
+
+        let sound = Arc::new(Mutex::new(SoundFrame::silent()));
+
+        fn build<T: cpal::Sample>(
+            device: &cpal::Device,
+            config: &cpal::StreamConfig,
+            channels: usize,
+            mut generator: ToneGenerator,
+            sound: Arc<Mutex<SoundFrame>>,
+        ) -> Result<cpal::Stream, cpal::BuildStreamError> {
+            device.build_output_stream(
+                config,
+                move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                    let current = *sound.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        let value = T::from(&generator.next_sample(&current));
+                        for sample in frame.iter_mut() {
+                            *sample = value;
+                        }
+                    }
+                },
+                |err| eprintln!("Audio stream error: {}", err),
+            )
+        }
+
+        let generator = ToneGenerator::new(settings, config.sample_rate.0);
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => build::<f32>(&device, &config, channels, generator, sound.clone())?,
+            cpal::SampleFormat::I16 => build::<i16>(&device, &config, channels, generator, sound.clone())?,
+            cpal::SampleFormat::U16 => build::<u16>(&device, &config, channels, generator, sound.clone())?,
+        };
+        stream.play()?;
+
+        Ok(CpalAudio {
+            sound,
+            _stream: stream,
+        })
+    }
+}
+
+#[cfg(feature = "audio")]
+impl AudioBackend for CpalAudio {
+    fn frame(&mut self, sound: &SoundFrame) {
+        *self.sound.lock().unwrap() = *sound;
+    }
+}
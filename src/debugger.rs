@@ -0,0 +1,470 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::fault::{MachineFault, StepOutcome};
+use crate::instruction::{decode, Instruction};
+use crate::machine::Machine;
+use crate::headless::screen_ascii;
+use crate::timers::FrameScheduler;
+use crate::MEMORY_SIZE;
+
+// continue gives up after this many instructions without a stop
+const RUN_LIMIT: u64 = 100_000_000;
+
+/// Where the debugger stops before executing an instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    // The instruction at this address
+    Address(u16),
+    // Any opcode whose bits under mask equal value, from a pattern like D01N
+    Opcode { value: u16, mask: u16 },
+    // Any instruction with this mnemonic, e.g. DRW or CALL
+    Class(String),
+}
+
+impl Breakpoint {
+    /// Parse a 4 character opcode pattern.
+    ///
+    /// Hex digits must match exactly, X, Y, N, K and ? match any digit, so
+    /// `DXYN` is every draw and `00E0` only clears the screen.
+    pub fn opcode_pattern(pattern: &str) -> Result<Breakpoint, String> {
+        if pattern.len() != 4 {
+            return Err(format!("opcode pattern '{}' should be 4 characters", pattern));
+        }
+        let mut value = 0;
+        let mut mask = 0;
+        for c in pattern.chars() {
+            value <<= 4;
+            mask <<= 4;
+            match c.to_ascii_uppercase() {
+                'X' | 'Y' | 'N' | 'K' | '?' => {}
+                c => {
+                    value |= c.to_digit(16).ok_or(format!("bad digit '{}' in opcode pattern", c))? as u16;
+                    mask |= 0xF;
+                }
+            }
+        }
+        Ok(Breakpoint::Opcode { value, mask })
+    }
+
+    fn hits(&self, address: u16, opcode: u16) -> bool {
+        match self {
+            Breakpoint::Address(breakpoint) => *breakpoint == address,
+            Breakpoint::Opcode { value, mask } => opcode & mask == *value,
+            Breakpoint::Class(mnemonic) => decode(opcode).is_ok_and(|instruction| instruction_class(&instruction) == *mnemonic),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Address(address) => write!(f, "address 0x{:03X}", address),
+            Breakpoint::Opcode { value, mask } => {
+                write!(f, "opcode ")?;
+                for shift in [12, 8, 4, 0] {
+                    if (mask >> shift) & 0xF == 0 {
+                        write!(f, "?")?;
+                    } else {
+                        write!(f, "{:X}", (value >> shift) & 0xF)?;
+                    }
+                }
+                Ok(())
+            }
+            Breakpoint::Class(mnemonic) => write!(f, "class {}", mnemonic),
+        }
+    }
+}
+
+// The mnemonic an instruction is listed under, e.g. LD or DRW
+pub fn instruction_class(instruction: &Instruction) -> String {
+    instruction.to_string().split_whitespace().next().unwrap_or_default().to_string()
+}
+
+/// Why execution stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    // A step, step-over or finish completed
+    Done,
+    // Breakpoint number n, counting from 0
+    Breakpoint(usize),
+    Fault(MachineFault),
+    // FX0A wants a key, press one and carry on
+    WaitingForKey,
+    Exited,
+    // The program jumped to itself, it will never get anywhere else
+    Looping,
+    // RUN_LIMIT instructions ran without stopping
+    Limit,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Done => Ok(()),
+            Stop::Breakpoint(number) => write!(f, "breakpoint {}", number),
+            Stop::Fault(fault) => write!(f, "fault: {}", fault),
+            Stop::WaitingForKey => write!(f, "waiting for a key, use press"),
+            Stop::Exited => write!(f, "program exited"),
+            Stop::Looping => write!(f, "program is jumping to itself"),
+            Stop::Limit => write!(f, "stopped after {} instructions", RUN_LIMIT),
+        }
+    }
+}
+
+/// Runs a machine one instruction at a time under user control.
+///
+/// The timers tick as they would in the window, once every frame's worth of
+/// instructions, so delay loops behave the same when stepped through.
+pub struct Debugger {
+    pub machine: Machine,
+    breakpoints: Vec<Option<Breakpoint>>,
+    scheduler: FrameScheduler,
+    // Instructions left before the timers next tick
+    frame_left: u32,
+}
+
+impl Debugger {
+    pub fn new(machine: Machine, instructions_per_second: u32) -> Debugger {
+        let mut scheduler = FrameScheduler::new(instructions_per_second);
+        let frame_left = scheduler.next_frame();
+        Debugger {
+            machine,
+            breakpoints: Vec::new(),
+            scheduler,
+            frame_left,
+        }
+    }
+
+    // Returns the breakpoint's number
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(Some(breakpoint));
+        self.breakpoints.len() - 1
+    }
+
+    // False if there was no such breakpoint. Numbers of the others don't change
+    pub fn remove_breakpoint(&mut self, number: usize) -> bool {
+        match self.breakpoints.get_mut(number) {
+            Some(breakpoint) => breakpoint.take().is_some(),
+            None => false,
+        }
+    }
+
+    // Breakpoints with their numbers
+    pub fn breakpoints(&self) -> impl Iterator<Item = (usize, &Breakpoint)> {
+        self.breakpoints.iter().enumerate().filter_map(|(number, breakpoint)| breakpoint.as_ref().map(|b| (number, b)))
+    }
+
+    // The opcode at PC, None past the end of memory
+    pub fn current_opcode(&self) -> Option<u16> {
+        let pc = self.machine.program_counter as usize;
+        if pc + 1 >= MEMORY_SIZE {
+            return None;
+        }
+        Some(u16::from_be_bytes([self.machine.memory[pc], self.machine.memory[pc + 1]]))
+    }
+
+    // Number of the first breakpoint on the instruction at PC
+    pub fn breakpoint_hit(&self) -> Option<usize> {
+        let opcode = self.current_opcode()?;
+        let pc = self.machine.program_counter;
+        self.breakpoints().find(|(_, breakpoint)| breakpoint.hits(pc, opcode)).map(|(number, _)| number)
+    }
+
+    /// Execute one instruction, ticking the timers at the end of each frame.
+    ///
+    /// Anything other than the instruction simply running is returned as
+    /// the reason to stop.
+    pub fn step(&mut self) -> Result<(), Stop> {
+        let pc = self.machine.program_counter;
+        let outcome = self.machine.execute_cycle();
+
+        let end_of_frame = match outcome {
+            Ok(StepOutcome::Executed(Instruction::Drw { .. })) => self.machine.quirks.wait_for_vblank,
+            _ => false,
+        };
+        self.frame_left = self.frame_left.saturating_sub(1);
+        if self.frame_left == 0 || end_of_frame {
+            self.machine.tick_timers();
+            self.frame_left = self.scheduler.next_frame();
+        }
+
+        match outcome {
+            Ok(StepOutcome::Executed(Instruction::Jp(address))) if address == pc => Err(Stop::Looping),
+            Ok(StepOutcome::Executed(_)) => Ok(()),
+            Ok(StepOutcome::WaitingForKey) => Err(Stop::WaitingForKey),
+            Ok(StepOutcome::Exited) => Err(Stop::Exited),
+            Err(fault) => Err(Stop::Fault(fault)),
+        }
+    }
+
+    // Run until `done` says so after an instruction, a breakpoint or some other stop
+    fn run_until<F: Fn(&Machine) -> bool>(&mut self, done: F) -> Stop {
+        for count in 0..RUN_LIMIT {
+            // Carrying on from a breakpoint shouldn't stop on it again straight away
+            if count > 0 {
+                if let Some(number) = self.breakpoint_hit() {
+                    return Stop::Breakpoint(number);
+                }
+            }
+            if let Err(stop) = self.step() {
+                return stop;
+            }
+            if done(&self.machine) {
+                return Stop::Done;
+            }
+        }
+        Stop::Limit
+    }
+
+    pub fn continue_running(&mut self) -> Stop {
+        self.run_until(|_| false)
+    }
+
+    // Like step, but runs a whole subroutine called by 2NNN
+    pub fn step_over(&mut self) -> Stop {
+        match self.current_opcode().and_then(|opcode| decode(opcode).ok()) {
+            Some(Instruction::Call(_)) => {
+                let depth = self.machine.stack_pointer;
+                let return_address = self.machine.program_counter.wrapping_add(2);
+                self.run_until(|machine| machine.stack_pointer == depth && machine.program_counter == return_address)
+            }
+            _ => self.step().err().unwrap_or(Stop::Done),
+        }
+    }
+
+    // Run until the current subroutine returns
+    pub fn finish(&mut self) -> Stop {
+        let depth = self.machine.stack_pointer;
+        if depth < 0 {
+            return Stop::Done;
+        }
+        self.run_until(|machine| machine.stack_pointer < depth)
+    }
+
+    // The instruction at PC, as shown after every stop
+    pub fn current_instruction(&self) -> String {
+        let pc = self.machine.program_counter;
+        match self.current_opcode() {
+            Some(opcode) => match decode(opcode) {
+                Ok(instruction) => format!("0x{:03X}: {:04X}  {}", pc, opcode, instruction),
+                Err(_) => format!("0x{:03X}: {:04X}  ???", pc, opcode),
+            },
+            None => format!("0x{:03X}: end of memory", pc),
+        }
+    }
+
+    pub fn registers(&self) -> String {
+        let machine = &self.machine;
+        let mut out = String::new();
+        for (number, value) in machine.general_registers.iter().enumerate() {
+            out += &format!("V{:X}=0x{:02X}{}", number, value, if number % 8 == 7 { "\n" } else { " " });
+        }
+        out += &format!(
+            "I=0x{:03X} PC=0x{:03X} SP={} DT={} ST={}\nstack:",
+            machine.memory_register, machine.program_counter, machine.stack_pointer, machine.delay_timer, machine.sound_timer
+        );
+        for address in machine.stack.iter().take((machine.stack_pointer + 1) as usize) {
+            out += &format!(" 0x{:03X}", address);
+        }
+        out
+    }
+
+    // 16 bytes per line with their ASCII alongside
+    pub fn hexdump(&self, start: usize, length: usize) -> String {
+        let end = (start + length).min(MEMORY_SIZE);
+        let mut out = String::new();
+        for line in (start..end).step_by(16) {
+            let bytes = &self.machine.memory[line..(line + 16).min(end)];
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let ascii: String = bytes.iter().map(|&byte| if byte.is_ascii_graphic() { byte as char } else { '.' }).collect();
+            out += &format!("0x{:04X}: {:<47}  {}\n", line, hex.join(" "), ascii);
+        }
+        out
+    }
+
+    // The display with # for lit pixels
+    pub fn screen(&self) -> String {
+        screen_ascii(&self.machine.display)
+    }
+
+    /// Read commands from `input` until it ends or `quit`, writing to `output`.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        writeln!(output, "{}", self.current_instruction())?;
+        write!(output, "(chip8) ")?;
+        output.flush()?;
+
+        for line in input.lines() {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if let Some(&"quit") | Some(&"q") = words.first() {
+                break;
+            }
+            match self.command(&words) {
+                Ok(text) => write!(output, "{}", text)?,
+                Err(err) => writeln!(output, "error: {}", err)?,
+            }
+            write!(output, "(chip8) ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    // Run one command, returning what to print
+    fn command(&mut self, words: &[&str]) -> Result<String, String> {
+        let stop = match words {
+            [] => return Ok(String::new()),
+            ["help" | "h"] => return Ok(HELP.to_string()),
+            ["break" | "b", "op", pattern] => return Ok(self.report_breakpoint(Breakpoint::opcode_pattern(pattern)?)),
+            ["break" | "b", "class", mnemonic] => return Ok(self.report_breakpoint(Breakpoint::Class(mnemonic.to_uppercase()))),
+            ["break" | "b", address] => {
+                let address = parse_number(address)?;
+                if address as usize >= self.machine.memory_size() {
+                    return Err(format!("0x{:X} is past the end of memory", address));
+                }
+                return Ok(self.report_breakpoint(Breakpoint::Address(address as u16)));
+            }
+            ["delete" | "d", number] => {
+                let number = parse_number(number)? as usize;
+                if !self.remove_breakpoint(number) {
+                    return Err(format!("no breakpoint {}", number));
+                }
+                return Ok(String::new());
+            }
+            ["breaks"] => {
+                let list: String = self.breakpoints().map(|(number, breakpoint)| format!("{}: {}\n", number, breakpoint)).collect();
+                return Ok(list);
+            }
+            ["regs" | "r"] => return Ok(format!("{}\n", self.registers())),
+            ["x", address] => return Ok(self.hexdump(parse_number(address)? as usize, 64)),
+            ["x", address, length] => return Ok(self.hexdump(parse_number(address)? as usize, parse_number(length)? as usize)),
+            ["screen"] => return Ok(self.screen()),
+            ["set", target, values @ ..] if !values.is_empty() => {
+                self.set(target, values)?;
+                return Ok(String::new());
+            }
+            ["press", key] => {
+                self.machine.press_key(parse_key(key)?);
+                return Ok(String::new());
+            }
+            ["release", key] => {
+                self.machine.release_key(parse_key(key)?);
+                return Ok(String::new());
+            }
+            ["step" | "s"] => self.step().err().unwrap_or(Stop::Done),
+            ["step" | "s", count] => {
+                let mut stop = Stop::Done;
+                for _ in 0..parse_number(count)? {
+                    if let Err(err) = self.step() {
+                        stop = err;
+                        break;
+                    }
+                }
+                stop
+            }
+            ["next" | "n"] => self.step_over(),
+            ["finish" | "f"] => self.finish(),
+            ["continue" | "c"] => self.continue_running(),
+            _ => return Err(format!("unknown command '{}', try help", words.join(" "))),
+        };
+
+        let mut out = String::new();
+        if stop != Stop::Done {
+            out += &format!("{}\n", stop);
+        }
+        out += &format!("{}\n", self.current_instruction());
+        Ok(out)
+    }
+
+    fn report_breakpoint(&mut self, breakpoint: Breakpoint) -> String {
+        let text = breakpoint.to_string();
+        format!("breakpoint {}: {}\n", self.add_breakpoint(breakpoint), text)
+    }
+
+    // set V0-VF, I, PC, SP, DT or ST to a value, or memory from an address onwards
+    fn set(&mut self, target: &str, values: &[&str]) -> Result<(), String> {
+        let machine = &mut self.machine;
+        let upper = target.to_uppercase();
+        let value = parse_number(values[0])?;
+        let too_big = |max: u32| if value > max { Err(format!("0x{:X} doesn't fit in {}", value, target)) } else { Ok(()) };
+
+        match upper.as_str() {
+            "I" => {
+                too_big(0xFFFF)?;
+                machine.memory_register = value as u16;
+            }
+            "PC" => {
+                too_big(0xFFFF)?;
+                machine.program_counter = value as u16;
+            }
+            "SP" => {
+                let value = values[0].parse::<i8>().map_err(|_| format!("invalid stack pointer '{}'", values[0]))?;
+                if !(-1..16).contains(&value) {
+                    return Err("the stack pointer goes from -1 to 15".to_string());
+                }
+                machine.stack_pointer = value;
+            }
+            "DT" => {
+                too_big(0xFF)?;
+                machine.delay_timer = value as u8;
+            }
+            "ST" => {
+                too_big(0xFF)?;
+                machine.sound_timer = value as u8;
+            }
+            _ if upper.len() == 2 && upper.starts_with('V') => {
+                let register = u8::from_str_radix(&upper[1..], 16).map_err(|_| format!("no register {}", target))?;
+                too_big(0xFF)?;
+                machine.general_registers[register as usize] = value as u8;
+            }
+            _ => {
+                // Anything else is an address to write bytes from, all checked before any are written
+                let address = parse_number(target)? as usize;
+                let mut bytes = Vec::with_capacity(values.len());
+                for (offset, value) in values.iter().enumerate() {
+                    let byte = parse_number(value)?;
+                    if byte > 0xFF || address + offset >= machine.memory_size() {
+                        return Err(format!("can't write {} to 0x{:X}", value, address + offset));
+                    }
+                    bytes.push(byte as u8);
+                }
+                machine.memory[address..address + bytes.len()].copy_from_slice(&bytes);
+            }
+        }
+        Ok(())
+    }
+}
+
+// 0x prefixed hex or decimal
+fn parse_number(text: &str) -> Result<u32, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number '{}'", text))
+}
+
+// A single hex digit naming a key
+fn parse_key(text: &str) -> Result<u8, String> {
+    let key = if text.len() == 1 { text.chars().next().and_then(|digit| digit.to_digit(16)) } else { None };
+    key.map(|key| key as u8).ok_or(format!("invalid key '{}'", text))
+}
+
+const HELP: &str = "Commands (numbers are decimal or 0x hex):
+  break ADDR | break op PATTERN | break class MNEMONIC
+                   stop at an address, an opcode like DXYN or 00E0, or every e.g. DRW
+  breaks           list breakpoints
+  delete N         remove breakpoint N
+  step [N]         run one or N instructions
+  next             step, running a 2NNN call to its return
+  finish           run until the current subroutine returns
+  continue         run until a breakpoint, fault or exit
+  regs             show V0-VF, I, PC, SP, DT, ST and the stack
+  x ADDR [LEN]     hexdump memory (default 64 bytes)
+  screen           show the display
+  set REG VALUE    set V0-VF, I, PC, SP, DT or ST
+  set ADDR BYTE..  write bytes to memory
+  press K | release K
+                   press or release hex key K
+  quit
+";
@@ -0,0 +1,156 @@
+use crate::instruction::{decode, Instruction};
+use crate::PROGRAM_START;
+
+/// Plain English for an instruction.
+///
+/// The wording is that of the debugging `println!`s in `execute_cycle`, so
+/// what this prints is what the interpreter used to print as it ran. The
+/// one change is 8XY0, which used to name the registers the wrong way round:
+/// it copies VY to VX, and now says so.
+pub fn describe(instruction: &Instruction) -> String {
+    match *instruction {
+        Instruction::Sys(address) => format!("Call machine code routine at {:X} (ignored by this interpreter)", address),
+        Instruction::ScrollDown(n) => format!("Scroll display down {} rows", n),
+        Instruction::ScrollUp(n) => format!("Scroll display up {} rows", n),
+        Instruction::Cls => "Clear display".to_string(),
+        Instruction::Ret => "Return from subroutine".to_string(),
+        Instruction::ScrollRight => "Scroll display right 4 pixels".to_string(),
+        Instruction::ScrollLeft => "Scroll display left 4 pixels".to_string(),
+        Instruction::Exit => "Exit the interpreter".to_string(),
+        Instruction::Low => "Switch to 64x32 lores mode".to_string(),
+        Instruction::High => "Switch to 128x64 hires mode".to_string(),
+        Instruction::Jp(address) => format!("JUMP TO {:X}", address),
+        Instruction::Call(address) => format!("CALLING SUBROUTING AT {:X}", address),
+        Instruction::SeVxByte { x, byte } => format!("SKIP IF Register {:X} == {:X}", x, byte),
+        Instruction::SneVxByte { x, byte } => format!("SKIP IF Register {:X} != {:X}", x, byte),
+        Instruction::SeVxVy { x, y } => format!("SKIP IF Register {:X} == Register {:X}", x, y),
+        Instruction::SaveRange { x, y } => format!("Store registers {:X} through {:X} in memory starting at location I", x, y),
+        Instruction::LoadRange { x, y } => format!("Load registers {:X} through {:X} from memory starting at location I", x, y),
+        Instruction::LdVxByte { x, byte } => format!("SET Register {:X} to {:X}", x, byte),
+        Instruction::AddVxByte { x, byte } => format!("SET Register {} to Register {} + {}", x, x, byte),
+        Instruction::LdVxVy { x, y } => format!("Copy value in Register {:X} to Register {:X}", y, x),
+        Instruction::Or { x, y } => format!("Bitwise OR on Registers {:X} and {:X} and store in {:X}", x, y, x),
+        Instruction::And { x, y } => format!("Bitwise AND on Registers {:X} and {:X} and store in {:X}", x, y, x),
+        Instruction::Xor { x, y } => format!("Bitwise XOR on Registers {:X} and {:X} and store in {:X}", x, y, x),
+        Instruction::AddVxVy { x, y } => format!("Add values of Registers {:X} and {:X} and store in {:X}", x, y, x),
+        Instruction::Sub { x, y } => format!("Subtract the value of Register {:X} from {:X} and store in {:X}", y, x, x),
+        Instruction::Shr { x, .. } => format!("Divide Register {:X} by 2", x),
+        Instruction::Subn { x, y } => format!("Subtract the value of Register {:X} from {:X} and store in {:X}", x, y, x),
+        Instruction::Shl { x, .. } => format!("Multiply register {:X} by 2", x),
+        Instruction::SneVxVy { x, y } => format!("Skip next instruction if Reg {:X} != Reg {:X}", x, y),
+        Instruction::LdI(address) => format!("Set Reg I to {:X}", address),
+        Instruction::JpV0(address) => format!("Jump to location {:X} + Reg 0", address),
+        Instruction::Rnd { x, byte } => format!("Set Reg {:X} to random byte AND {:b}", x, byte),
+        Instruction::Drw { x, y, n } => format!("Draw sprite of size {:X} stored in Reg I at coords Reg {:X}, Reg {:X}", n, x, y),
+        Instruction::Skp(x) => format!("Skip instruction if key pressed with value of register {:X}", x),
+        Instruction::Sknp(x) => format!("Skip instruction if key not pressed with value of register {:X}", x),
+        Instruction::LongI => "Set Reg I to the address in the next 2 bytes".to_string(),
+        Instruction::Plane(n) => format!("Select drawing planes {:b}", n),
+        Instruction::Audio => "Load 16 byte audio pattern from memory starting at location I".to_string(),
+        Instruction::LdVxDt(x) => format!("Copy value of Delay Timer to Reg {:X}", x),
+        Instruction::LdVxK(x) => format!("Wait for key press and store in Reg {:X}", x),
+        Instruction::LdDtVx(x) => format!("Set Delay timer to value of Reg {:X}", x),
+        Instruction::LdStVx(x) => format!("Set sound timer to value of Reg {:X}", x),
+        Instruction::AddIVx(x) => format!("Set I to I + Reg {:X}", x),
+        Instruction::LdFVx(x) => format!("Set I to location of Sprite for digit in Reg {:X}", x),
+        Instruction::LdHfVx(x) => format!("Set I to location of big Sprite for digit in Reg {:X}", x),
+        Instruction::LdBVx(x) => format!("Store BCD representation of Reg {:X} at I, I+1, I+2", x),
+        Instruction::Pitch(x) => format!("Set audio pitch to value of Reg {:X}", x),
+        Instruction::LdIVx(x) => format!("Store registers 0 through Reg {:X} in memory starting at location I.", x),
+        Instruction::LdVxI(x) => format!("Load registers 0 through Reg {:X} from memory starting at location I.", x),
+        Instruction::LdRVx(x) => format!("Store registers 0 through Reg {:X} in the RPL user flags", x),
+        Instruction::LdVxR(x) => format!("Load registers 0 through Reg {:X} from the RPL user flags", x),
+    }
+}
+
+// Description of an opcode, or why there isn't one
+fn describe_opcode(opcode: u16) -> String {
+    match decode(opcode) {
+        Ok(instruction) => describe(&instruction),
+        Err(err) => err.to_string(),
+    }
+}
+
+// The opcode at the start of a listing line, with or without 0x
+fn line_opcode(line: &str) -> Option<u16> {
+    let word = line.split_whitespace().next()?;
+    let word = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")).unwrap_or(word);
+    if word.len() != 4 {
+        return None;
+    }
+    u16::from_str_radix(word, 16).ok()
+}
+
+/// True if `text` looks like a hex listing rather than a ROM.
+///
+/// Every line that isn't blank or a comment has to start with an opcode.
+pub fn is_listing(bytes: &[u8]) -> bool {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return false,
+    };
+    let mut opcodes = 0;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with("//") || trimmed.starts_with(';') {
+            continue;
+        }
+        if line_opcode(trimmed).is_none() {
+            return false;
+        }
+        opcodes += 1;
+    }
+    opcodes > 0
+}
+
+/// Add a comment describing the opcode to the end of each line of a hex listing.
+///
+/// Lines without an opcode are left as they are. The word after `F000` is
+/// the address it loads, so it is described as that rather than decoded.
+pub fn describe_listing(text: &str) -> String {
+    let mut out = String::new();
+    let mut long_address_next = false;
+
+    for line in text.lines() {
+        match line_opcode(line.trim()) {
+            Some(opcode) if long_address_next => {
+                out += &format!("{}  // Address {:X} for the instruction above\n", line, opcode);
+                long_address_next = false;
+            }
+            Some(opcode) => {
+                out += &format!("{}  // {}\n", line, describe_opcode(opcode));
+                long_address_next = opcode == 0xF000;
+            }
+            None => {
+                out += line;
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// One line per opcode of a raw ROM, with its address and description.
+pub fn describe_rom(rom: &[u8]) -> String {
+    let mut out = String::new();
+    let mut long_address_next = false;
+
+    for (index, pair) in rom.chunks(2).enumerate() {
+        let address = PROGRAM_START + index * 2;
+        match *pair {
+            [high, low] => {
+                let opcode = u16::from_be_bytes([high, low]);
+                if long_address_next {
+                    out += &format!("0x{:03X}: {:04X}  // Address {:X} for the instruction above\n", address, opcode, opcode);
+                    long_address_next = false;
+                } else {
+                    out += &format!("0x{:03X}: {:04X}  // {}\n", address, opcode, describe_opcode(opcode));
+                    long_address_next = opcode == 0xF000;
+                }
+            }
+            [byte] => out += &format!("0x{:03X}: {:02X}    // Odd byte at the end of the ROM\n", address, byte),
+            _ => unreachable!("chunks of 2"),
+        }
+    }
+    out
+}
@@ -0,0 +1,255 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::instruction::{decode, Instruction};
+use crate::PROGRAM_START;
+
+// What the disassembler decided a ROM byte is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteKind {
+    // Not reached by any path through the program
+    Unknown,
+    // The first byte of an instruction, with its length in bytes
+    Code(usize),
+    // The rest of an instruction
+    Operand,
+    // Drawn by DXYN, with the sprite width in bytes
+    Sprite(usize),
+}
+
+/// The result of following every path through a ROM from its first instruction.
+///
+/// Addresses are machine addresses, so the first ROM byte is at `PROGRAM_START`.
+pub struct Analysis<'a> {
+    rom: &'a [u8],
+    kinds: Vec<ByteKind>,
+    // Label of every address that is jumped to, called or loaded into I
+    labels: BTreeMap<usize, String>,
+}
+
+impl<'a> Analysis<'a> {
+    pub fn new(rom: &'a [u8]) -> Analysis<'a> {
+        let mut analysis = Analysis {
+            rom,
+            kinds: vec![ByteKind::Unknown; rom.len()],
+            labels: BTreeMap::new(),
+        };
+        analysis.trace();
+        analysis
+    }
+
+    // Byte at an address, None outside of the ROM
+    fn byte(&self, address: usize) -> Option<u8> {
+        address.checked_sub(PROGRAM_START).and_then(|offset| self.rom.get(offset)).copied()
+    }
+
+    fn opcode(&self, address: usize) -> Option<u16> {
+        Some(u16::from_be_bytes([self.byte(address)?, self.byte(address + 1)?]))
+    }
+
+    fn kind(&self, address: usize) -> ByteKind {
+        self.kinds[address - PROGRAM_START]
+    }
+
+    fn set_kind(&mut self, address: usize, kind: ByteKind) {
+        if let Some(slot) = address.checked_sub(PROGRAM_START).and_then(|offset| self.kinds.get_mut(offset)) {
+            *slot = kind;
+        }
+    }
+
+    fn in_rom(&self, address: usize) -> bool {
+        self.byte(address).is_some()
+    }
+
+    fn add_label(&mut self, address: usize, prefix: &str) {
+        if self.in_rom(address) {
+            // The first name given sticks, so a subroutine that is also jumped to stays sub_
+            self.labels.entry(address).or_insert_with(|| format!("{}_{:03X}", prefix, address));
+        }
+    }
+
+    pub fn label(&self, address: usize) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    // Address as a label if it has one
+    fn operand(&self, address: usize) -> String {
+        match self.label(address) {
+            Some(label) => label.to_string(),
+            None => format!("0x{:03X}", address),
+        }
+    }
+
+    // Length of the instruction at an address, F000 NNNN is two words
+    fn instruction_length(&self, address: usize) -> usize {
+        if self.opcode(address) == Some(0xF000) { 4 } else { 2 }
+    }
+
+    // Follow every path from the start, marking code, data and labels
+    fn trace(&mut self) {
+        // Each path carries the value of I where it is known
+        let mut pending = vec![(PROGRAM_START, None)];
+        let mut visited = BTreeSet::new();
+
+        while let Some((address, mut index)) = pending.pop() {
+            let mut address = address;
+            loop {
+                if !visited.insert((address, index)) {
+                    break;
+                }
+                let instruction = match self.opcode(address).map(decode) {
+                    Some(Ok(instruction)) => instruction,
+                    _ => break,
+                };
+                if self.kind(address) == ByteKind::Operand {
+                    // Jumped into the middle of another instruction, leave the first reading alone
+                    break;
+                }
+
+                let length = self.instruction_length(address);
+                self.set_kind(address, ByteKind::Code(length));
+                for operand in address + 1..address + length {
+                    self.set_kind(operand, ByteKind::Operand);
+                }
+                let next = address + length;
+
+                match instruction {
+                    Instruction::Jp(target) => {
+                        self.add_label(target as usize, "label");
+                        address = target as usize;
+                        continue;
+                    }
+                    Instruction::Call(target) => {
+                        self.add_label(target as usize, "sub");
+                        pending.push((target as usize, index));
+                    }
+                    Instruction::JpV0(target) => {
+                        // Where it lands depends on V0, only the base is known
+                        self.add_label(target as usize, "label");
+                        break;
+                    }
+                    Instruction::Ret | Instruction::Exit => break,
+                    Instruction::SeVxByte { .. }
+                    | Instruction::SneVxByte { .. }
+                    | Instruction::SeVxVy { .. }
+                    | Instruction::SneVxVy { .. }
+                    | Instruction::Skp(_)
+                    | Instruction::Sknp(_) => {
+                        pending.push((next + self.instruction_length(next), index));
+                    }
+                    Instruction::LdI(target) => {
+                        index = Some(target as usize);
+                        self.add_label(target as usize, "data");
+                    }
+                    Instruction::LongI => {
+                        if let Some(target) = self.opcode(address + 2) {
+                            index = Some(target as usize);
+                            self.add_label(target as usize, "data");
+                        }
+                    }
+                    // I moves by an amount only known at run time
+                    Instruction::AddIVx(_) | Instruction::LdFVx(_) | Instruction::LdHfVx(_) => index = None,
+                    Instruction::Drw { n, .. } => {
+                        if let Some(start) = index {
+                            let (rows, width) = if n == 0 { (16, 2) } else { (n as usize, 1) };
+                            self.mark_sprite(start, rows * width, width);
+                        }
+                    }
+                    _ => {}
+                }
+                address = next;
+            }
+        }
+    }
+
+    // Sprite bytes that are also code stay code
+    fn mark_sprite(&mut self, start: usize, length: usize, width: usize) {
+        for address in start..start + length {
+            if self.in_rom(address) && self.kind(address) == ByteKind::Unknown {
+                self.set_kind(address, ByteKind::Sprite(width));
+            }
+        }
+    }
+
+    /// The annotated listing.
+    ///
+    /// Code lines show the address, the raw opcode and the mnemonic. Bytes
+    /// that are only ever read through I come out as `db` lines with a `#`/`.`
+    /// picture of the bits, one sprite row per line. Anything else that is
+    /// never executed is a plain `db`.
+    pub fn listing(&self) -> String {
+        let mut out = String::new();
+        let end = PROGRAM_START + self.rom.len();
+        // Data after a data label is shown as pictures up to the next code
+        let mut in_data = false;
+        let mut address = PROGRAM_START;
+
+        while address < end {
+            if let Some(label) = self.label(address) {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                let _ = writeln!(out, "{}:", label);
+                in_data = label.starts_with("data_");
+            }
+
+            match self.kind(address) {
+                ByteKind::Code(length) => {
+                    in_data = false;
+                    let opcode = self.opcode(address).expect("code is inside the ROM");
+                    let instruction = decode(opcode).expect("code decodes");
+                    let raw = (0..length).map(|offset| format!("{:02X}", self.byte(address + offset).unwrap_or(0))).collect::<String>();
+                    let _ = writeln!(out, "0x{:03X}: {:<8}  {}", address, raw, self.mnemonic(address, &instruction));
+                    address += length;
+                }
+                // Operands only get here when a jump lands mid instruction
+                ByteKind::Operand | ByteKind::Unknown => {
+                    let byte = self.byte(address).unwrap_or(0);
+                    if in_data {
+                        let _ = writeln!(out, "0x{:03X}: {:02X}        db 0x{:02X}  ; {}", address, byte, byte, picture(&[byte]));
+                    } else {
+                        let _ = writeln!(out, "0x{:03X}: {:02X}        db 0x{:02X}", address, byte, byte);
+                    }
+                    address += 1;
+                }
+                ByteKind::Sprite(width) => {
+                    // Keep 16 pixel rows together unless a label or the end splits them
+                    let row: Vec<u8> = (address..address + width)
+                        .take_while(|&a| a < end && (a == address || self.label(a).is_none()) && matches!(self.kind(a), ByteKind::Sprite(_)))
+                        .map(|a| self.byte(a).unwrap_or(0))
+                        .collect();
+                    let raw: String = row.iter().map(|byte| format!("{:02X}", byte)).collect();
+                    let values: Vec<String> = row.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+                    let _ = writeln!(out, "0x{:03X}: {:<8}  db {}  ; {}", address, raw, values.join(", "), picture(&row));
+                    address += row.len();
+                }
+            }
+        }
+        out
+    }
+
+    // Mnemonic with addresses replaced by labels
+    fn mnemonic(&self, address: usize, instruction: &Instruction) -> String {
+        match *instruction {
+            Instruction::Jp(target) => format!("JP {}", self.operand(target as usize)),
+            Instruction::Call(target) => format!("CALL {}", self.operand(target as usize)),
+            Instruction::JpV0(target) => format!("JP V0, {}", self.operand(target as usize)),
+            Instruction::LdI(target) => format!("LD I, {}", self.operand(target as usize)),
+            Instruction::LongI => match self.opcode(address + 2) {
+                Some(target) => format!("LD I, LONG {}", self.operand(target as usize)),
+                None => instruction.to_string(),
+            },
+            _ => instruction.to_string(),
+        }
+    }
+}
+
+// Bits as # for set and . for clear, most significant first
+fn picture(bytes: &[u8]) -> String {
+    bytes.iter().flat_map(|byte| (0..8).rev().map(move |bit| if byte >> bit & 1 == 1 { '#' } else { '.' })).collect()
+}
+
+// Annotated listing of a ROM, see Analysis::listing
+pub fn disassemble(rom: &[u8]) -> String {
+    Analysis::new(rom).listing()
+}
@@ -0,0 +1,194 @@
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+pub const PLANES: usize = 2;
+
+/// Framebuffer, 64x32 in lores mode and 128x64 in hires mode.
+///
+/// XO-CHIP gives it two bitplanes, so each pixel has one of four colours:
+/// bit 0 of the colour is plane 1 and bit 1 is plane 2. Drawing, clearing
+/// and scrolling only touch the planes picked with `select_planes`, which is
+/// just plane 1 unless a program asks otherwise.
+///
+/// Each row is a u128 with the leftmost pixel in the most significant bit.
+/// In lores mode only the top 32 rows and the top 64 bits of each row are used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Display {
+    pub(crate) planes: [[u128; HIRES_HEIGHT]; PLANES],
+    pub(crate) hires: bool,
+    // Bitmask of the planes FN01 selected
+    pub(crate) selected: u8,
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Bits covering the first `width` pixels of a row
+fn row_mask(width: usize) -> u128 {
+    if width >= 128 {
+        !0
+    } else {
+        !(!0u128 >> width)
+    }
+}
+
+impl Display {
+    pub fn new() -> Display {
+        Display {
+            planes: [[0; HIRES_HEIGHT]; PLANES],
+            hires: false,
+            selected: 0b01,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { LORES_WIDTH }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { LORES_HEIGHT }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // Switching resolution clears every plane
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.planes = [[0; HIRES_HEIGHT]; PLANES];
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.selected
+    }
+
+    // FN01, bit 0 of mask is plane 1 and bit 1 is plane 2
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected = mask & 0b11;
+    }
+
+    // Indexes of the selected planes, in drawing order
+    pub fn selected_plane_indexes(&self) -> impl Iterator<Item = usize> {
+        let selected = self.selected;
+        (0..PLANES).filter(move |plane| selected & (1 << plane) != 0)
+    }
+
+    // Clears the selected planes
+    pub fn clear(&mut self) {
+        for plane in self.selected_plane_indexes() {
+            self.planes[plane] = [0; HIRES_HEIGHT];
+        }
+    }
+
+    // True if the pixel is lit on any plane
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.colour(x, y) != 0
+    }
+
+    // Colour 0 to 3 of a pixel, one bit per plane
+    pub fn colour(&self, x: usize, y: usize) -> u8 {
+        if x >= self.width() || y >= self.height() {
+            return 0;
+        }
+        (0..PLANES).map(|plane| (((self.planes[plane][y] >> (127 - x)) & 1) as u8) << plane).sum()
+    }
+
+    // Row y of a plane, leftmost pixel in the most significant bit
+    pub fn row(&self, plane: usize, y: usize) -> u128 {
+        self.planes[plane][y]
+    }
+
+    /// XOR one row of a sprite onto the screen at column `x` of row `y`.
+    ///
+    /// `bits` holds `sprite_width` pixels (8 or 16), leftmost in the highest
+    /// used bit. `x` wraps round the screen. Pixels past the right edge are
+    /// dropped when `clip` is set, otherwise they wrap round to the left edge.
+    /// Returns true if any lit pixel was turned off.
+    pub fn xor_sprite_row(&mut self, plane: usize, x: usize, y: usize, bits: u16, sprite_width: usize, clip: bool) -> bool {
+        let width = self.width();
+        let x = x % width;
+        let sprite = (bits as u128) << (128 - sprite_width);
+
+        let mut positioned = (sprite >> x) & row_mask(width);
+        if !clip && x + sprite_width > width {
+            positioned |= (sprite << (width - x)) & row_mask(width);
+        }
+
+        let row = &mut self.planes[plane][y];
+        let collision = *row & positioned != 0;
+        *row ^= positioned;
+        collision
+    }
+
+    /// XOR a sprite onto one plane with its top left corner at (`x`, `y`).
+    ///
+    /// The start coordinates wrap round the screen. `rows` holds one entry per
+    /// sprite row, laid out as for `xor_sprite_row`. Rows and pixels past the
+    /// bottom and right edges are dropped when `clip` is set and wrap round to
+    /// the top and left otherwise. Returns true if any lit pixel in any row
+    /// was turned off.
+    pub fn draw_sprite(&mut self, plane: usize, x: usize, y: usize, rows: &[u16], sprite_width: usize, clip: bool) -> bool {
+        let (width, height) = (self.width(), self.height());
+        let (x, y) = (x % width, y % height);
+
+        let mut collision = false;
+        for (i, &bits) in rows.iter().enumerate() {
+            let mut row = y + i;
+            if row >= height {
+                if clip {
+                    break;
+                }
+                row %= height;
+            }
+            collision |= self.xor_sprite_row(plane, x, row, bits, sprite_width, clip);
+        }
+        collision
+    }
+
+    // 00CN, move the selected planes down n rows
+    pub fn scroll_down(&mut self, n: usize) {
+        let height = self.height();
+        let n = n.min(height);
+        for plane in self.selected_plane_indexes() {
+            let rows = &mut self.planes[plane];
+            rows.copy_within(0..height - n, n);
+            rows[..n].fill(0);
+        }
+    }
+
+    // 00DN, move the selected planes up n rows
+    pub fn scroll_up(&mut self, n: usize) {
+        let height = self.height();
+        let n = n.min(height);
+        for plane in self.selected_plane_indexes() {
+            let rows = &mut self.planes[plane];
+            rows.copy_within(n..height, 0);
+            rows[height - n..height].fill(0);
+        }
+    }
+
+    // 00FB, move the selected planes right n pixels
+    pub fn scroll_right(&mut self, n: usize) {
+        let mask = row_mask(self.width());
+        for plane in self.selected_plane_indexes() {
+            for row in self.planes[plane].iter_mut() {
+                *row = (*row >> n) & mask;
+            }
+        }
+    }
+
+    // 00FC, move the selected planes left n pixels
+    pub fn scroll_left(&mut self, n: usize) {
+        for plane in self.selected_plane_indexes() {
+            for row in self.planes[plane].iter_mut() {
+                *row <<= n;
+            }
+        }
+    }
+}
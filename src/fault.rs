@@ -0,0 +1,180 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::instruction::Instruction;
+
+/// What happened during a successful `execute_cycle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    // The instruction ran to completion
+    Executed(Instruction),
+    // FX0A found no key pressed, the same instruction runs again next cycle
+    WaitingForKey,
+    // 00FD stopped the program, nothing more will run
+    Exited,
+}
+
+/// A fault raised by a program the machine cannot run.
+///
+/// `pc` is always the address of the instruction that faulted. The program
+/// counter itself has already moved past it, so a frontend that chooses to
+/// carry on simply calls `execute_cycle` again. The exception is a program
+/// counter that would run off the end of memory, which stays where it was
+/// rather than wrapping round to 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineFault {
+    // 2NNN with all 16 stack slots in use
+    StackOverflow { pc: u16 },
+    // 00EE with nothing on the stack
+    StackUnderflow { pc: u16 },
+    InvalidOpcode { pc: u16, opcode: u16 },
+    // An instruction read or wrote past the end of memory
+    MemoryOutOfBounds { pc: u16, address: usize },
+    // The ROM does not fit between PROGRAM_START and the end of memory
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl MachineFault {
+    pub fn kind(&self) -> FaultKind {
+        match self {
+            MachineFault::StackOverflow { .. } => FaultKind::StackOverflow,
+            MachineFault::StackUnderflow { .. } => FaultKind::StackUnderflow,
+            MachineFault::InvalidOpcode { .. } => FaultKind::InvalidOpcode,
+            MachineFault::MemoryOutOfBounds { .. } => FaultKind::MemoryOutOfBounds,
+            MachineFault::RomTooLarge { .. } => FaultKind::RomTooLarge,
+        }
+    }
+}
+
+impl fmt::Display for MachineFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MachineFault::StackOverflow { pc } => write!(f, "stack overflow at {:03X}", pc),
+            MachineFault::StackUnderflow { pc } => write!(f, "stack underflow at {:03X}", pc),
+            MachineFault::InvalidOpcode { pc, opcode } => write!(f, "invalid opcode {:04X} at {:03X}", opcode, pc),
+            MachineFault::MemoryOutOfBounds { pc, address } => write!(f, "memory access at {:X} out of bounds at {:03X}", address, pc),
+            MachineFault::RomTooLarge { size, max } => write!(f, "ROM is {} bytes but only {} fit in memory", size, max),
+        }
+    }
+}
+
+impl Error for MachineFault {}
+
+/// The kinds of `MachineFault`, without their details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FaultKind {
+    StackOverflow,
+    StackUnderflow,
+    InvalidOpcode,
+    MemoryOutOfBounds,
+    RomTooLarge,
+}
+
+impl FromStr for FaultKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stack-overflow" => Ok(FaultKind::StackOverflow),
+            "stack-underflow" => Ok(FaultKind::StackUnderflow),
+            "invalid-opcode" => Ok(FaultKind::InvalidOpcode),
+            "memory" => Ok(FaultKind::MemoryOutOfBounds),
+            "rom-too-large" => Ok(FaultKind::RomTooLarge),
+            _ => Err(format!("unknown fault '{}'", s)),
+        }
+    }
+}
+
+/// What a frontend should do when a fault is raised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultAction {
+    // Stop executing instructions
+    Halt,
+    // Print the fault and carry on with the next instruction
+    Log,
+    // Silently carry on with the next instruction
+    Ignore,
+}
+
+impl FromStr for FaultAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "halt" => Ok(FaultAction::Halt),
+            "log" => Ok(FaultAction::Log),
+            "ignore" => Ok(FaultAction::Ignore),
+            _ => Err(format!("unknown fault action '{}'", s)),
+        }
+    }
+}
+
+/// Chooses a `FaultAction` for each kind of fault.
+///
+/// By default invalid opcodes are logged, as the interpreter always did, and
+/// everything else halts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultPolicy {
+    pub stack_overflow: FaultAction,
+    pub stack_underflow: FaultAction,
+    pub invalid_opcode: FaultAction,
+    pub memory_out_of_bounds: FaultAction,
+}
+
+impl Default for FaultPolicy {
+    fn default() -> Self {
+        FaultPolicy {
+            stack_overflow: FaultAction::Halt,
+            stack_underflow: FaultAction::Halt,
+            invalid_opcode: FaultAction::Log,
+            memory_out_of_bounds: FaultAction::Halt,
+        }
+    }
+}
+
+impl FaultPolicy {
+    // Use the same action for every kind of fault
+    pub fn all(action: FaultAction) -> FaultPolicy {
+        FaultPolicy {
+            stack_overflow: action,
+            stack_underflow: action,
+            invalid_opcode: action,
+            memory_out_of_bounds: action,
+        }
+    }
+
+    pub fn action_for(&self, fault: &MachineFault) -> FaultAction {
+        match fault.kind() {
+            FaultKind::StackOverflow => self.stack_overflow,
+            FaultKind::StackUnderflow => self.stack_underflow,
+            FaultKind::InvalidOpcode => self.invalid_opcode,
+            FaultKind::MemoryOutOfBounds => self.memory_out_of_bounds,
+            // Can only happen while loading, there is nothing to carry on with
+            FaultKind::RomTooLarge => FaultAction::Halt,
+        }
+    }
+
+    pub fn set(&mut self, kind: FaultKind, action: FaultAction) {
+        match kind {
+            FaultKind::StackOverflow => self.stack_overflow = action,
+            FaultKind::StackUnderflow => self.stack_underflow = action,
+            FaultKind::InvalidOpcode => self.invalid_opcode = action,
+            FaultKind::MemoryOutOfBounds => self.memory_out_of_bounds = action,
+            FaultKind::RomTooLarge => {}
+        }
+    }
+
+    /// Apply a `kind=action` or bare `action` setting, as given on the command line.
+    pub fn apply(&mut self, setting: &str) -> Result<(), String> {
+        match setting.split_once('=') {
+            Some((kind, action)) => {
+                self.set(kind.parse()?, action.parse()?);
+            }
+            None => {
+                *self = FaultPolicy::all(setting.parse()?);
+            }
+        }
+        Ok(())
+    }
+}
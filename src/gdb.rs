@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::debugger::{Breakpoint, Debugger, Stop};
+use crate::fault::MachineFault;
+use crate::MEMORY_SIZE;
+
+// Instructions run between checks for a Ctrl-C from gdb while continuing
+const INTERRUPT_CHECK_INTERVAL: u32 = 10_000;
+
+/// The registers gdb sees, in `g` packet order.
+///
+/// V0 to VF are 8 bits, I and PC 16 bits little endian, and SP is the signed
+/// 8 bit stack pointer, -1 with nothing on the stack.
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0" group="general"/>
+    <reg name="v1" bitsize="8" type="uint8" group="general"/>
+    <reg name="v2" bitsize="8" type="uint8" group="general"/>
+    <reg name="v3" bitsize="8" type="uint8" group="general"/>
+    <reg name="v4" bitsize="8" type="uint8" group="general"/>
+    <reg name="v5" bitsize="8" type="uint8" group="general"/>
+    <reg name="v6" bitsize="8" type="uint8" group="general"/>
+    <reg name="v7" bitsize="8" type="uint8" group="general"/>
+    <reg name="v8" bitsize="8" type="uint8" group="general"/>
+    <reg name="v9" bitsize="8" type="uint8" group="general"/>
+    <reg name="va" bitsize="8" type="uint8" group="general"/>
+    <reg name="vb" bitsize="8" type="uint8" group="general"/>
+    <reg name="vc" bitsize="8" type="uint8" group="general"/>
+    <reg name="vd" bitsize="8" type="uint8" group="general"/>
+    <reg name="ve" bitsize="8" type="uint8" group="general"/>
+    <reg name="vf" bitsize="8" type="uint8" group="general"/>
+    <reg name="i" bitsize="16" type="data_ptr" group="general"/>
+    <reg name="pc" bitsize="16" type="code_ptr" group="general"/>
+    <reg name="sp" bitsize="8" type="int8" group="general"/>
+  </feature>
+</target>
+"#;
+
+// Register numbers after V0 to VF
+const REGISTER_I: usize = 16;
+const REGISTER_PC: usize = 17;
+const REGISTER_SP: usize = 18;
+
+/// Serves one gdb connection at a time over the GDB remote serial protocol.
+///
+/// Supports reading and writing registers and memory, software breakpoints
+/// (`Z0`/`z0`), single step, continue and Ctrl-C. `monitor press K` and
+/// `monitor release K` work the keypad, so FX0A can be answered from gdb.
+pub struct GdbStub {
+    pub debugger: Debugger,
+    // Debugger breakpoint numbers of the Z0 breakpoints, by address
+    breakpoints: HashMap<u16, usize>,
+}
+
+// What came in from gdb
+enum Incoming {
+    Packet(String),
+    // A packet whose checksum didn't match, for gdb to send again
+    Corrupt,
+    // A Ctrl-C outside of a packet
+    Interrupt,
+}
+
+impl GdbStub {
+    pub fn new(debugger: Debugger) -> GdbStub {
+        GdbStub {
+            debugger,
+            breakpoints: HashMap::new(),
+        }
+    }
+
+    // Wait for gdb on the port and serve it until it detaches or kills the program
+    pub fn listen(&mut self, port: u16) -> io::Result<()> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("Waiting for gdb on 127.0.0.1:{}, use target remote :{}", port, port);
+        let (stream, address) = listener.accept()?;
+        println!("gdb connected from {}", address);
+        self.serve(stream)
+    }
+
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let writer = stream.try_clone()?;
+        self.serve_connection(BufReader::new(stream), writer)
+    }
+
+    /// Serve packets read from `reader` until gdb detaches, kills the program
+    /// or the input ends, writing acks and replies to `writer`.
+    pub fn serve_connection<R: GdbInput, W: Write>(&mut self, mut reader: R, mut writer: W) -> io::Result<()> {
+        while let Some(incoming) = read_packet(&mut reader)? {
+            let packet = match incoming {
+                Incoming::Packet(packet) => {
+                    writer.write_all(b"+")?;
+                    packet
+                }
+                Incoming::Corrupt => {
+                    writer.write_all(b"-")?;
+                    writer.flush()?;
+                    continue;
+                }
+                // Nothing is running, report where it is stopped. Ctrl-C isn't a packet so isn't acked
+                Incoming::Interrupt => "?".to_string(),
+            };
+
+            let reply = match packet.as_bytes().first() {
+                Some(b'c') => self.continue_running(&mut reader)?,
+                Some(b'D') => {
+                    write_packet(&mut writer, "OK")?;
+                    return Ok(());
+                }
+                Some(b'k') => return Ok(()),
+                _ => self.handle(&packet),
+            };
+            write_packet(&mut writer, &reply)?;
+        }
+        Ok(())
+    }
+
+    // The reply to every packet but continue, detach and kill
+    fn handle(&mut self, packet: &str) -> String {
+        // Query names run up to the first separator, other commands are one letter
+        let split = match packet.as_bytes().first() {
+            Some(b'q') | Some(b'Q') | Some(b'v') => packet.find([':', ',']).unwrap_or(packet.len()),
+            _ => packet.len().min(1),
+        };
+        let (command, arguments) = packet.split_at(split);
+
+        match command {
+            "?" => "S05".to_string(),
+            "g" => (0..=REGISTER_SP).map(|register| self.read_register(register)).collect(),
+            "G" => {
+                let bytes = match from_hex(arguments) {
+                    Some(bytes) if bytes.len() == 21 && valid_stack_pointer(bytes[20]) => bytes,
+                    _ => return "E01".to_string(),
+                };
+                let machine = &mut self.debugger.machine;
+                machine.general_registers.copy_from_slice(&bytes[..16]);
+                machine.memory_register = u16::from_le_bytes([bytes[16], bytes[17]]);
+                machine.program_counter = u16::from_le_bytes([bytes[18], bytes[19]]);
+                machine.stack_pointer = bytes[20] as i8;
+                "OK".to_string()
+            }
+            "p" => match usize::from_str_radix(arguments, 16) {
+                Ok(register) if register <= REGISTER_SP => self.read_register(register),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let written = arguments.split_once('=').and_then(|(register, value)| {
+                    self.write_register(usize::from_str_radix(register, 16).ok()?, &from_hex(value)?)
+                });
+                if written.is_some() { "OK".to_string() } else { "E01".to_string() }
+            }
+            "m" => {
+                let (address, length) = match parse_range(arguments) {
+                    Some(range) => range,
+                    None => return "E01".to_string(),
+                };
+                hex::encode(&self.debugger.machine.memory[address..address + length])
+            }
+            "M" => {
+                let written = arguments.split_once(':').and_then(|(range, data)| {
+                    let (address, length) = parse_range(range)?;
+                    let bytes = from_hex(data).filter(|bytes| bytes.len() == length)?;
+                    self.debugger.machine.memory[address..address + length].copy_from_slice(&bytes);
+                    Some(())
+                });
+                if written.is_some() { "OK".to_string() } else { "E01".to_string() }
+            }
+            "s" => stop_reply(self.debugger.step().err().unwrap_or(Stop::Done)),
+            "Z" | "z" => self.breakpoint(command == "Z", arguments),
+            "H" => "OK".to_string(),
+            "qSupported" => "PacketSize=4000;qXfer:features:read+".to_string(),
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "qXfer" => self.target_description(arguments),
+            "qRcmd" => self.monitor(arguments),
+            // An empty reply tells gdb the packet isn't supported
+            _ => String::new(),
+        }
+    }
+
+    // Register as little endian hex
+    fn read_register(&self, register: usize) -> String {
+        let machine = &self.debugger.machine;
+        match register {
+            0..=15 => format!("{:02x}", machine.general_registers[register]),
+            REGISTER_I => hex::encode(machine.memory_register.to_le_bytes()),
+            REGISTER_PC => hex::encode(machine.program_counter.to_le_bytes()),
+            _ => format!("{:02x}", machine.stack_pointer as u8),
+        }
+    }
+
+    fn write_register(&mut self, register: usize, bytes: &[u8]) -> Option<()> {
+        let machine = &mut self.debugger.machine;
+        match (register, bytes) {
+            (0..=15, [value]) => machine.general_registers[register] = *value,
+            (REGISTER_I, [low, high]) => machine.memory_register = u16::from_le_bytes([*low, *high]),
+            (REGISTER_PC, [low, high]) => machine.program_counter = u16::from_le_bytes([*low, *high]),
+            (REGISTER_SP, [value]) if valid_stack_pointer(*value) => machine.stack_pointer = *value as i8,
+            _ => return None,
+        }
+        Some(())
+    }
+
+    // Z0,addr,kind and z0,addr,kind, only software breakpoints are supported
+    fn breakpoint(&mut self, insert: bool, arguments: &str) -> String {
+        let mut fields = arguments.split(',');
+        if fields.next() != Some("0") {
+            return String::new();
+        }
+        let address = match fields.next().and_then(|address| u16::from_str_radix(address, 16).ok()) {
+            Some(address) => address,
+            None => return "E01".to_string(),
+        };
+
+        if insert {
+            if !self.breakpoints.contains_key(&address) {
+                let number = self.debugger.add_breakpoint(Breakpoint::Address(address));
+                self.breakpoints.insert(address, number);
+            }
+        } else if let Some(number) = self.breakpoints.remove(&address) {
+            self.debugger.remove_breakpoint(number);
+        }
+        "OK".to_string()
+    }
+
+    // qXfer:features:read:target.xml:offset,length
+    fn target_description(&self, arguments: &str) -> String {
+        let range = match arguments.strip_prefix(":features:read:target.xml:") {
+            Some(range) => range,
+            None => return String::new(),
+        };
+        let (offset, length) = match range.split_once(',') {
+            Some((offset, length)) => match (usize::from_str_radix(offset, 16), usize::from_str_radix(length, 16)) {
+                (Ok(offset), Ok(length)) => (offset, length),
+                _ => return "E01".to_string(),
+            },
+            None => return "E01".to_string(),
+        };
+
+        let start = offset.min(TARGET_XML.len());
+        let end = (start + length).min(TARGET_XML.len());
+        // m means there is more to come, l that this is the last of it
+        let marker = if end < TARGET_XML.len() { 'm' } else { 'l' };
+        format!("{}{}", marker, &TARGET_XML[start..end])
+    }
+
+    // monitor commands arrive hex encoded
+    fn monitor(&mut self, arguments: &str) -> String {
+        let command = match arguments.strip_prefix(',').and_then(from_hex).and_then(|bytes| String::from_utf8(bytes).ok()) {
+            Some(command) => command,
+            None => return "E01".to_string(),
+        };
+        let words: Vec<&str> = command.split_whitespace().collect();
+        // A single hex digit
+        let key = words.get(1).filter(|text| text.len() == 1).and_then(|text| u8::from_str_radix(text, 16).ok());
+
+        let output = match (words.as_slice(), key) {
+            (["press", _], Some(key)) => {
+                self.debugger.machine.press_key(key);
+                String::new()
+            }
+            (["release", _], Some(key)) => {
+                self.debugger.machine.release_key(key);
+                String::new()
+            }
+            (["regs"], _) => format!("{}\n", self.debugger.registers()),
+            (["screen"], _) => self.debugger.screen(),
+            _ => "monitor commands: press K, release K (K is a hex key), regs, screen\n".to_string(),
+        };
+        if output.is_empty() { "OK".to_string() } else { hex::encode(output.as_bytes()) }
+    }
+
+    // Run until a breakpoint, a fault, the program exiting or Ctrl-C from gdb
+    fn continue_running<R: GdbInput>(&mut self, reader: &mut R) -> io::Result<String> {
+        let mut first = true;
+        loop {
+            for _ in 0..INTERRUPT_CHECK_INTERVAL {
+                // Carrying on from a breakpoint shouldn't stop on it again straight away
+                if !first && self.debugger.breakpoint_hit().is_some() {
+                    return Ok("S05".to_string());
+                }
+                first = false;
+                match self.debugger.step() {
+                    // gdb decides how long to wait, keep going until it interrupts
+                    Ok(()) | Err(Stop::Looping) | Err(Stop::WaitingForKey) => {}
+                    Err(stop) => return Ok(stop_reply(stop)),
+                }
+            }
+
+            if reader.interrupted()? {
+                return Ok("S02".to_string());
+            }
+        }
+    }
+}
+
+// Stop reply packet for why the machine stopped
+fn stop_reply(stop: Stop) -> String {
+    match stop {
+        Stop::Exited => "W00".to_string(),
+        // SIGILL
+        Stop::Fault(MachineFault::InvalidOpcode { .. }) => "S04".to_string(),
+        // SIGSEGV
+        Stop::Fault(_) => "S0b".to_string(),
+        // SIGTRAP
+        _ => "S05".to_string(),
+    }
+}
+
+/// Where a stub reads what gdb sends.
+pub trait GdbInput: BufRead {
+    // Whether gdb has sent a Ctrl-C, without waiting for it to send anything
+    fn interrupted(&mut self) -> io::Result<bool>;
+}
+
+impl GdbInput for BufReader<TcpStream> {
+    fn interrupted(&mut self) -> io::Result<bool> {
+        if !self.buffer().is_empty() {
+            let interrupt = self.buffer()[0] == 0x03;
+            self.consume(1);
+            return Ok(interrupt);
+        }
+
+        self.get_ref().set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = self.get_mut().read(&mut byte);
+        self.get_ref().set_nonblocking(false)?;
+        match result {
+            Ok(1) => Ok(byte[0] == 0x03),
+            Ok(_) => Err(io::Error::new(ErrorKind::UnexpectedEof, "gdb disconnected")),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+// Input that is all there already, a recorded session
+impl GdbInput for &[u8] {
+    fn interrupted(&mut self) -> io::Result<bool> {
+        if self.first() == Some(&0x03) {
+            *self = &self[1..];
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+// SP is -1 with nothing on the stack and at most 15 with it full
+fn valid_stack_pointer(value: u8) -> bool {
+    (-1..16).contains(&(value as i8))
+}
+
+// Next $packet#checksum, None when gdb disconnects
+fn read_packet<R: BufRead>(reader: &mut R) -> io::Result<Option<Incoming>> {
+    let mut byte = [0];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        match byte[0] {
+            b'$' => break,
+            0x03 => return Ok(Some(Incoming::Interrupt)),
+            // Acks and anything else between packets
+            _ => {}
+        }
+    }
+
+    let mut data = Vec::new();
+    reader.read_until(b'#', &mut data)?;
+    data.pop();
+    let mut checksum = [0; 2];
+    reader.read_exact(&mut checksum)?;
+    let expected = std::str::from_utf8(&checksum).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    if expected != Some(data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))) {
+        return Ok(Some(Incoming::Corrupt));
+    }
+
+    // gdb doesn't escape anything it sends us apart from binary writes, which aren't supported
+    Ok(Some(Incoming::Packet(String::from_utf8_lossy(&data).into_owned())))
+}
+
+fn write_packet<W: Write>(writer: &mut W, data: &str) -> io::Result<()> {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    write!(writer, "${}#{:02x}", data, checksum)?;
+    writer.flush()
+}
+
+// addr,length within memory
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    let (address, length) = range.split_once(',')?;
+    let address = usize::from_str_radix(address, 16).ok()?;
+    let length = usize::from_str_radix(length, 16).ok()?;
+    if address.checked_add(length)? > MEMORY_SIZE {
+        return None;
+    }
+    Some((address, length))
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    hex::decode(text).ok()
+}
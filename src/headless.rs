@@ -0,0 +1,208 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::display::Display;
+use crate::fault::FaultPolicy;
+use crate::machine::Machine;
+use crate::timers::FrameScheduler;
+
+// RGB of each colour a pixel can have, matching the window's palette
+const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [255, 153, 25], [128, 128, 128]];
+
+/// Keypad input to feed a headless run, read from a script.
+///
+/// Each line is `FRAME press KEY` or `FRAME release KEY`, with the key as a
+/// hex digit. The change happens before frame FRAME runs, counting from 0.
+/// Blank lines and anything after `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyScript {
+    // (frame, key, pressed) sorted by frame
+    events: Vec<(u64, u8, bool)>,
+}
+
+impl KeyScript {
+    pub fn parse(text: &str) -> Result<KeyScript, String> {
+        let mut events = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            events.push(parse_event(&words).map_err(|err| format!("line {}: {}", index + 1, err))?);
+        }
+        Ok(KeyScript::from_events(events))
+    }
+
+    // Script of (frame, key, pressed) changes in any order
+    pub(crate) fn from_events(mut events: Vec<(u64, u8, bool)>) -> KeyScript {
+        // Stable, so changes on the same frame keep their order
+        events.sort_by_key(|&(frame, _, _)| frame);
+        KeyScript { events }
+    }
+
+    pub fn from_file(path: &str) -> Result<KeyScript, Box<dyn Error>> {
+        Ok(KeyScript::parse(&fs::read_to_string(path)?)?)
+    }
+
+    // Press and release the keys scripted for a frame
+    fn apply(&self, frame: u64, machine: &mut Machine) {
+        for &(_, key, pressed) in self.events.iter().filter(|&&(at, _, _)| at == frame) {
+            if pressed {
+                machine.press_key(key);
+            } else {
+                machine.release_key(key);
+            }
+        }
+    }
+}
+
+// The words of a FRAME press|release KEY line
+pub(crate) fn parse_event(words: &[&str]) -> Result<(u64, u8, bool), String> {
+    match words[..] {
+        [frame, action, key] => {
+            let frame = frame.parse().map_err(|_| format!("invalid frame '{}'", frame))?;
+            let pressed = match action {
+                "press" => true,
+                "release" => false,
+                _ => return Err(format!("expected press or release, found '{}'", action)),
+            };
+            let key = u8::from_str_radix(key, 16).ok().filter(|&key| key < 16).ok_or(format!("invalid key '{}'", key))?;
+            Ok((frame, key, pressed))
+        }
+        _ => Err("expected FRAME press|release KEY".to_string()),
+    }
+}
+
+/// The framebuffer as text, `#` for a lit pixel and `.` for a dark one.
+pub fn screen_ascii(display: &Display) -> String {
+    let mut out = String::new();
+    for y in 0..display.height() {
+        out.extend((0..display.width()).map(|x| if display.pixel(x, y) { '#' } else { '.' }));
+        out.push('\n');
+    }
+    out
+}
+
+/// The framebuffer as a binary PBM, black for lit pixels on any plane.
+pub fn screen_pbm(display: &Display) -> Vec<u8> {
+    let mut out = format!("P4\n{} {}\n", display.width(), display.height()).into_bytes();
+    for y in 0..display.height() {
+        // Eight pixels to a byte, leftmost in the most significant bit
+        for x in (0..display.width()).step_by(8) {
+            out.push((0..8).fold(0, |byte, bit| byte << 1 | display.pixel(x + bit, y) as u8));
+        }
+    }
+    out
+}
+
+/// The framebuffer as an RGB PNG in the window's colours.
+pub fn screen_png(display: &Display) -> Result<Vec<u8>, png::EncodingError> {
+    let mut pixels = Vec::with_capacity(display.width() * display.height() * 3);
+    for y in 0..display.height() {
+        for x in 0..display.width() {
+            pixels.extend_from_slice(&PALETTE[display.colour(x, y) as usize]);
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, display.width() as u32, display.height() as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(out)
+}
+
+/// Write the framebuffer to `path` and its ASCII rendering next to it.
+///
+/// A path ending in `.txt` gets only the ASCII. Otherwise `.png` gets a PNG,
+/// anything else a PBM, and the text goes to the same path with a `.txt`
+/// extension.
+pub fn dump_screen(display: &Display, path: &str) -> Result<(), Box<dyn Error>> {
+    let image = match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+        Some("txt") => None,
+        Some("png") => Some(screen_png(display)?),
+        _ => Some(screen_pbm(display)),
+    };
+    if let Some(image) = image {
+        fs::write(path, image)?;
+    }
+
+    let mut text = BufWriter::new(File::create(Path::new(path).with_extension("txt"))?);
+    text.write_all(screen_ascii(display).as_bytes())?;
+    text.flush()?;
+    Ok(())
+}
+
+// out.pbm dumped at frame 60 becomes out-000060.pbm
+fn numbered_path(path: &str, frame: u64) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{:06}.{}", stem, frame, extension.to_string_lossy()),
+        None => format!("{}-{:06}", stem, frame),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Runs a machine for a fixed number of frames without a window.
+pub struct Headless {
+    pub machine: Machine,
+    scheduler: FrameScheduler,
+    fault_policy: FaultPolicy,
+    keys: KeyScript,
+    // Frames run so far
+    frame: u64,
+}
+
+impl Headless {
+    pub fn new(machine: Machine, scheduler: FrameScheduler, fault_policy: FaultPolicy, keys: KeyScript) -> Headless {
+        Headless {
+            machine,
+            scheduler,
+            fault_policy,
+            keys,
+            frame: 0,
+        }
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Run one frame with its scripted input
+    pub fn step_frame(&mut self) -> Result<(), Box<dyn Error>> {
+        self.keys.apply(self.frame, &mut self.machine);
+        let instructions = self.scheduler.next_frame();
+        self.machine.run_frame(instructions, &self.fault_policy)?;
+        self.frame += 1;
+        Ok(())
+    }
+
+    /// Run up to `frames` frames, stopping early if the program exits.
+    ///
+    /// With `dump` set the final screen is written there, even when a fault
+    /// stops the run. With `every` as well, a numbered copy is also written
+    /// after every that many frames.
+    pub fn run(&mut self, frames: u64, dump: Option<&str>, every: Option<u64>) -> Result<(), Box<dyn Error>> {
+        let mut result = Ok(());
+        while self.frame < frames && !self.machine.exited {
+            result = self.step_frame();
+            if result.is_err() {
+                break;
+            }
+            if let (Some(path), Some(every)) = (dump, every) {
+                // checked_rem is None for every 0, and predates is_multiple_of
+                if self.frame.checked_rem(every) == Some(0) {
+                    dump_screen(&self.machine.display, &numbered_path(path, self.frame))?;
+                }
+            }
+        }
+        if let Some(path) = dump {
+            dump_screen(&self.machine.display, path)?;
+        }
+        result
+    }
+}
@@ -0,0 +1,347 @@
+use std::error::Error;
+use std::fmt;
+
+/// A single decoded CHIP-8 instruction.
+///
+/// Register operands (`x`, `y`) are register numbers 0x0 to 0xF, `addr` is a
+/// 12 bit address, `byte` is the 8 bit immediate `KK` and `n` the 4 bit nibble.
+/// Every opcode that decodes gives back the same opcode, so
+/// `encode(&decode(op)?) == op`. The round trip only holds that way round:
+/// a `Sys` whose address belongs to another 0NNN instruction, such as
+/// `Sys(0x0E0)`, encodes to that instruction's opcode and decodes as it.
+///
+/// XO-CHIP's `F000 NNNN` is the only instruction longer than one word:
+/// `LongI` decodes from the `F000` word and the address is the word after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    // 0NNN
+    Sys(u16),
+    // 00CN, SUPER-CHIP
+    ScrollDown(u8),
+    // 00DN, XO-CHIP
+    ScrollUp(u8),
+    // 00E0
+    Cls,
+    // 00EE
+    Ret,
+    // 00FB, SUPER-CHIP
+    ScrollRight,
+    // 00FC, SUPER-CHIP
+    ScrollLeft,
+    // 00FD, SUPER-CHIP
+    Exit,
+    // 00FE, SUPER-CHIP
+    Low,
+    // 00FF, SUPER-CHIP
+    High,
+    // 1NNN
+    Jp(u16),
+    // 2NNN
+    Call(u16),
+    // 3XKK
+    SeVxByte { x: u8, byte: u8 },
+    // 4XKK
+    SneVxByte { x: u8, byte: u8 },
+    // 5XY0
+    SeVxVy { x: u8, y: u8 },
+    // 5XY2, XO-CHIP
+    SaveRange { x: u8, y: u8 },
+    // 5XY3, XO-CHIP
+    LoadRange { x: u8, y: u8 },
+    // 6XKK
+    LdVxByte { x: u8, byte: u8 },
+    // 7XKK
+    AddVxByte { x: u8, byte: u8 },
+    // 8XY0
+    LdVxVy { x: u8, y: u8 },
+    // 8XY1
+    Or { x: u8, y: u8 },
+    // 8XY2
+    And { x: u8, y: u8 },
+    // 8XY3
+    Xor { x: u8, y: u8 },
+    // 8XY4
+    AddVxVy { x: u8, y: u8 },
+    // 8XY5
+    Sub { x: u8, y: u8 },
+    // 8XY6
+    Shr { x: u8, y: u8 },
+    // 8XY7
+    Subn { x: u8, y: u8 },
+    // 8XYE
+    Shl { x: u8, y: u8 },
+    // 9XY0
+    SneVxVy { x: u8, y: u8 },
+    // ANNN
+    LdI(u16),
+    // BNNN
+    JpV0(u16),
+    // CXKK
+    Rnd { x: u8, byte: u8 },
+    // DXYN, DXY0 draws a 16x16 sprite on SUPER-CHIP
+    Drw { x: u8, y: u8, n: u8 },
+    // EX9E
+    Skp(u8),
+    // EXA1
+    Sknp(u8),
+    // F000 NNNN, XO-CHIP
+    LongI,
+    // FN01, XO-CHIP
+    Plane(u8),
+    // F002, XO-CHIP
+    Audio,
+    // FX07
+    LdVxDt(u8),
+    // FX0A
+    LdVxK(u8),
+    // FX15
+    LdDtVx(u8),
+    // FX18
+    LdStVx(u8),
+    // FX1E
+    AddIVx(u8),
+    // FX29
+    LdFVx(u8),
+    // FX30, SUPER-CHIP
+    LdHfVx(u8),
+    // FX33
+    LdBVx(u8),
+    // FX3A, XO-CHIP
+    Pitch(u8),
+    // FX55
+    LdIVx(u8),
+    // FX65
+    LdVxI(u8),
+    // FX75, SUPER-CHIP
+    LdRVx(u8),
+    // FX85, SUPER-CHIP
+    LdVxR(u8),
+}
+
+/// Returned by `decode` for opcodes that are not a CHIP-8 instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid opcode {:04X}", self.opcode)
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Decode a big endian opcode into an `Instruction`.
+pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+    let addr = opcode & 0x0FFF;
+    let x = ((opcode >> 8) & 0xF) as u8;
+    let y = ((opcode >> 4) & 0xF) as u8;
+    let n = (opcode & 0xF) as u8;
+    let byte = (opcode & 0xFF) as u8;
+
+    let instruction = match opcode >> 12 {
+        0x0 => match opcode {
+            0x00C0..=0x00CF => Instruction::ScrollDown(n),
+            0x00D0..=0x00DF => Instruction::ScrollUp(n),
+            0x00E0 => Instruction::Cls,
+            0x00EE => Instruction::Ret,
+            0x00FB => Instruction::ScrollRight,
+            0x00FC => Instruction::ScrollLeft,
+            0x00FD => Instruction::Exit,
+            0x00FE => Instruction::Low,
+            0x00FF => Instruction::High,
+            _ => Instruction::Sys(addr),
+        },
+        0x1 => Instruction::Jp(addr),
+        0x2 => Instruction::Call(addr),
+        0x3 => Instruction::SeVxByte { x, byte },
+        0x4 => Instruction::SneVxByte { x, byte },
+        0x5 => match n {
+            0x0 => Instruction::SeVxVy { x, y },
+            0x2 => Instruction::SaveRange { x, y },
+            0x3 => Instruction::LoadRange { x, y },
+            _ => return Err(DecodeError { opcode }),
+        },
+        0x6 => Instruction::LdVxByte { x, byte },
+        0x7 => Instruction::AddVxByte { x, byte },
+        0x8 => match n {
+            0x0 => Instruction::LdVxVy { x, y },
+            0x1 => Instruction::Or { x, y },
+            0x2 => Instruction::And { x, y },
+            0x3 => Instruction::Xor { x, y },
+            0x4 => Instruction::AddVxVy { x, y },
+            0x5 => Instruction::Sub { x, y },
+            0x6 => Instruction::Shr { x, y },
+            0x7 => Instruction::Subn { x, y },
+            0xE => Instruction::Shl { x, y },
+            _ => return Err(DecodeError { opcode }),
+        },
+        0x9 if n == 0x0 => Instruction::SneVxVy { x, y },
+        0xA => Instruction::LdI(addr),
+        0xB => Instruction::JpV0(addr),
+        0xC => Instruction::Rnd { x, byte },
+        0xD => Instruction::Drw { x, y, n },
+        0xE => match byte {
+            0x9E => Instruction::Skp(x),
+            0xA1 => Instruction::Sknp(x),
+            _ => return Err(DecodeError { opcode }),
+        },
+        0xF => match byte {
+            0x00 if x == 0x0 => Instruction::LongI,
+            0x01 => Instruction::Plane(x),
+            0x02 if x == 0x0 => Instruction::Audio,
+            0x07 => Instruction::LdVxDt(x),
+            0x0A => Instruction::LdVxK(x),
+            0x15 => Instruction::LdDtVx(x),
+            0x18 => Instruction::LdStVx(x),
+            0x1E => Instruction::AddIVx(x),
+            0x29 => Instruction::LdFVx(x),
+            0x30 => Instruction::LdHfVx(x),
+            0x33 => Instruction::LdBVx(x),
+            0x3A => Instruction::Pitch(x),
+            0x55 => Instruction::LdIVx(x),
+            0x65 => Instruction::LdVxI(x),
+            0x75 => Instruction::LdRVx(x),
+            0x85 => Instruction::LdVxR(x),
+            _ => return Err(DecodeError { opcode }),
+        },
+        _ => return Err(DecodeError { opcode }),
+    };
+
+    Ok(instruction)
+}
+
+/// Encode an `Instruction` back into its big endian opcode.
+///
+/// Operands are masked to their field width, so out of range register
+/// numbers or addresses cannot spill into the neighbouring nibbles.
+/// `Sys` addresses 0x0C0 to 0x0DF, 0x0E0, 0x0EE and 0x0FB to 0x0FF are not
+/// checked either, they come out as the opcodes `decode` reads as scrolls,
+/// CLS, RET and the other SUPER-CHIP instructions.
+pub fn encode(instruction: &Instruction) -> u16 {
+    fn xkk(high: u16, x: u8, byte: u8) -> u16 {
+        high << 12 | ((x & 0xF) as u16) << 8 | byte as u16
+    }
+
+    fn xyn(high: u16, x: u8, y: u8, n: u8) -> u16 {
+        high << 12 | ((x & 0xF) as u16) << 8 | ((y & 0xF) as u16) << 4 | (n & 0xF) as u16
+    }
+
+    fn nnn(high: u16, addr: u16) -> u16 {
+        high << 12 | (addr & 0x0FFF)
+    }
+
+    match *instruction {
+        Instruction::Sys(addr) => nnn(0x0, addr),
+        Instruction::ScrollDown(n) => 0x00C0 | (n & 0xF) as u16,
+        Instruction::ScrollUp(n) => 0x00D0 | (n & 0xF) as u16,
+        Instruction::Cls => 0x00E0,
+        Instruction::Ret => 0x00EE,
+        Instruction::ScrollRight => 0x00FB,
+        Instruction::ScrollLeft => 0x00FC,
+        Instruction::Exit => 0x00FD,
+        Instruction::Low => 0x00FE,
+        Instruction::High => 0x00FF,
+        Instruction::Jp(addr) => nnn(0x1, addr),
+        Instruction::Call(addr) => nnn(0x2, addr),
+        Instruction::SeVxByte { x, byte } => xkk(0x3, x, byte),
+        Instruction::SneVxByte { x, byte } => xkk(0x4, x, byte),
+        Instruction::SeVxVy { x, y } => xyn(0x5, x, y, 0x0),
+        Instruction::SaveRange { x, y } => xyn(0x5, x, y, 0x2),
+        Instruction::LoadRange { x, y } => xyn(0x5, x, y, 0x3),
+        Instruction::LdVxByte { x, byte } => xkk(0x6, x, byte),
+        Instruction::AddVxByte { x, byte } => xkk(0x7, x, byte),
+        Instruction::LdVxVy { x, y } => xyn(0x8, x, y, 0x0),
+        Instruction::Or { x, y } => xyn(0x8, x, y, 0x1),
+        Instruction::And { x, y } => xyn(0x8, x, y, 0x2),
+        Instruction::Xor { x, y } => xyn(0x8, x, y, 0x3),
+        Instruction::AddVxVy { x, y } => xyn(0x8, x, y, 0x4),
+        Instruction::Sub { x, y } => xyn(0x8, x, y, 0x5),
+        Instruction::Shr { x, y } => xyn(0x8, x, y, 0x6),
+        Instruction::Subn { x, y } => xyn(0x8, x, y, 0x7),
+        Instruction::Shl { x, y } => xyn(0x8, x, y, 0xE),
+        Instruction::SneVxVy { x, y } => xyn(0x9, x, y, 0x0),
+        Instruction::LdI(addr) => nnn(0xA, addr),
+        Instruction::JpV0(addr) => nnn(0xB, addr),
+        Instruction::Rnd { x, byte } => xkk(0xC, x, byte),
+        Instruction::Drw { x, y, n } => xyn(0xD, x, y, n),
+        Instruction::Skp(x) => xkk(0xE, x, 0x9E),
+        Instruction::Sknp(x) => xkk(0xE, x, 0xA1),
+        Instruction::LongI => 0xF000,
+        Instruction::Plane(n) => xkk(0xF, n, 0x01),
+        Instruction::Audio => 0xF002,
+        Instruction::LdVxDt(x) => xkk(0xF, x, 0x07),
+        Instruction::LdVxK(x) => xkk(0xF, x, 0x0A),
+        Instruction::LdDtVx(x) => xkk(0xF, x, 0x15),
+        Instruction::LdStVx(x) => xkk(0xF, x, 0x18),
+        Instruction::AddIVx(x) => xkk(0xF, x, 0x1E),
+        Instruction::LdFVx(x) => xkk(0xF, x, 0x29),
+        Instruction::LdHfVx(x) => xkk(0xF, x, 0x30),
+        Instruction::LdBVx(x) => xkk(0xF, x, 0x33),
+        Instruction::Pitch(x) => xkk(0xF, x, 0x3A),
+        Instruction::LdIVx(x) => xkk(0xF, x, 0x55),
+        Instruction::LdVxI(x) => xkk(0xF, x, 0x65),
+        Instruction::LdRVx(x) => xkk(0xF, x, 0x75),
+        Instruction::LdVxR(x) => xkk(0xF, x, 0x85),
+    }
+}
+
+// Mnemonics follow Cowgod's CHIP-8 technical reference
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Sys(addr) => write!(f, "SYS 0x{:03X}", addr),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Low => write!(f, "LOW"),
+            Instruction::High => write!(f, "HIGH"),
+            Instruction::Jp(addr) => write!(f, "JP 0x{:03X}", addr),
+            Instruction::Call(addr) => write!(f, "CALL 0x{:03X}", addr),
+            Instruction::SeVxByte { x, byte } => write!(f, "SE V{:X}, 0x{:02X}", x, byte),
+            Instruction::SneVxByte { x, byte } => write!(f, "SNE V{:X}, 0x{:02X}", x, byte),
+            Instruction::SeVxVy { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SaveRange { x, y } => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Instruction::LdVxByte { x, byte } => write!(f, "LD V{:X}, 0x{:02X}", x, byte),
+            Instruction::AddVxByte { x, byte } => write!(f, "ADD V{:X}, 0x{:02X}", x, byte),
+            Instruction::LdVxVy { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddVxVy { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::Shr { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::Subn { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::Shl { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SneVxVy { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LdI(addr) => write!(f, "LD I, 0x{:03X}", addr),
+            Instruction::JpV0(addr) => write!(f, "JP V0, 0x{:03X}", addr),
+            Instruction::Rnd { x, byte } => write!(f, "RND V{:X}, 0x{:02X}", x, byte),
+            Instruction::Drw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::Skp(x) => write!(f, "SKP V{:X}", x),
+            Instruction::Sknp(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LongI => write!(f, "LD I, LONG"),
+            Instruction::Plane(n) => write!(f, "PLANE {}", n),
+            Instruction::Audio => write!(f, "AUDIO"),
+            Instruction::LdVxDt(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::LdVxK(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::LdDtVx(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::LdStVx(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIVx(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::LdFVx(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::LdHfVx(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::LdBVx(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Instruction::LdIVx(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::LdVxI(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::LdRVx(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::LdVxR(x) => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
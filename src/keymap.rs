@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+// COSMAC VIP keypad rows laid over 1234/QWER/ASDF/ZXCV
+const COSMAC_LAYOUT: [(u8, &str); 16] = [
+    (0x1, "1"), (0x2, "2"), (0x3, "3"), (0xC, "4"),
+    (0x4, "q"), (0x5, "w"), (0x6, "e"), (0xD, "r"),
+    (0x7, "a"), (0x8, "s"), (0x9, "d"), (0xE, "f"),
+    (0xA, "z"), (0x0, "x"), (0xB, "c"), (0xF, "v"),
+];
+
+// Controller inputs, the D-pad and left stick move with the usual 2/4/6/8 and the first button is 5
+const PAD_LAYOUT: [(u8, &str); 9] = [
+    (0x2, "hat-up"), (0x8, "hat-down"), (0x4, "hat-left"), (0x6, "hat-right"),
+    (0x2, "axis1-"), (0x8, "axis1+"), (0x4, "axis0-"), (0x6, "axis0+"),
+    (0x5, "button0"),
+];
+
+// How far a stick has to move from the centre to count as pressed
+pub const AXIS_THRESHOLD: f64 = 0.5;
+
+/// Which physical inputs press which of the 16 CHIP-8 keys.
+///
+/// Inputs are named in lower case. Keyboard keys are e.g. `q`, `7`, `space`,
+/// `up` or `numpad5`. Controllers have `buttonN`, `hat-up`, `hat-down`,
+/// `hat-left` and `hat-right` for the D-pad, and `axisN-` and `axisN+` for
+/// each way a stick can be pushed. Any number of inputs can press the same
+/// CHIP-8 key, but each input presses at most one.
+///
+/// A config file has a `[default]` section and any number of sections named
+/// after a ROM file, each made of lines like `5 = w up`. The default section
+/// replaces the COSMAC layout bindings of the keys it lists, and a ROM's
+/// section does the same on top of that for the ROM. `#` starts a comment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: HashMap<String, u8>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::cosmac()
+    }
+}
+
+impl Keymap {
+    /// No inputs bound at all.
+    pub fn empty() -> Keymap {
+        Keymap { bindings: HashMap::new() }
+    }
+
+    /// The VIP's 4x4 keypad on the left hand side of a QWERTY keyboard, with
+    /// a controller's D-pad and left stick on 2/4/6/8 and its first button on 5.
+    pub fn cosmac() -> Keymap {
+        let mut keymap = Keymap::empty();
+        for (key, input) in COSMAC_LAYOUT.iter().chain(PAD_LAYOUT.iter()) {
+            keymap.bind(input, *key);
+        }
+        keymap
+    }
+
+    // Make an input press a CHIP-8 key, taking it away from any other
+    pub fn bind(&mut self, input: &str, key: u8) {
+        self.bindings.insert(input.to_lowercase(), key & 0xF);
+    }
+
+    // Remove every input bound to a CHIP-8 key
+    pub fn unbind_key(&mut self, key: u8) {
+        self.bindings.retain(|_, bound| *bound != key);
+    }
+
+    // The CHIP-8 key an input presses
+    pub fn key(&self, input: &str) -> Option<u8> {
+        self.bindings.get(input).copied()
+    }
+
+    // Every input bound to a CHIP-8 key, sorted
+    pub fn inputs(&self, key: u8) -> Vec<&str> {
+        let mut inputs: Vec<&str> = self.bindings.iter().filter(|&(_, &bound)| bound == key).map(|(input, _)| input.as_str()).collect();
+        inputs.sort_unstable();
+        inputs
+    }
+
+    /// The COSMAC layout changed by the `[default]` section of a config and
+    /// then by the section for `rom`, if there is one.
+    pub fn parse(text: &str, rom: Option<&str>) -> Result<Keymap, String> {
+        let mut keymap = Keymap::cosmac();
+        let mut rom_lines = Vec::new();
+        // Lines of the section being read go to the default keymap, a ROM's list or nowhere
+        let mut section = Some(false);
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                let name = name.trim();
+                section = if name == "default" {
+                    Some(false)
+                } else if Some(name) == rom {
+                    Some(true)
+                } else {
+                    None
+                };
+                continue;
+            }
+            match section {
+                Some(false) => apply_line(&mut keymap, line).map_err(|err| format!("line {}: {}", index + 1, err))?,
+                Some(true) => rom_lines.push((index, line)),
+                // Still checked, so a typo in another ROM's section isn't missed
+                None => apply_line(&mut Keymap::empty(), line).map_err(|err| format!("line {}: {}", index + 1, err))?,
+            }
+        }
+
+        // ROM sections apply last wherever they are in the file
+        for (index, line) in rom_lines {
+            apply_line(&mut keymap, line).map_err(|err| format!("line {}: {}", index + 1, err))?;
+        }
+        Ok(keymap)
+    }
+
+    pub fn load(path: &str, rom: Option<&str>) -> Result<Keymap, Box<dyn Error>> {
+        Ok(Keymap::parse(&fs::read_to_string(path)?, rom)?)
+    }
+}
+
+// KEY = INPUT INPUT ..., replacing the key's bindings
+fn apply_line(keymap: &mut Keymap, line: &str) -> Result<(), String> {
+    let (key, inputs) = line.split_once('=').ok_or(format!("expected KEY = INPUTS, found '{}'", line))?;
+    let key = key.trim();
+    let key = u8::from_str_radix(key, 16).ok().filter(|&key| key < 16).ok_or(format!("invalid key '{}'", key))?;
+
+    keymap.unbind_key(key);
+    for input in inputs.split_whitespace() {
+        keymap.bind(input, key);
+    }
+    Ok(())
+}
+
+// Name of a controller button
+pub fn button_input(button: u8) -> String {
+    format!("button{}", button)
+}
+
+// The two inputs of a stick axis and whether each is pushed at a position from -1.0 to 1.0
+pub fn axis_inputs(axis: u8, position: f64) -> [(String, bool); 2] {
+    [
+        (format!("axis{}-", axis), position <= -AXIS_THRESHOLD),
+        (format!("axis{}+", axis), position >= AXIS_THRESHOLD),
+    ]
+}
+
+// The four D-pad inputs and whether each is held, a diagonal holds two
+pub fn hat_inputs(up: bool, down: bool, left: bool, right: bool) -> [(&'static str, bool); 4] {
+    [("hat-up", up), ("hat-down", down), ("hat-left", left), ("hat-right", right)]
+}
@@ -0,0 +1,125 @@
+//! CHIP-8 interpreter core
+//!
+//! Everything needed to run a program without opening a window lives here:
+//! the machine (CPU, memory, timers, framebuffer and keypad state), save
+//! states of it and a rewind history built on them, input movies that replay
+//! a session exactly, plus assemblers for Cowgod mnemonics and Octo source.
+//! Frontends drive it by calling `execute_cycle` and reading `display`.
+
+pub mod assembler;
+pub mod audio;
+pub mod debugger;
+pub mod describe;
+pub mod disasm;
+pub mod display;
+pub mod fault;
+pub mod gdb;
+pub mod headless;
+pub mod instruction;
+pub mod keymap;
+pub mod machine;
+pub mod movie;
+pub mod octo;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod state;
+pub mod timers;
+
+pub use assembler::{assemble, assemble_file, AsmError};
+pub use audio::{AudioBackend, NullAudio, SoundFrame, ToneSettings, WavAudio, Waveform};
+pub use debugger::{Breakpoint, Debugger, Stop};
+pub use disasm::disassemble;
+pub use display::{Display, HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH, PLANES};
+pub use fault::{FaultAction, FaultKind, FaultPolicy, MachineFault, StepOutcome};
+pub use gdb::{GdbInput, GdbStub};
+pub use headless::{dump_screen, screen_ascii, Headless, KeyScript};
+pub use instruction::{decode, encode, DecodeError, Instruction};
+pub use keymap::Keymap;
+pub use machine::Machine;
+pub use movie::{state_hash, Movie};
+pub use octo::compile as compile_octo;
+pub use quirks::{IndexIncrement, Quirks};
+pub use random::{RandomKind, RandomSource, SeededRandom, VipRandom, DEFAULT_SEED};
+pub use rewind::RewindBuffer;
+pub use state::{MachineState, StateError, STATE_VERSION};
+pub use timers::{FrameScheduler, FRAME_SECONDS, TIMER_HZ};
+
+pub const SPRITE_START: usize = 0;
+// SUPER-CHIP 8x10 digits are stored straight after the small ones
+pub const BIG_SPRITE_START: usize = SPRITE_START + SPRITES.len();
+pub const PROGRAM_START: usize = 512;
+// XO-CHIP extends memory to the full 16 bit address space
+pub const MEMORY_SIZE: usize = 0x10000;
+// All a CHIP-8 or SUPER-CHIP program can use
+pub const CHIP8_MEMORY_SIZE: usize = 0x1000;
+
+pub const SPRITES: [u8; 80] = [
+    // 0
+    0xF0, 0x90, 0x90, 0x90, 0xF0,
+    // 1
+    0x20, 0x60, 0x20, 0x20, 0x70,
+    // 2
+    0xF0, 0x10, 0xF0, 0x80, 0xF0,
+    // 3
+    0xF0, 0x10, 0xF0, 0x10, 0xF0,
+    // 4
+    0x90, 0x90, 0xF0, 0x10, 0x10,
+    // 5
+    0xF0, 0x80, 0xF0, 0x10, 0xF0,
+    // 6
+    0xF0, 0x80, 0xF0, 0x90, 0xF0,
+    // 7
+    0xF0, 0x10, 0x20, 0x40, 0x40,
+    // 8
+    0xF0, 0x90, 0xF0, 0x90, 0xF0,
+    // 9
+    0xF0, 0x90, 0xF0, 0x10, 0xF0,
+    // A
+    0xF0, 0x90, 0xF0, 0x90, 0x90,
+    // B
+    0xE0, 0x90, 0xE0, 0x90, 0xE0,
+    // C
+    0xF0, 0x80, 0x80, 0x80, 0xF0,
+    // D
+    0xE0, 0x90, 0x90, 0x90, 0xE0,
+    // E
+    0xF0, 0x80, 0xF0, 0x80, 0xF0,
+    // F
+    0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+pub const BIG_SPRITES: [u8; 160] = [
+    // 0
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C,
+    // 1
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C,
+    // 2
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF,
+    // 3
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C,
+    // 4
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06,
+    // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C,
+    // 6
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C,
+    // 7
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60,
+    // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C,
+    // 9
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C,
+    // A
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3,
+    // B
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC,
+    // C
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C,
+    // D
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC,
+    // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,
+    // F
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0,
+];
@@ -0,0 +1,617 @@
+use std::error::Error;
+use std::fs;
+
+use crate::audio::{AudioBackend, NullAudio, SoundFrame, DEFAULT_PITCH};
+use crate::fault::{FaultAction, FaultPolicy, MachineFault, StepOutcome};
+use crate::instruction::{decode, Instruction};
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::display::Display;
+use crate::octo;
+use crate::random::{RandomSource, SeededRandom, DEFAULT_SEED};
+use crate::{BIG_SPRITES, BIG_SPRITE_START, MEMORY_SIZE, PROGRAM_START, SPRITES, SPRITE_START};
+
+/*
+ * Memory
+ *  8 byte per location
+ *  must start memory at 512 bytes as this would contain the interpreter
+ *
+ * Registers
+ *  16 8 bit registers
+ *  V0 to VF
+ *  VF used as a flag for some instructions
+ *  address register:
+ *      12 bits wide
+ *  PC (starts at 64 (40 hex) )
+ *
+ * Stack
+ *  stores return addresses for subroutines
+ *
+ * Timers
+ *  count down at 60 times per second
+ *  Delay:
+ *      used for timing events in video games
+ *  Sound
+ *      when nonzero a beep is made
+ *
+ * Input
+ *  input hex characters for input
+ *  maybe remap to different keybaord characters
+ *
+ * Graphics
+ *  monochrome 64 x 32
+ *  drawn with sprites (8 x 1 to 15)
+ *  sprite pixels XORd wit corresponding screen pixels
+ *  carry flag (VF) set to 1 if any screen pixels flipped from set to unset when sprite drawn otherwise 0
+ *  STORING
+ *      store all of pixels as binary for each row
+ *  WRITING
+ *      XOR the data at location I With data starting at a position
+ *      set VF to 1 if any pixels unset
+ *
+ * Opcode understanding
+ *  NNN = address location
+ *  N or NN = value
+ *  X or Y
+ *  I (MAR) 16 bit
+ */
+
+/// The CHIP-8 machine: CPU, memory, timers, framebuffer and keypad state.
+///
+/// It knows nothing about windows or input devices. A frontend presses keys
+/// with `press_key`/`release_key`, calls `execute_cycle` at its chosen rate
+/// and draws `display` however it likes.
+pub struct Machine {
+    // MEMORY_SIZE bytes, of which programs can use memory_size()
+    pub memory: Vec<u8>,
+    pub general_registers: [u8; 16],
+    // I register
+    pub memory_register: u16,
+    pub program_counter: u16,
+    pub stack_pointer: i8,
+    // Both timers count down once per frame, see tick_timers
+    pub sound_timer: u8,
+    pub delay_timer: u8,
+    pub stack: [u16; 16],
+    pub display: Display,
+    // SUPER-CHIP RPL user flags, saved and restored by FX75 and FX85
+    pub rpl_flags: [u8; 16],
+    // Set by 00FD, the machine executes nothing more
+    pub exited: bool,
+    // XO-CHIP audio pattern loaded by F002, the configured tone plays until then
+    pub audio_pattern: Option<[u8; 16]>,
+    // XO-CHIP playback pitch set by FX3A
+    pub pitch: u8,
+    // Pressed state of the 16 hex keys 0x0 to 0xF
+    pub keypad: [bool; 16],
+    // Bit N is set once EX9E or EXA1 reads key N, FX0A sets them all. Cleared
+    // at the start of each run_frame and left out of save states
+    pub keys_polled: u16,
+    // Which interpretation of the ambiguous instructions to follow
+    pub quirks: Quirks,
+    // Where the beeper goes, driven by tick_timers
+    audio: Box<dyn AudioBackend>,
+    // Where CXNN gets its bytes, its state is part of save states
+    pub(crate) random: Box<dyn RandomSource>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Machine {
+        Machine::with_quirks(Quirks::default())
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> Machine {
+        let mut memory_prepared = vec![0; MEMORY_SIZE];
+
+        // Add sprites to memory (interpreter part)
+        memory_prepared[SPRITE_START..SPRITE_START + SPRITES.len()].copy_from_slice(&SPRITES);
+        memory_prepared[BIG_SPRITE_START..BIG_SPRITE_START + BIG_SPRITES.len()].copy_from_slice(&BIG_SPRITES);
+
+        Machine {
+            memory: memory_prepared,
+            general_registers: [0; 16],
+            program_counter: PROGRAM_START as u16,
+            memory_register: 0,
+            sound_timer: 0,
+            delay_timer: 0,
+            stack_pointer: -1,
+            stack: [0; 16],
+            display: Display::new(),
+            rpl_flags: [0; 16],
+            exited: false,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            keypad: [false; 16],
+            keys_polled: 0,
+            quirks,
+            audio: Box::new(NullAudio),
+            random: Box::new(SeededRandom::new(DEFAULT_SEED)),
+        }
+    }
+
+    // Bytes of memory the program can use, 4 KiB unless the quirks are XO-CHIP's
+    pub fn memory_size(&self) -> usize {
+        self.quirks.memory_size()
+    }
+
+    // Loads raw program data into memory at the program start
+    pub fn load_rom(&mut self, program_bytes: &[u8]) -> Result<(), MachineFault> {
+        let max = self.memory_size() - PROGRAM_START;
+        if program_bytes.len() > max {
+            return Err(MachineFault::RomTooLarge { size: program_bytes.len(), max });
+        }
+
+        self.memory[PROGRAM_START..PROGRAM_START + program_bytes.len()].copy_from_slice(program_bytes);
+        Ok(())
+    }
+
+    // Loads raw data from a file into the memory, compiling Octo source (.8o) first
+    pub fn load_from_file(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        let program_bytes = if path.ends_with(".8o") { octo::compile_file(path)? } else { fs::read(path)? };
+
+        self.load_rom(&program_bytes)?;
+        Ok(())
+    }
+
+    pub fn press_key(&mut self, key: u8) {
+        self.keypad[(key & 0xF) as usize] = true;
+    }
+
+    pub fn release_key(&mut self, key: u8) {
+        self.keypad[(key & 0xF) as usize] = false;
+    }
+
+    pub fn is_key_pressed(&self, key: u8) -> bool {
+        self.keypad[(key & 0xF) as usize]
+    }
+
+    // Swap the audio backend, returning the previous one
+    pub fn set_audio(&mut self, audio: Box<dyn AudioBackend>) -> Box<dyn AudioBackend> {
+        std::mem::replace(&mut self.audio, audio)
+    }
+
+    // Swap the random source CXNN reads, returning the previous one
+    pub fn set_random(&mut self, random: Box<dyn RandomSource>) -> Box<dyn RandomSource> {
+        std::mem::replace(&mut self.random, random)
+    }
+
+    // Count both timers down by one, called once per 60 Hz frame.
+    // The beeper sounds for every frame the sound timer starts nonzero.
+    pub fn tick_timers(&mut self) {
+        self.audio.frame(&SoundFrame {
+            on: self.sound_timer > 0,
+            pattern: self.audio_pattern,
+            pitch: self.pitch,
+        });
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    /// Run one 60 Hz frame: execute `instructions` cycles then tick the timers.
+    ///
+    /// Faults are handled as `policy` says. A halting fault is returned
+    /// straight away, leaving the rest of the frame and the timer tick undone.
+    /// With the `wait_for_vblank` quirk a sprite draw ends the frame early.
+    pub fn run_frame(&mut self, instructions: u32, policy: &FaultPolicy) -> Result<(), MachineFault> {
+        self.keys_polled = 0;
+        for _ in 0..instructions {
+            match self.execute_cycle() {
+                Ok(StepOutcome::Executed(Instruction::Drw { .. })) if self.quirks.wait_for_vblank => break,
+                Ok(StepOutcome::Exited) => break,
+                Ok(_) => {}
+                Err(fault) => match policy.action_for(&fault) {
+                    FaultAction::Halt => return Err(fault),
+                    FaultAction::Log => eprintln!("{}", fault),
+                    FaultAction::Ignore => {}
+                },
+            }
+        }
+
+        self.tick_timers();
+        Ok(())
+    }
+
+    // Move I on after FX55 or FX65 as the quirks say
+    fn increment_index(&mut self, x: u8) {
+        let increment = match self.quirks.index_increment {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::ByX => x as u16,
+            IndexIncrement::ByXPlusOne => x as u16 + 1,
+        };
+        self.memory_register = self.memory_register.wrapping_add(increment);
+    }
+
+    // Where skipping the instruction at next lands, F000 NNNN being two words long
+    fn skip_from(&self, next: usize) -> usize {
+        let next_is_long = self.memory.get(next) == Some(&0xF0) && self.memory.get(next + 1) == Some(&0x00);
+        next + if next_is_long { 4 } else { 2 }
+    }
+
+    // Read a byte of memory an instruction at pc asked for
+    fn read_memory(&self, pc: u16, address: usize) -> Result<u8, MachineFault> {
+        match self.memory.get(address) {
+            Some(&value) if address < self.memory_size() => Ok(value),
+            _ => Err(MachineFault::MemoryOutOfBounds { pc, address }),
+        }
+    }
+
+    // Write a byte of memory an instruction at pc asked for
+    fn write_memory(&mut self, pc: u16, address: usize, value: u8) -> Result<(), MachineFault> {
+        let size = self.memory_size();
+        match self.memory.get_mut(address) {
+            Some(cell) if address < size => {
+                *cell = value;
+                Ok(())
+            }
+            _ => Err(MachineFault::MemoryOutOfBounds { pc, address }),
+        }
+    }
+
+    /// Fetch, decode and run the instruction at the program counter.
+    ///
+    /// Arithmetic wraps at 8 bits. Instructions that set VF write their result
+    /// to VX first and VF last, so when X is F the register ends up holding
+    /// the flag. Running off the end of memory, 4 KiB or 64 KiB with the
+    /// XO-CHIP quirks, is a `MemoryOutOfBounds` fault, raised by the instruction
+    /// whose next fetch would be past the end. Jumps and returns from the last
+    /// word are fine.
+    pub fn execute_cycle(&mut self) -> Result<StepOutcome, MachineFault> {
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+
+        let pc = self.program_counter;
+
+        // Get instruction PC points to. They are split in two bytes
+        let opcode = u16::from_be_bytes([self.read_memory(pc, pc as usize)?, self.read_memory(pc, pc as usize + 1)?]);
+
+        // The next instruction follows this one unless a jump or skip says otherwise. Move
+        // on now while that's in memory so an ignored fault carries on after this instruction
+        let mut next = pc as usize + 2;
+        if next < self.memory_size() {
+            self.program_counter = next as u16;
+        }
+
+        let instruction = decode(opcode).map_err(|_| MachineFault::InvalidOpcode { pc, opcode })?;
+
+        match instruction {
+            Instruction::Sys(_) => {
+                // Machine code routines only existed on the original hardware, ignored like every modern interpreter
+            }
+            Instruction::Cls => {
+                // println!("Clear display");
+                self.display.clear();
+            }
+            Instruction::ScrollDown(n) => {
+                self.display.scroll_down(n as usize);
+            }
+            Instruction::ScrollUp(n) => {
+                self.display.scroll_up(n as usize);
+            }
+            Instruction::ScrollRight => {
+                self.display.scroll_right(4);
+            }
+            Instruction::ScrollLeft => {
+                self.display.scroll_left(4);
+            }
+            Instruction::Exit => {
+                self.exited = true;
+                return Ok(StepOutcome::Exited);
+            }
+            Instruction::Low => {
+                self.display.set_hires(false);
+            }
+            Instruction::High => {
+                self.display.set_hires(true);
+            }
+            Instruction::Ret => {
+                // println!("Return from subroutine");
+                if self.stack_pointer < 0 {
+                    return Err(MachineFault::StackUnderflow { pc });
+                }
+                next = self.stack[self.stack_pointer as usize] as usize;
+                self.stack_pointer -= 1;
+            }
+            Instruction::Jp(address) => {
+                next = address as usize;
+                // println!("JUMP TO {:X}", address)
+            }
+            Instruction::Call(address) => {
+                if self.stack_pointer + 1 >= self.stack.len() as i8 {
+                    return Err(MachineFault::StackOverflow { pc });
+                }
+                self.stack_pointer += 1;
+                self.stack[self.stack_pointer as usize] = next as u16;
+                next = address as usize;
+                // println!("CALLING SUBROUTING AT {:X}", address)
+            }
+            Instruction::SeVxByte { x, byte: k } => {
+                if self.general_registers[x as usize] == k {
+                    next = self.skip_from(next);
+                }
+                // println!("SKIP IF Register {:X} == {:X}", x, k);
+            }
+            Instruction::SneVxByte { x, byte: k } => {
+                if self.general_registers[x as usize] != k {
+                    next = self.skip_from(next);
+                }
+                // println!("SKIP IF Register {:X} != {:X}", x, k);
+            }
+            Instruction::SeVxVy { x, y } => {
+                if self.general_registers[x as usize] == self.general_registers[y as usize] {
+                    next = self.skip_from(next);
+                }
+                // println!("SKIP IF Register {:X} == Register {:X}", x, y);
+            }
+            Instruction::SaveRange { x, y } => {
+                // Store Reg X through Reg Y (either way round) at I, I is unchanged
+                let address = self.memory_register as usize;
+                for (offset, register) in register_range(x, y).enumerate() {
+                    self.write_memory(pc, address + offset, self.general_registers[register])?;
+                }
+            }
+            Instruction::LoadRange { x, y } => {
+                // Load Reg X through Reg Y (either way round) from I, I is unchanged
+                let address = self.memory_register as usize;
+                for (offset, register) in register_range(x, y).enumerate() {
+                    self.general_registers[register] = self.read_memory(pc, address + offset)?;
+                }
+            }
+            Instruction::LdVxByte { x, byte: k } => {
+                // println!("SET Register {:X} to {:X}", x, k);
+                self.general_registers[x as usize] = k;
+            }
+            Instruction::AddVxByte { x, byte: k } => {
+                // println!("SET Register {} to Register {} ({}) + {}",x, x, self.general_registers[x as usize], k);
+                self.general_registers[x as usize] = self.general_registers[x as usize].wrapping_add(k);
+            }
+            Instruction::LdVxVy { x, y } => {
+                // println!("Copy value in Register {:X} to Register {:X}", x, y);
+                self.general_registers[x as usize] = self.general_registers[y as usize];
+            }
+            Instruction::Or { x, y } => {
+                // println!("Bitwise OR on Registers {:X} and {:X} and store in {:X}", x, y, x);
+                self.general_registers[x as usize] |= self.general_registers[y as usize];
+                if self.quirks.logic_resets_vf {
+                    self.general_registers[0xF] = 0;
+                }
+            }
+            Instruction::And { x, y } => {
+                // println!("Bitwise AND on Registers {:X} and {:X} and store in {:X}", x, y, x);
+                self.general_registers[x as usize] &= self.general_registers[y as usize];
+                if self.quirks.logic_resets_vf {
+                    self.general_registers[0xF] = 0;
+                }
+            }
+            Instruction::Xor { x, y } => {
+                // println!("Bitwise XOR on Registers {:X} and {:X} and store in {:X}", x, y, x);
+                self.general_registers[x as usize] ^= self.general_registers[y as usize];
+                if self.quirks.logic_resets_vf {
+                    self.general_registers[0xF] = 0;
+                }
+            }
+            Instruction::AddVxVy { x, y } => {
+                // IF value overflows then Register F is set to 1, else 0
+                let reg1 = self.general_registers[x as usize];
+                let reg2 = self.general_registers[y as usize];
+                let (sum, carry) = reg1.overflowing_add(reg2);
+                self.general_registers[x as usize] = sum;
+                self.general_registers[0xF] = carry as u8;
+                // println!("Add values of Registers {:X} and {:X} and store in {:X}", x, y, x);
+            }
+            Instruction::Sub { x, y } => {
+                // If Reg X >= Reg Y (no borrow) set Reg F to 1 else 0
+                let reg1 = self.general_registers[x as usize];
+                let reg2 = self.general_registers[y as usize];
+                self.general_registers[x as usize] = reg1.wrapping_sub(reg2);
+                self.general_registers[0xF] = (reg1 >= reg2) as u8;
+                // println!("Subtract the value of Register {:X} from {:X} and store in {:X}", y, x, x);
+            }
+            Instruction::Shr { x, y } => {
+                // If least significant bit of Reg X is 1 set Reg F to 1, else 0
+                // println!("Divide Register {:X} by 2", x);
+                let source = if self.quirks.shift_uses_vy { y } else { x };
+                let regx = self.general_registers[source as usize];
+                self.general_registers[x as usize] = regx / 2;
+                self.general_registers[0xF] = regx & 1;
+            }
+            Instruction::Subn { x, y } => {
+                let reg1 = self.general_registers[x as usize];
+                let reg2 = self.general_registers[y as usize];
+                self.general_registers[x as usize] = reg2.wrapping_sub(reg1);
+                self.general_registers[0xF] = (reg2 >= reg1) as u8;
+
+                // If Reg Y >= Reg X (no borrow) set Reg F to 1 else 0
+                // println!("Subtract the value of Register {:X} from {:X} and store in {:X}", x, y, x);
+            }
+            Instruction::Shl { x, y } => {
+                // If most significant bit of Reg X is 1 set Reg F to 1, else 0
+                let source = if self.quirks.shift_uses_vy { y } else { x };
+                let reg1 = self.general_registers[source as usize];
+                self.general_registers[x as usize] = reg1 << 1;
+                self.general_registers[0xF] = (reg1 & 0b10000000) >> 7;
+                // println!("Multiply register {:X} by 2", x)
+            }
+            Instruction::SneVxVy { x, y } => {
+                if self.general_registers[x as usize] != self.general_registers[y as usize] {
+                    next = self.skip_from(next);
+                }
+                // println!("Skip next instruction if Reg {:X} != Reg {:X}", x, y);
+            }
+            Instruction::LdI(address) => {
+                // println!("Set Reg I to {:X}", address);
+                self.memory_register = address;
+            }
+            Instruction::JpV0(address) => {
+                // BXNN reads the register from the top nibble of the address
+                let register = if self.quirks.jump_uses_vx { (address >> 8) as usize } else { 0 };
+                next = address as usize + self.general_registers[register] as usize;
+                // println!("Jump to location {:X} + Reg 0", address);
+            }
+            Instruction::Rnd { x, byte: k } => {
+                let random_byte = self.random.next_byte(&self.memory);
+                self.general_registers[x as usize] = random_byte & k;
+                // println!("Set Reg {:X} to random byte AND {:b}", x, k);
+            }
+            Instruction::Drw { x, y, n } => {
+                // Set VF = 1 if any pixel of the sprite was erased else 0
+                // Data XORed over screen data
+                // println!("Draw sprite of size {:X} stored in Reg I at coords Reg {:X}, Reg {:X}", n, x, y);
+                let x_pos = self.general_registers[x as usize] as usize;
+                let y_pos = self.general_registers[y as usize] as usize;
+                // DXY0 is a 16x16 sprite stored as two bytes per row
+                let (rows, sprite_width) = if n == 0 { (16, 16) } else { (n as usize, 8) };
+                let bytes_per_row = sprite_width / 8;
+                // XO-CHIP draws the sprite once per selected plane, each plane's
+                // data following on from the last in memory
+                let planes: Vec<usize> = self.display.selected_plane_indexes().collect();
+                let mut has_hidden = false;
+                for (plane_number, &plane) in planes.iter().enumerate() {
+                    let mut sprite = Vec::with_capacity(rows);
+                    for i in 0..rows {
+                        let address = self.memory_register as usize + (plane_number * rows + i) * bytes_per_row;
+                        let mut sprite_row = 0u16;
+                        for byte in 0..bytes_per_row {
+                            sprite_row = sprite_row << 8 | self.read_memory(pc, address + byte)? as u16;
+                        }
+                        sprite.push(sprite_row);
+                    }
+                    has_hidden |= self.display.draw_sprite(plane, x_pos, y_pos, &sprite, sprite_width, self.quirks.clip_sprites);
+                }
+                self.general_registers[0xF] = has_hidden as u8;
+            }
+            Instruction::Skp(x) => {
+                // println!("Skip instruction if key pressed with value of register {:X}", x);
+                let key_in = self.general_registers[x as usize];
+                self.keys_polled |= 1 << (key_in & 0xF);
+                if self.is_key_pressed(key_in) {
+                    next = self.skip_from(next);
+                }
+            }
+            Instruction::Sknp(x) => {
+                // println!("Skip instruction if key not pressed with value of register {:X}", x);
+                let key_in = self.general_registers[x as usize];
+                self.keys_polled |= 1 << (key_in & 0xF);
+                if !self.is_key_pressed(key_in) {
+                    next = self.skip_from(next);
+                }
+            }
+            Instruction::LongI => {
+                // The address is the word after F000, step over it too
+                self.memory_register = u16::from_be_bytes([self.read_memory(pc, next)?, self.read_memory(pc, next + 1)?]);
+                next += 2;
+            }
+            Instruction::Plane(mask) => {
+                self.display.select_planes(mask);
+            }
+            Instruction::Audio => {
+                // Load the 16 byte audio pattern from I
+                let address = self.memory_register as usize;
+                let mut pattern = [0; 16];
+                for (offset, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.read_memory(pc, address + offset)?;
+                }
+                self.audio_pattern = Some(pattern);
+            }
+            Instruction::LdVxDt(x) => {
+                // println!("Copy value of Delay Timer to Reg {:X}", x);
+                self.general_registers[x as usize] = self.delay_timer;
+            }
+            Instruction::LdVxK(x) => {
+                // println!("Wait for key press and store in Reg {:X}", x);
+                self.keys_polled = 0xFFFF;
+                match self.keypad.iter().rposition(|&pressed| pressed) {
+                    Some(key_pressed) => {
+                        self.general_registers[x as usize] = key_pressed as u8;
+                    }
+                    None => {
+                        self.program_counter = pc;
+                        return Ok(StepOutcome::WaitingForKey);
+                    }
+                }
+            }
+            Instruction::LdDtVx(x) => {
+                // println!("Set Delay timer to value of Reg {:X}", x)
+                self.delay_timer = self.general_registers[x as usize];
+            }
+            Instruction::LdStVx(x) => {
+                // println!("Set sound timer to value of Reg {:X}", x)
+                self.sound_timer = self.general_registers[x as usize];
+            }
+            Instruction::AddIVx(x) => {
+                // println!("Set I to I + Reg {:X}", x)
+                self.memory_register = self.memory_register.wrapping_add(self.general_registers[x as usize] as u16);
+            }
+            Instruction::LdFVx(x) => {
+                // println!("Set I to location of Sprite for digit in Reg {:X}", x);
+                let digit = (self.general_registers[x as usize] & 0xF) as usize;
+                self.memory_register = (SPRITE_START + 5 * digit) as u16;
+            }
+            Instruction::LdHfVx(x) => {
+                // Set I to location of the 8x10 sprite for the digit in Reg X
+                let digit = (self.general_registers[x as usize] & 0xF) as usize;
+                self.memory_register = (BIG_SPRITE_START + 10 * digit) as u16;
+            }
+            Instruction::LdBVx(x) => {
+                // The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at location in I, the tens digit at location I+1, and the ones digit at location I+2.
+                // println!("Store BCD representation of Reg {:X} at I, I+1, I+2", x)
+                let value = self.general_registers[x as usize];
+                let address = self.memory_register as usize;
+                self.write_memory(pc, address, value / 100)?;
+                self.write_memory(pc, address + 1, value / 10 % 10)?;
+                self.write_memory(pc, address + 2, value % 10)?;
+            }
+            Instruction::Pitch(x) => {
+                self.pitch = self.general_registers[x as usize];
+            }
+            Instruction::LdIVx(x) => {
+                // println!("Store registers 0 through Reg {:X} in memory starting at location I. ", x);
+                for i in 0..x+1 {
+                    let reg_value = self.general_registers[i as usize];
+                    self.write_memory(pc, self.memory_register as usize + i as usize, reg_value)?;
+                }
+                self.increment_index(x);
+            }
+            Instruction::LdVxI(x) => {
+                // println!("Load registers 0 through Reg {:X} from memory starting at location I. ", x);
+                for i in 0..x+1 {
+                    let memory_value = self.read_memory(pc, self.memory_register as usize + i as usize)?;
+                    self.general_registers[i as usize] = memory_value;
+                }
+                self.increment_index(x);
+            }
+            Instruction::LdRVx(x) => {
+                // Store registers 0 through Reg X in the RPL user flags
+                let count = x as usize + 1;
+                self.rpl_flags[..count].copy_from_slice(&self.general_registers[..count]);
+            }
+            Instruction::LdVxR(x) => {
+                // Load registers 0 through Reg X from the RPL user flags
+                let count = x as usize + 1;
+                self.general_registers[..count].copy_from_slice(&self.rpl_flags[..count]);
+            }
+        }
+
+        // Only fault once the next fetch is known to be off the end, so a jump in the last word is fine
+        if next >= self.memory_size() {
+            return Err(MachineFault::MemoryOutOfBounds { pc, address: next });
+        }
+        self.program_counter = next as u16;
+        Ok(StepOutcome::Executed(instruction))
+    }
+}
+
+// Registers X through Y for 5XY2 and 5XY3, counting down if Y is below X
+fn register_range(x: u8, y: u8) -> Box<dyn Iterator<Item = usize>> {
+    let (x, y) = (x as usize, y as usize);
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
@@ -1,215 +1,308 @@
+use std::collections::HashSet;
 use std::env;
-use std::fs;
-extern crate hex;
+use std::io;
+use std::path::Path;
+use std::process;
 extern crate piston_window;
 use piston_window::*;
 extern crate opengl_graphics;
 use opengl_graphics::{GlGraphics, OpenGL};
-#[macro_use]
-extern crate twelve_bit;
-use twelve_bit::u12::*;
-extern crate rand;
-use rand::Rng;
-use std::collections::HashMap;
-
-const MEMORY_OFFSET: u16 = 512;
-const SPRITE_START: usize = 0;
-const PROGRAM_START: usize = 512;
-const INSTRUCTIONS_PER_SECOND: u16 = 700;
-const MEMORY_SIZE: usize = 4096;
+
+use chip8::{
+    keymap::{axis_inputs, button_input, hat_inputs},
+    screen_ascii, AudioBackend, Debugger, FaultPolicy, FrameScheduler, GdbStub, Headless, KeyScript, Keymap, Machine, Movie,
+    NullAudio, Quirks, RandomKind, RewindBuffer, ToneSettings, WavAudio, DEFAULT_SEED, FRAME_SECONDS, LORES_WIDTH, SPRITES,
+    TIMER_HZ,
+};
+
+const INSTRUCTIONS_PER_SECOND: u32 = 700;
+const REWIND_SECONDS: u32 = 10;
+const GDB_PORT: u16 = 1234;
+// Ten seconds
+const HEADLESS_FRAMES: u64 = 600;
+// Hold to play the game backwards
+const REWIND_KEY: Key = Key::Backspace;
 const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
 const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
-
-const SCREEN_X: u8 = 64;
-const SCREEN_Y: usize = 32;
+// XO-CHIP colours for a pixel lit on plane 1, plane 2 and both planes
+const PLANE_COLOURS: [[f32; 4]; 3] = [WHITE, [1.0, 0.6, 0.1, 1.0], [0.5, 0.5, 0.5, 1.0]];
 
 const SHOW_GRID: bool = true;
-
-const SPRITES: [u8; 80] = [
-    // 0
-    0xF0, 0x90, 0x90, 0x90, 0xF0,
-    // 1
-    0x20, 0x60, 0x20, 0x20, 0x70,
-    // 2
-    0xF0, 0x10, 0xF0, 0x80, 0xF0,
-    // 3
-    0xF0, 0x10, 0xF0, 0x10, 0xF0,
-    // 4
-    0x90, 0x90, 0xF0, 0x10, 0x10,
-    // 5
-    0xF0, 0x80, 0xF0, 0x10, 0xF0,
-    // 6
-    0xF0, 0x80, 0xF0, 0x90, 0xF0,
-    // 7
-    0xF0, 0x10, 0x20, 0x40, 0x40,
-    // 8
-    0xF0, 0x90, 0xF0, 0x90, 0xF0,
-    // 9
-    0xF0, 0x90, 0xF0, 0x10, 0xF0,
-    // A
-    0xF0, 0x90, 0xF0, 0x90, 0x90,
-    // B
-    0xE0, 0x90, 0xE0, 0x90, 0xE0,
-    // C
-    0xF0, 0x80, 0x80, 0x80, 0xF0,
-    // D
-    0xE0, 0x90, 0x90, 0x90, 0xE0,
-    // E
-    0xF0, 0x80, 0xF0, 0x80, 0xF0,
-    // F
-    0xF0, 0x80, 0xF0, 0x80, 0x80,
-];
-
-/**
- * Memory
- *  8 byte per location
- *  must start memory at 512 bytes as this would contain the interpreter
- *  
- * Registers
- *  16 8 bit registers
- *  V0 to VF
- *  VF used as a flag for some instructions
- *  address register: 
- *      12 bits wide
- *  PC (starts at 64 (40 hex) )
- * 
- * Stack
- *  stores return addresses for subroutines
- * 
- * Timers
- *  count down at 60 times per second 
- *  Delay:
- *      used for timing events in video games
- *  Sound
- *      when nonzero a beep is made
- * 
- * Input
- *  input hex characters for input
- *  maybe remap to different keybaord characters
- * 
- * Graphics
- *  monochrome 64 x 32
- *  drawn with sprites (8 x 1 to 15)
- *  sprite pixels XORd wit corresponding screen pixels
- *  carry flag (VF) set to 1 if any screen pixels flipped from set to unset when sprite drawn otherwise 0
- *  STORING
- *      store all of pixels as binary for each row 
- *  WRITING
- *      XOR the data at location I With data starting at a position
- *      set VF to 1 if any pixels unset
- * 
- * Opcode understanding
- *  NNN = address location
- *  N or NN = value
- *  X or Y 
- *  I (MAR) 16 bit
- */
-
-//  TODO: need to store hex character bytes in the memory from 0x000 to 0x1FF
-// TODO: reference suggests memory should be 8 bit not u16 as I have done. Determine what is better?
-        // 8 bit would be better for storing font sprites and other things to maybe do this
-        // plus memory locations will be broke if not done so 
-
-fn handle_invalid_instruction(&instruction: &(u8, u8)) {
-    println!("Invalid instruction {:X},{:X}", instruction.0, instruction.1);
+// Size of a lores pixel on screen, hires pixels are half this
+const PIXEL_SIZE: f64 = 20.0;
+
+// The COSMAC VIP keypad as it is laid out on the on-screen keypad
+const KEYPAD_LAYOUT: [[u8; 4]; 4] = [[0x1, 0x2, 0x3, 0xC], [0x4, 0x5, 0x6, 0xD], [0x7, 0x8, 0x9, 0xE], [0xA, 0x0, 0xB, 0xF]];
+const KEY_SIZE: f64 = 70.0;
+const KEY_GAP: f64 = 10.0;
+// The keypad sits right of the display, with a gap the size of one between keys
+const KEYPAD_LEFT: f64 = PIXEL_SIZE * LORES_WIDTH as f64 + KEY_GAP;
+const KEYPAD_WIDTH: f64 = 4.0 * (KEY_SIZE + KEY_GAP) + KEY_GAP;
+const KEY_COLOUR: [f32; 4] = [0.25, 0.25, 0.25, 1.0];
+// A key the program checked this frame
+const POLLED_COLOUR: [f32; 4] = [1.0, 0.6, 0.1, 1.0];
+
+// The on-screen keypad key at a point in the window
+fn keypad_key_at(position: [f64; 2]) -> Option<u8> {
+    let column = (position[0] - KEYPAD_LEFT) / (KEY_SIZE + KEY_GAP);
+    let row = (position[1] - KEY_GAP) / (KEY_SIZE + KEY_GAP);
+    if column < 0.0 || row < 0.0 || column >= 4.0 || row >= 4.0 {
+        return None;
+    }
+    // The gaps between keys don't count
+    if column.fract() * (KEY_SIZE + KEY_GAP) > KEY_SIZE || row.fract() * (KEY_SIZE + KEY_GAP) > KEY_SIZE {
+        return None;
+    }
+    Some(KEYPAD_LAYOUT[row as usize][column as usize])
 }
 
-fn extract_address(&instruction: &(u8, u8)) -> u16 {
-    return ((instruction.0 & 0x0F) as u16) << 8 | instruction.1 as u16;
+// Name of a keyboard key in keymap files, the digit keys are just their digit
+fn key_name(key: Key) -> String {
+    match key {
+        Key::D0 => "0".to_string(),
+        Key::D1 => "1".to_string(),
+        Key::D2 => "2".to_string(),
+        Key::D3 => "3".to_string(),
+        Key::D4 => "4".to_string(),
+        Key::D5 => "5".to_string(),
+        Key::D6 => "6".to_string(),
+        Key::D7 => "7".to_string(),
+        Key::D8 => "8".to_string(),
+        Key::D9 => "9".to_string(),
+        _ => format!("{:?}", key).to_lowercase(),
+    }
 }
 
-// Returns {0: x, 1: kk}
-fn xkk(&instruction: &(u8, u8)) -> (u8, u8) {
-    return ((instruction.0 & 0x0F), instruction.1)
+// F1 to F4 save to slots 1 to 4, F5 to F8 load them back
+fn key_to_save_slot(key_in: Key) -> Option<(bool, u8)> {
+    match key_in {
+        Key::F1 => Some((true, 1)),
+        Key::F2 => Some((true, 2)),
+        Key::F3 => Some((true, 3)),
+        Key::F4 => Some((true, 4)),
+        Key::F5 => Some((false, 1)),
+        Key::F6 => Some((false, 2)),
+        Key::F7 => Some((false, 3)),
+        Key::F8 => Some((false, 4)),
+        _ => None,
+    }
 }
 
-// Returns {0: x, 1: y, 2: _}
-fn xy_(&instruction: &(u8, u8)) -> (u8, u8, u8) {
-    return (instruction.0 & 0x0F, (instruction.1 & 0xF0) >> 4 , instruction.1 & 0x0F)
+const USAGE: &str = "Usage: chip8 [run] [OPTIONS] ROM       ROM ending in .8o is compiled from Octo source first
+       chip8 debug [OPTIONS] ROM    step through ROM in a terminal debugger, type help there
+       chip8 gdb [OPTIONS] ROM      wait for gdb to attach with target remote :PORT
+
+Options:
+  --ips N                           instructions per second (default 700)
+  --quirks vip|chip48|schip|xochip  interpreter to behave like (default vip)
+  --seed N                          seed for CXNN random numbers (default a new one each run,
+                                    fixed for headless runs)
+  --rng seeded|vip                  random numbers from a seeded generator, or an approximation
+                                    of the COSMAC VIP's routine (default seeded)
+  --on-fault [KIND=]halt|log|ignore what to do when the program faults
+  --tone-hz HZ                      beeper frequency (default 440)
+  --volume V                        beeper volume from 0.0 to 1.0 (default 0.25)
+  --waveform square|triangle|sawtooth|sine
+  --wav PATH                        write the beeper to a WAV file instead of playing it
+  --mute                            no sound
+  --load-state PATH                 resume from a save state instead of the start of the ROM
+  --rewind-seconds N                how far back holding Backspace can go (default 10)
+  --port N                          port chip8 gdb listens on (default 1234)
+  --keypad                          show a 4x4 keypad beside the display to click, keys the program
+                                    checks light up
+  --keymap PATH                     keypad bindings, [default] and per ROM sections of lines like
+                                    5 = w up button2 (controllers: buttonN, hat-up, axisN- and axisN+)
+  --headless                        run without a window and print the final screen
+  --frames N                        frames a headless run lasts (default 600, 60 a second)
+  --keys PATH                       keypad script for a headless run, lines of FRAME press|release KEY
+  --dump-screen PATH                write the final screen as PBM (or PNG if PATH ends in .png)
+                                    and as text to PATH with .txt, or only as text if it ends in .txt
+  --dump-every K                    also write PATH-FRAME copies every K frames
+  --record PATH                     record the keypad to a movie file while playing in the window
+  --replay PATH                     replay a movie headless and check it ends where the recording did
+
+Keys: the keypad is on 1234/QWER/ASDF/ZXCV unless --keymap says otherwise,
+      a controller's D-pad and left stick press 2/4/6/8 and its first button 5
+      F1-F4 save to slots 1-4 (ROM.slotN.state next to the ROM), F5-F8 load them,
+      hold Backspace to rewind
+
+Fault kinds: stack-overflow, stack-underflow, invalid-opcode, memory";
+
+struct Options {
+    rom: String,
+    instructions_per_second: u32,
+    quirks: Quirks,
+    seed: Option<u64>,
+    rng: RandomKind,
+    fault_policy: FaultPolicy,
+    tone: ToneSettings,
+    wav: Option<String>,
+    mute: bool,
+    load_state: Option<String>,
+    rewind_seconds: u32,
+    gdb_port: u16,
+    keymap: Option<String>,
+    show_keypad: bool,
+    headless: bool,
+    frames: u64,
+    keys: Option<String>,
+    dump_screen: Option<String>,
+    dump_every: Option<u64>,
+    record: Option<String>,
+    replay: Option<String>,
 }
 
-fn xor(base: u64, add: u64) -> (u64, bool) {
-    let xored = base ^ add; 
-    let ored = base | add;
-    return (xored, xored != ored);
+// Parse the value following an option
+fn option_value<T: std::str::FromStr>(args: &mut std::slice::Iter<String>, name: &str) -> Result<T, String> {
+    let value = args.next().ok_or(format!("{} needs a value", name))?;
+    value.parse().map_err(|_| format!("invalid {} '{}'", name, value))
 }
 
-fn keyToEnum(charIn: u8) -> Key {
-    match charIn {
-        0x0 => return Key::D0,
-        0x1 => return Key::D1,
-        0x2 => return Key::D2,
-        0x3 => return Key::D3,
-        0x4 => return Key::D4,
-        0x5 => return Key::D5,
-        0x6 => return Key::D6,
-        0x7 => return Key::D7,
-        0x8 => return Key::D8,
-        0x9 => return Key::D9,
-        0xA => return Key::A,
-        0xB => return Key::B,
-        0xC => return Key::C,
-        0xD => return Key::D,
-        0xE => return Key::E,
-        0xF => return Key::F,
-        // basically null
-        _ => return Key::NumPad0,
-    };
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let mut rom = None;
+        let mut instructions_per_second = INSTRUCTIONS_PER_SECOND;
+        let mut quirks = Quirks::default();
+        let mut seed = None;
+        let mut rng = RandomKind::Seeded;
+        let mut fault_policy = FaultPolicy::default();
+        let mut tone = ToneSettings::default();
+        let mut wav = None;
+        let mut mute = false;
+        let mut load_state = None;
+        let mut rewind_seconds = REWIND_SECONDS;
+        let mut gdb_port = GDB_PORT;
+        let mut keymap = None;
+        let mut show_keypad = false;
+        let mut headless = false;
+        let mut frames = HEADLESS_FRAMES;
+        let mut keys = None;
+        let mut dump_screen = None;
+        let mut dump_every = None;
+        let mut record = None;
+        let mut replay = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--ips" => instructions_per_second = option_value(&mut args, arg)?,
+                "--on-fault" => {
+                    let setting = args.next().ok_or("--on-fault needs a value")?;
+                    fault_policy.apply(setting)?;
+                }
+                "--quirks" => {
+                    let value = args.next().ok_or("--quirks needs a value")?;
+                    quirks = value.parse()?;
+                }
+                "--seed" => seed = Some(option_value(&mut args, arg)?),
+                "--rng" => {
+                    let value = args.next().ok_or("--rng needs a value")?;
+                    rng = value.parse()?;
+                }
+                "--tone-hz" => tone.frequency = option_value(&mut args, arg)?,
+                "--volume" => tone.volume = option_value(&mut args, arg)?,
+                "--waveform" => {
+                    let value = args.next().ok_or("--waveform needs a value")?;
+                    tone.waveform = value.parse()?;
+                }
+                "--wav" => wav = Some(option_value(&mut args, arg)?),
+                "--mute" => mute = true,
+                "--load-state" => load_state = Some(option_value(&mut args, arg)?),
+                "--rewind-seconds" => rewind_seconds = option_value(&mut args, arg)?,
+                "--port" => gdb_port = option_value(&mut args, arg)?,
+                "--keypad" => show_keypad = true,
+                "--keymap" => keymap = Some(option_value(&mut args, arg)?),
+                "--headless" => headless = true,
+                "--frames" => frames = option_value(&mut args, arg)?,
+                "--keys" => keys = Some(option_value(&mut args, arg)?),
+                "--dump-screen" => dump_screen = Some(option_value(&mut args, arg)?),
+                "--dump-every" => dump_every = Some(option_value(&mut args, arg)?),
+                "--record" => record = Some(option_value(&mut args, arg)?),
+                "--replay" => replay = Some(option_value(&mut args, arg)?),
+                _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+                _ => rom = Some(arg.clone()),
+            }
+        }
+
+        Ok(Options {
+            rom: rom.ok_or("no ROM given")?,
+            instructions_per_second,
+            quirks,
+            seed,
+            rng,
+            fault_policy,
+            tone,
+            wav,
+            mute,
+            load_state,
+            rewind_seconds,
+            gdb_port,
+            keymap,
+            show_keypad,
+            headless,
+            frames,
+            keys,
+            dump_screen,
+            dump_every,
+            record,
+            replay,
+        })
+    }
 }
 
-fn keyToHex(keyIn: Key) -> u8 {
-    match keyIn {
-        Key::D0 => return 0x0,
-        Key::D1 => return 0x1,
-        Key::D2 => return 0x2,
-        Key::D3 => return 0x3,
-        Key::D4 => return 0x4,
-        Key::D5 => return 0x5,
-        Key::D6 => return 0x6,
-        Key::D7 => return 0x7,
-        Key::D8 => return 0x8,
-        Key::D9 => return 0x9,
-        Key::A => return 0xA,
-        Key::B => return 0xB,
-        Key::C => return 0xC,
-        Key::D => return 0xD,
-        Key::E => return 0xE,
-        Key::F => return 0xF,
-        _ => return 0x0,
-    };
+// Pick where the beeper goes from the options
+fn audio_backend(options: &Options) -> Result<Box<dyn AudioBackend>, String> {
+    if let Some(path) = &options.wav {
+        let wav = WavAudio::create(path, options.tone).map_err(|err| format!("Couldn't create {}: {}", path, err))?;
+        return Ok(Box::new(wav));
+    }
+    if options.mute {
+        return Ok(Box::new(NullAudio));
+    }
+
+    #[cfg(feature = "audio")]
+    match chip8::audio::CpalAudio::new(options.tone) {
+        Ok(audio) => return Ok(Box::new(audio)),
+        Err(err) => eprintln!("No sound: {}", err),
+    }
+
+    Ok(Box::new(NullAudio))
 }
 
-struct Chip8 {
-    // to change
-    memory: [u8; MEMORY_SIZE],
-    general_registers: [u8; 16],
-    address_register: U12,
-    // I register
-    memory_register: u16,
-    program_counter: u16,
-    stack_pointer: i8,
-    sound_timer: u32,
-    delay_timer: u32,
-    stack: [u16; 16],
-    display: [u64; SCREEN_Y],
+// Piston window frontend, owns a Machine and feeds it input and time
+struct Frontend {
+    machine: Machine,
+    scheduler: FrameScheduler,
+    fault_policy: FaultPolicy,
+    // Seconds of wall clock time not yet emulated
+    pending_time: f64,
+    // Set once a fault halts the machine, the window stays open to inspect it
+    halted: bool,
+    // Save slot N is written to this path with .slotN.state added
+    rom: String,
+    rewind: RewindBuffer,
+    // The rewind key is held, frames run backwards
+    rewinding: bool,
+    keymap: Keymap,
+    // Names of the inputs held down, a CHIP-8 key is down while any of its inputs is
+    held: HashSet<String>,
+    show_keypad: bool,
+    // Where the mouse is, and the on-screen key it is holding down
+    cursor: [f64; 2],
+    clicked: Option<u8>,
+    // The movie being recorded and the path it is saved to when the window closes
+    recording: Option<(Movie, String)>,
     window: PistonWindow,
     gl: GlGraphics,
     events: Events,
-    keysMap: HashMap<Key, bool>,
 }
 
-impl Chip8 {
-    fn new() -> Chip8 {
-        let mut memory_prepared = [0; 4096];
-
-        // Add sprites to memory (interpreter part)
-        for i in 0..SPRITES.len() {
-            memory_prepared[i+SPRITE_START] = SPRITES[i];
-        }
-
+impl Frontend {
+    fn new(machine: Machine, scheduler: FrameScheduler, fault_policy: FaultPolicy, rom: String, rewind: RewindBuffer, keymap: Keymap, show_keypad: bool) -> Frontend {
         // Graphics stuff
         let opengl = OpenGL::V3_2;
-        let window: PistonWindow = WindowSettings::new("shapes", [1300, 660])
+        let width = if show_keypad { KEYPAD_LEFT + KEYPAD_WIDTH } else { 1300.0 };
+        let window: PistonWindow = WindowSettings::new("shapes", [width, 660.0])
             .exit_on_esc(true)
             .graphics_api(opengl)
             .build()
@@ -217,37 +310,82 @@ impl Chip8 {
 
         let gl = GlGraphics::new(opengl);
         let mut event_settings = EventSettings::new();
-        event_settings.max_fps(1);
-        event_settings.set_ups(700);
+        event_settings.max_fps(TIMER_HZ as u64);
+        event_settings.set_ups(TIMER_HZ as u64);
         let events = Events::new(event_settings);
 
-        let mut keysMap: HashMap<Key, bool> = HashMap::new();
-
-        Chip8 {
-            memory: memory_prepared,
-            general_registers: [0; 16],
-            address_register: u12![0],
-            program_counter: PROGRAM_START as u16,
-            memory_register: 0,
-            sound_timer: 0,
-            delay_timer: 0,
-            stack_pointer: -1,
-            stack: [0; 16],
-            display: [0b0; SCREEN_Y],
+        Frontend {
+            machine,
+            scheduler,
+            fault_policy,
+            pending_time: 0.0,
+            halted: false,
+            rom,
+            rewind,
+            rewinding: false,
+            keymap,
+            held: HashSet::new(),
+            show_keypad,
+            cursor: [0.0; 2],
+            clicked: None,
+            recording: None,
             window,
             gl,
             events,
-            keysMap,
         }
     }
 
-    // Loads raw data from a file into the memory
-    fn load_from_file(&mut self, path: &str ) {
-        let program_bytes = fs::read(path)
-            .expect("Couldn't read file");
+    // An input went down or up, update the CHIP-8 key it is bound to
+    fn set_input(&mut self, input: &str, down: bool) {
+        let hex = match self.keymap.key(input) {
+            Some(hex) => hex,
+            None => return,
+        };
+        if down {
+            self.held.insert(input.to_string());
+        } else {
+            self.held.remove(input);
+        }
+        self.update_key(hex);
+    }
 
-        for i in 0..program_bytes.len() {
-            self.memory[PROGRAM_START + i] = program_bytes[i];
+    // A CHIP-8 key is down while any input bound to it is, or the mouse holds it on screen
+    fn update_key(&mut self, hex: u8) {
+        if self.clicked == Some(hex) || self.held.iter().any(|held| self.keymap.key(held) == Some(hex)) {
+            self.machine.press_key(hex);
+        } else {
+            self.machine.release_key(hex);
+        }
+    }
+
+    // The mouse button went down or up over the window
+    fn click(&mut self, down: bool) {
+        if let Some(hex) = self.clicked.take() {
+            self.update_key(hex);
+        }
+        if down && self.show_keypad {
+            self.clicked = keypad_key_at(self.cursor);
+            if let Some(hex) = self.clicked {
+                self.update_key(hex);
+            }
+        }
+    }
+
+    // The D-pad moved to a new position
+    fn set_hat(&mut self, state: HatState) {
+        let (up, down, left, right) = match state {
+            HatState::Centered => (false, false, false, false),
+            HatState::Up => (true, false, false, false),
+            HatState::Down => (false, true, false, false),
+            HatState::Left => (false, false, true, false),
+            HatState::Right => (false, false, false, true),
+            HatState::LeftUp => (true, false, true, false),
+            HatState::RightUp => (true, false, false, true),
+            HatState::LeftDown => (false, true, true, false),
+            HatState::RightDown => (false, true, false, true),
+        };
+        for (input, held) in hat_inputs(up, down, left, right) {
+            self.set_input(input, held);
         }
     }
 
@@ -257,416 +395,326 @@ impl Chip8 {
 
             // Key press handling
             if let Some(Button::Keyboard(key)) = e.press_args() {
-
-                self.keysMap.insert(key, true);
-
-                println!("Pressed keyboard key '{:?}'", key);
+                self.set_input(&key_name(key), true);
+                if let Some((save, slot)) = key_to_save_slot(key) {
+                    self.save_slot(save, slot);
+                }
+                if key == REWIND_KEY {
+                    if self.recording.is_some() {
+                        eprintln!("Can't rewind while recording a movie");
+                    } else {
+                        self.rewinding = true;
+                    }
+                }
             };
+            if let Some(position) = e.mouse_cursor_args() {
+                self.cursor = position;
+            }
+            if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
+                self.click(true);
+            }
+            if let Some(Button::Controller(button)) = e.press_args() {
+                self.set_input(&button_input(button.button), true);
+            }
+            if let Some(Button::Hat(hat)) = e.press_args() {
+                self.set_hat(hat.state);
+            }
+            if let Some(axis) = e.controller_axis_args() {
+                for (input, pushed) in axis_inputs(axis.axis, axis.position) {
+                    self.set_input(&input, pushed);
+                }
+            }
             if let Some(button) = e.release_args() {
                 match button {
                     Button::Keyboard(key) => {
-                        self.keysMap.remove(&key);
-                        println!("Released keyboard key '{:?}'", key)
-                    },
-                    Button::Mouse(button) => println!("Released mouse button '{:?}'", button),
-                    Button::Controller(button) => println!("Released controller button '{:?}'", button),
-                    Button::Hat(hat) => println!("Released controller hat `{:?}`", hat),
+                        self.set_input(&key_name(key), false);
+                        if key == REWIND_KEY {
+                            self.rewinding = false;
+                        }
+                    }
+                    Button::Mouse(MouseButton::Left) => self.click(false),
+                    Button::Mouse(_) => {}
+                    Button::Controller(button) => self.set_input(&button_input(button.button), false),
+                    Button::Hat(_) => self.set_hat(HatState::Centered),
                 }
             };
 
-            // Execute a cycle on each update
+            // Only render to the screen when wanted
             if let Some(args) = e.render_args() {
                 self.update_display(&args);
             }
-            
-            // Only render to the screen when wanted
+
+            // Run however many frames the elapsed time is worth
             if let Some(args) = e.update_args() {
-                self.execute_cycle(&args);
+                self.pending_time += args.dt;
+                while self.pending_time >= FRAME_SECONDS {
+                    self.pending_time -= FRAME_SECONDS;
+                    self.step_frame();
+                }
             }
         }
-    }
 
-    // Handle the next instruction
-    fn execute_cycle(&mut self, _args: &UpdateArgs) {
-        // Get instruction PC points to. They are split in two bytes
-        let instruction : (u8, u8) = ((self.memory[self.program_counter as usize]), self.memory[(self.program_counter+1) as usize]);
-
-        // increment to get next instruction next cycle
-        self.program_counter += 2;
-
-        // Hack for now, should exit instead
-        // Many programs have a loop when finished anyway or will exit
-        if self.program_counter >= MEMORY_SIZE as u16 {
-            self.program_counter = 0;
+        if let Some((movie, path)) = &mut self.recording {
+            movie.finish(&self.machine);
+            match movie.save(path) {
+                Ok(()) => println!("Saved movie of {} frames to {}", movie.frames, path),
+                Err(err) => eprintln!("Couldn't save movie {}: {}", path, err),
+            }
         }
+    }
 
-        match &instruction.0 >> 4 {
-            0x0 => {
-                match &instruction.1 {
-                    0xE0 => {
-                        // println!("Clear display");
-                        for i in 0..SCREEN_Y {
-                            self.display[i] = 0;
-                        }
-                    }
-                    0xEE => {
-                        // println!("Return from subroutine");
-                        self.program_counter = self.stack[self.stack_pointer as usize];
-                        self.stack_pointer = self.stack_pointer - 1;
-                    }
-                    _ => {
-                        handle_invalid_instruction(&instruction);
-                    }
-                }
-            }
-            0x1 => {
-                let data = extract_address(&instruction);
-                self.program_counter = data;
-                // println!("JUMP TO {:X}", data)
-            }
-            0x2 => {
-                let data = extract_address(&instruction);
-                self.stack_pointer += 1;
-                self.stack[self.stack_pointer as usize] = self.program_counter;
-                self.program_counter = data;
-                // println!("CALLING SUBROUTING AT {:X}", data)
+    fn step_frame(&mut self) {
+        if self.rewinding {
+            // The keys held now still count, not the ones held back then
+            let keypad = self.machine.keypad;
+            // Rewinding back past a fault gives the machine another go
+            if self.rewind.rewind(&mut self.machine) {
+                self.halted = false;
             }
-            0x3 => {
-                let (x, k) = xkk(&instruction);
-                if self.general_registers[x as usize] == k {
-                    self.program_counter += 2;
-                }
-                // println!("SKIP IF Register {:X} == {:X}", x, k);
-            }
-            0x4 => {
-                let (x, k) = xkk(&instruction);
-                if self.general_registers[x as usize] != k {
-                    self.program_counter += 2;
-                }
-                // println!("SKIP IF Register {:X} != {:X}", x, k);
-            }
-            0x5 => {
-                let (x, y, _) = xy_(&instruction);
-                if self.general_registers[x as usize] == self.general_registers[y as usize] {
-                    self.program_counter += 2;
-                }
-                // println!("SKIP IF Register {:X} == Register {:X}", x, y);
-            }
-            0x6 => {
-                let (x, k) = xkk(&instruction);
-                // println!("SET Register {:X} to {:X}", x, k);
-                self.general_registers[x as usize] = k;
-            }
-            0x7 => {
-                let (x, k) = xkk(&instruction);
-                // println!("SET Register {} to Register {} ({}) + {}",x, x, self.general_registers[x as usize], k);
-                self.general_registers[x as usize] = self.general_registers[x as usize].saturating_add(k);
-            }
-            0x8 => {
-                let (x, y, op) = xy_(&instruction);
-                match op {
-                    0x0 => {
-                        // println!("Copy value in Register {:X} to Register {:X}", x, y);
-                        self.general_registers[x as usize] = self.general_registers[y as usize];
-                    }
-                    0x1 => {
-                        // println!("Bitwise OR on Registers {:X} and {:X} and store in {:X}", x, y, x);
-                        self.general_registers[x as usize] = self.general_registers[y as usize] | self.general_registers[x as usize];
-                    }
-                    0x2 => {
-                        // println!("Bitwise AND on Registers {:X} and {:X} and store in {:X}", x, y, x);
-                        self.general_registers[x as usize] = self.general_registers[y as usize] & self.general_registers[x as usize];
-                    }
-                    0x3 => {
-                        // println!("Bitwise XOR on Registers {:X} and {:X} and store in {:X}", x, y, x);
-                        self.general_registers[x as usize] = self.general_registers[y as usize] ^ self.general_registers[x as usize];
-                    }
-                    0x4 => {
-                        // IF value overflows then Register F is set to 1, else 0
-                        let reg1 = self.general_registers[x as usize];
-                        let reg2 = self.general_registers[y as usize];
-                        if (reg1 as u16 + reg2 as u16) > 255 {
-                            self.general_registers[0xF] = 1;
-                        } else {
-                            self.general_registers[0xF] = 0;
-                        }
-
-                        self.general_registers[x as usize] = reg1.saturating_add(reg2);
-                        // println!("Add values of Registers {:X} and {:X} and store in {:X}", x, y, x);
-                    }
-                    0x5 => {
-                        // If Reg X > Reg Y set Reg F to 1 else 0
-                        let reg1 = self.general_registers[x as usize];
-                        let reg2 = self.general_registers[y as usize];
-                        if reg1 > reg2 {
-                            self.general_registers[0xF] = 1;
-                        } else {
-                            self.general_registers[0xF] = 0;
-                        }
+            self.machine.keypad = keypad;
+            return;
+        }
+        // After 00FD the window may still be catching up on frames before it closes
+        if self.halted || self.machine.exited {
+            return;
+        }
 
-                        self.general_registers[x as usize] = reg1.saturating_sub(reg2);
-                        // println!("Subtract the value of Register {:X} from {:X} and store in {:X}", y, x, x);
-                    }
-                    0x6 => {
-                        // If least significant bit of Reg X is 1 set Reg F to 1, else 0 
-                        // println!("Divide Register {:X} by 2", x);
-                        let regx = self.general_registers[x as usize];
-                        self.general_registers[0xF] = regx & 1;
-                        self.general_registers[x as usize] = regx / 2;
-                    }
-                    0x7 => {
-                        let reg1 = self.general_registers[x as usize];
-                        let reg2 = self.general_registers[y as usize];
-                        if reg1 > reg2 {
-                            self.general_registers[0xF] = 0;
-                        } else {
-                            self.general_registers[0xF] = 1;
-                        }
-                        
-                        self.general_registers[x as usize] = reg2.saturating_sub(reg1);
-                        
-                        // If Reg Y > Reg X set Reg F to 1 else 0
-                        // println!("Subtract the value of Register {:X} from {:X} and store in {:X}", x, y, x);
-                    }
-                    0xE => {
-                        // If most significant bit of Reg X is 1 set Reg F to 1, else 0 
-                        let reg1 = self.general_registers[x as usize];
-                        // let reg2 = self.general_registers[y as usize];
-                        self.general_registers[0xF] = (reg1 & 0b10000000) >> 7;
-                        self.general_registers[x as usize] = reg1 << 1;
-                        // println!("Multiply register {:X} by 2", x)
-                    }
-                    _ => {
-                        handle_invalid_instruction(&instruction)
-                    }
-                }                
-            }
-            0x9 => {
-                let (x, y, _) = xy_(&instruction);
-                if self.general_registers[x as usize] != self.general_registers[y as usize] {
-                    self.program_counter += 2;
-                }
-                // println!("Skip next instruction if Reg {:X} != Reg {:X}", x, y);
-            }
-            0xA => {
-                let address = extract_address(&instruction);
-                // println!("Set Reg I to {:X}", address);
-                self.memory_register = address;
-            }
-            0xB => {
-                let address = extract_address(&instruction);
-                self.program_counter = address + self.general_registers[0] as u16
-                // println!("Jump to location {:X} + Reg 0", address);
-            }
-            0xC => {
-                let (x, k) = xkk(&instruction);
-                let random_byte: u8 = rand::thread_rng().gen::<u8>();
-                self.general_registers[x as usize] = random_byte & k;
-                // println!("Set Reg {:X} to random byte AND {:b}", x, k);
-            }
-            0xD => {
-                // TODO this will be refactored and cleaned
-                // CURRENTLY CUTS OFF DATA
-                // Set VF = 1 if a pixel erased else 0
-                // Data XORed over screen data
-                // Wraps around of coordinates outside of screen 
-                let (x, y, n) = xy_(&instruction);
-                // println!("Draw sprite of size {:X} stored in Reg I at coords Reg {:X}, Reg {:X}", n, x, y);
-                let x_pos = self.general_registers[x as usize];
-                let y_pos = self.general_registers[y as usize];
-                println!("Draw sprite of size {:X} stored in Reg I at coords  {},  {}", n, self.general_registers[x as usize], self.general_registers[y as usize]);
-                /*
-                get n rows from memory starting at I position
-                draw these over current screen from position (Reg x), (Reg y) XOR
-                if part out side of screen wrap round
-                */
-                for i in 0..n {
-                    let sprite_byte = self.memory[(self.memory_register as usize) + (i as usize)];
-                    println!("sprite row {:#b}", sprite_byte);
-                    // TODO should wrap around if larger then SCREEN_Y
-                    let y_offset = y_pos + i;
-                    let current_row_data = self.display[y_offset as usize];
-                    
-                    // determine what bytes will wrap round 
-                    println!("{} away from end", SCREEN_X-1 - x_pos);
-                    let to_end = SCREEN_X-1 - x_pos;
-                    if to_end < 8 {
-                        // println!("needs wrap of {:X} bits while {:X} not", 8 - to_end,  );
-                        let nowrap = (sprite_byte as u64) >> (8 - to_end) ;
-                        // let wrap = (current_row_data << to_end) >> to_end;
-                        let wrap = (sprite_byte as u64) << (SCREEN_X-1 - to_end);
-
-                        println!("{:#b}", nowrap);
-                        println!("data: {:#b} size: {:X}", wrap, 8- to_end);
-
-                        // xor from end for no wrap and start for wrap
-                        let (temp_result, new_hidden) = xor(current_row_data, nowrap);
-                        let (result, new_hidden2) = xor(temp_result, wrap);
-                        if new_hidden | new_hidden2 {
-                            self.general_registers[0xF] = 1;
-                        } else {
-                            self.general_registers[0xF] = 0;
-                        }
-                        self.display[y_offset as usize] = result;
-                    } else {
-                        println!("{:#b} previous row", current_row_data);
-                        let positioned_byte = (sprite_byte as u64 )<< (to_end - 8);
-                        let (result, has_hidden) = xor(current_row_data, positioned_byte);
-                        println!("{:#b} result of XOR", result);
-                        self.general_registers[0xF] = has_hidden as u8;
-                        self.display[y_offset as usize] = result;
-                    }
-                }
+        if let Some((movie, _)) = &mut self.recording {
+            movie.record_frame(&self.machine.keypad);
+        }
+        let instructions = self.scheduler.next_frame();
+        if let Err(fault) = self.machine.run_frame(instructions, &self.fault_policy) {
+            eprintln!("Halted: {}", fault);
+            self.halted = true;
+        }
+        self.rewind.push(&self.machine);
+        // 00FD closes the interpreter
+        if self.machine.exited {
+            self.window.set_should_close(true);
+        }
+    }
 
+    // Save to or load from a numbered slot
+    fn save_slot(&mut self, save: bool, slot: u8) {
+        let path = format!("{}.slot{}.state", self.rom, slot);
+        if save {
+            match self.machine.save_state_file(&path) {
+                Ok(()) => println!("Saved slot {} to {}", slot, path),
+                Err(err) => eprintln!("Couldn't save {}: {}", path, err),
             }
-            0xE => {
-                let (x, k) = xkk(&instruction);
-                match k {
-                    0xA1 => {
-                        // println!("Skip instruction if key not pressed with value of register {:X}", x);
-                        let keyIn = self.general_registers[x as usize];
-                        // convert to key enum
-                        if !self.isKeyPressed(keyToEnum(keyIn)) {
-                            self.skipNext()
-                        }
-                        
-                    }
-                    0x9E => {
-                        // println!("Skip instruction if key pressed with value of register {:X}", x);
-                        let keyIn = self.general_registers[x as usize];
-                        // convert to key enum
-                        if self.isKeyPressed(keyToEnum(keyIn)) {
-                            self.skipNext()
-                        }
-                    }
-                    _ => {
-                        handle_invalid_instruction(&instruction);
-                    }
-                }
-            }
-            0xF => {
-                let (x, k) = xkk(&instruction);
-                match k {
-                    0x07 => {
-                        println!("Copy value of Delay Timer to Reg {:X}", x);
-                    }
-                    0x0A => {
-                        println!("Wait for key press and store in Reg {:X}", x);
-                        let mut has_key_pressed = false;
-                        let mut key_pressed: Key = Key::Z;
-                        for (key, value) in &self.keysMap {
-                            if *value == true {
-                                has_key_pressed = true;
-                                key_pressed = *key;
-                            }
-                        }
-                        if has_key_pressed {
-                            self.general_registers[x as usize] = keyToHex(key_pressed);
-                        } else {
-                            self.program_counter -= 2;
-                        }
-                    }
-                    0x15 => {
-                        println!("Set Delay timer to value of Reg {:X}", x)
-                    }
-                    0x18 => {
-                        println!("Set sound timer to value of Reg {:X}", x)
-                    }
-                    0x1E => {
-                        // println!("Set I to I + Reg {:X}", x)
-                        self.memory_register = self.memory_register + self.general_registers[x as usize] as u16;
-                    }
-                    0x29 => {
-                        // Is it value in Reg X or value X?? 
-                        println!("Getting sprite {:?}", x);
-                        // println!("Set I to location of Sprite for digit in Reg {:X}", x);
-                        self.memory_register = (SPRITE_START + ( 5 * (x-1)) as usize) as u16;
-                    }
-                    0x33 => {
-                        // The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at location in I, the tens digit at location I+1, and the ones digit at location I+2.
-                        println!("Store BCD representation of Reg {:X} at I, I+1, I+2", x)
-                        
-                    }
-                    0x55 => {
-                        println!("Store registers 0 through Reg {:X} in memory starting at location I. ", x);
-                        for i in 0..x+1 {
-                            let reg_value = self.general_registers[i as usize];
-                            self.memory[self.memory_register as usize] = reg_value;
-                        }
-                    }
-                    0x65 => {
-                        println!("Load registers 0 through Reg {:X} from memory starting at location I. ", x);
-                        for i in 0..x+1 {
-                            let memory_value = self.memory[self.memory_register as usize];
-                            self.general_registers[i as usize] = memory_value;
-                        }
-                    }
-                    _ => {
-                        handle_invalid_instruction(&instruction);
-                    }
+        } else if self.recording.is_some() {
+            eprintln!("Can't load a state while recording a movie");
+        } else {
+            match self.machine.load_state_file(&path) {
+                Ok(()) => {
+                    // A fault that halted the old state doesn't apply to the loaded one
+                    self.halted = false;
+                    println!("Loaded slot {} from {}", slot, path);
                 }
-            }
-            _ => {
-                handle_invalid_instruction(&instruction)
+                Err(err) => eprintln!("Couldn't load {}: {}", path, err),
             }
         }
     }
 
     // This is called when the screen needs updating
     fn update_display(&mut self, args: &RenderArgs) {
+        let display = &self.machine.display;
+        let pixel_size = PIXEL_SIZE * LORES_WIDTH as f64 / display.width() as f64;
+        let show_keypad = self.show_keypad;
+        let keypad = self.machine.keypad;
+        let keys_polled = self.machine.keys_polled;
         self.gl.draw(args.viewport(), |c, gl| {
             clear([0.0; 4], gl);
-            
-            for y_offset in 0..SCREEN_Y {
-                let row = self.display[y_offset];
-                // println!("{:#b}", row);
-                for x_offset in 0..SCREEN_X {
-                    let offset = ((SCREEN_X-1)-x_offset) as u32;
-
-                    let mut converted = row & 2_u64.pow(offset);
-                    if converted != 0 {
-                        converted = converted >> offset;
-                    }
 
-                    let c = c.trans((x_offset) as f64 * 20.0, y_offset as f64 * 20.0);
-                    let white = [1.0, 1.0, 1.0, 1.0];
-                    let black = [0.0, 0.0, 0.0, 1.0];
-                    let rect = math::margin_rectangle([20.0; 4], 1.0);
-                    let mut colour = black;
-                    if converted == 0b1 {
-                        colour = white;
-                        rectangle(colour, rect, c.transform, gl);
+            for y_offset in 0..display.height() {
+                for x_offset in 0..display.width() {
+                    let colour = display.colour(x_offset, y_offset);
+
+                    let c = c.trans(x_offset as f64 * pixel_size, y_offset as f64 * pixel_size);
+                    let rect = math::margin_rectangle([pixel_size; 4], pixel_size / 20.0);
+                    if colour != 0 {
+                        rectangle(PLANE_COLOURS[colour as usize - 1], rect, c.transform, gl);
                     } else if SHOW_GRID {
-                        rectangle(colour, rect, c.transform, gl);
+                        rectangle(BLACK, rect, c.transform, gl);
                         let border_tickness = 0.5;
-                        Rectangle::new_border(white, border_tickness).draw(rect, &c.draw_state, c.transform, gl);
+                        Rectangle::new_border(WHITE, border_tickness).draw(rect, &c.draw_state, c.transform, gl);
                     }
                 }
             }
-        });
-    }
 
-    fn isKeyPressed(&mut self, key: Key) -> bool {
-        match self.keysMap.get(&key) {
-            Some(&value) => {
-                return value;
+            if show_keypad {
+                for (row, keys) in KEYPAD_LAYOUT.iter().enumerate() {
+                    for (column, &key) in keys.iter().enumerate() {
+                        let c = c.trans(KEYPAD_LEFT + column as f64 * (KEY_SIZE + KEY_GAP), KEY_GAP + row as f64 * (KEY_SIZE + KEY_GAP));
+                        let (background, foreground) = if keypad[key as usize] {
+                            (WHITE, BLACK)
+                        } else if keys_polled >> key & 1 == 1 {
+                            (POLLED_COLOUR, BLACK)
+                        } else {
+                            (KEY_COLOUR, WHITE)
+                        };
+                        rectangle(background, [0.0, 0.0, KEY_SIZE, KEY_SIZE], c.transform, gl);
+
+                        // The label is the interpreter's own 4x5 font sprite for the digit
+                        let dot = KEY_SIZE / 10.0;
+                        let c = c.trans((KEY_SIZE - 4.0 * dot) / 2.0, (KEY_SIZE - 5.0 * dot) / 2.0);
+                        let glyph = &SPRITES[key as usize * 5..key as usize * 5 + 5];
+                        for (y, bits) in glyph.iter().enumerate() {
+                            for x in 0..4 {
+                                if bits >> (7 - x) & 1 == 1 {
+                                    rectangle(foreground, [x as f64 * dot, y as f64 * dot, dot, dot], c.transform, gl);
+                                }
+                            }
+                        }
+                    }
+                }
             }
-            None => return false
-        }
+        });
     }
+}
 
-    fn skipNext(&mut self) {
-        self.program_counter += 2;
+// Run the frames asked for, then print the screen unless it was dumped to a file
+fn run_headless(machine: Machine, scheduler: FrameScheduler, options: &Options) {
+    let keys = match &options.keys {
+        Some(path) => KeyScript::from_file(path).unwrap_or_else(|err| {
+            eprintln!("Couldn't load keys {}: {}", path, err);
+            process::exit(1);
+        }),
+        None => KeyScript::default(),
+    };
+
+    let mut headless = Headless::new(machine, scheduler, options.fault_policy, keys);
+    let result = headless.run(options.frames, options.dump_screen.as_deref(), options.dump_every);
+    if options.dump_screen.is_none() {
+        print!("{}", screen_ascii(&headless.machine.display));
+    }
+    if let Err(err) = result {
+        eprintln!("Stopped at frame {}: {}", headless.frame(), err);
+        process::exit(1);
     }
+}
 
+// Replay a movie, then check the machine ended up as it did when recorded
+fn run_replay(machine: Machine, path: &str, options: &Options) {
+    let movie = Movie::load(path).unwrap_or_else(|err| {
+        eprintln!("Couldn't load movie {}: {}", path, err);
+        process::exit(1);
+    });
+    let mut headless = movie.headless(machine, options.fault_policy).unwrap_or_else(|err| {
+        eprintln!("Can't replay {}: {}", path, err);
+        process::exit(1);
+    });
+
+    // A fault may well be what the movie recorded, so the hash still decides
+    if let Err(err) = headless.run(movie.frames, options.dump_screen.as_deref(), options.dump_every) {
+        eprintln!("Stopped at frame {}: {}", headless.frame(), err);
+    }
+    if options.dump_screen.is_none() {
+        print!("{}", screen_ascii(&headless.machine.display));
+    }
+    match movie.verify(&headless.machine) {
+        Ok(()) => println!("Replay of {} frames matches the recording", headless.frame()),
+        Err(err) => {
+            eprintln!("Replay differs from the recording: {}", err);
+            process::exit(1);
+        }
+    }
 }
 
 fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // Subcommands running the machine without the window
+    let subcommand = match args.first().map(String::as_str) {
+        Some(name @ "debug") | Some(name @ "gdb") => Some(name.to_string()),
+        _ => None,
+    };
+    if subcommand.is_some() || args.first().map(String::as_str) == Some("run") {
+        args.remove(0);
+    }
+    let mut options = Options::parse(&args).unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        process::exit(2);
+    });
+
+    if options.record.is_some() && (subcommand.is_some() || options.headless || options.replay.is_some()) {
+        eprintln!("--record needs the window\n{}", USAGE);
+        process::exit(2);
+    }
+    // Movies start from the beginning of the ROM
+    if options.load_state.is_some() && (options.record.is_some() || options.replay.is_some()) {
+        eprintln!("--load-state can't be used with --record or --replay\n{}", USAGE);
+        process::exit(2);
+    }
 
-    let mut prog = Chip8::new();
-    let args: Vec<String> = env::args().collect();
-    let program_location = &args[1];
-    prog.load_from_file(program_location);
-    prog.run();
+    let mut machine = Machine::with_quirks(options.quirks);
+    // Headless runs are for comparing, so they repeat unless told otherwise
+    let seed = match options.seed {
+        Some(seed) => seed,
+        None if options.headless => DEFAULT_SEED,
+        None => rand::random(),
+    };
+    machine.set_random(options.rng.source(seed));
+    if let Err(err) = machine.load_from_file(&options.rom) {
+        eprintln!("Couldn't load {}: {}", options.rom, err);
+        process::exit(1);
+    }
+    if let Some(path) = &options.load_state {
+        if let Err(err) = machine.load_state_file(path) {
+            eprintln!("Couldn't load state {}: {}", path, err);
+            process::exit(1);
+        }
+    }
 
-}
\ No newline at end of file
+    // A beep would drone on whenever the debugger stops
+    if subcommand.is_some() || options.headless || options.replay.is_some() {
+        options.mute = true;
+    }
+    match audio_backend(&options) {
+        Ok(audio) => {
+            machine.set_audio(audio);
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+
+    if let Some(path) = &options.replay {
+        run_replay(machine, path, &options);
+        return;
+    }
+    if let Some(subcommand) = subcommand {
+        let mut debugger = Debugger::new(machine, options.instructions_per_second);
+        let result = if subcommand == "gdb" {
+            GdbStub::new(debugger).listen(options.gdb_port)
+        } else {
+            debugger.repl(io::stdin().lock(), io::stdout())
+        };
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let scheduler = FrameScheduler::new(options.instructions_per_second);
+    if options.headless {
+        run_headless(machine, scheduler, &options);
+        return;
+    }
+    let rewind = RewindBuffer::new((options.rewind_seconds * TIMER_HZ) as usize);
+    let rom_name = Path::new(&options.rom).file_name().map(|name| name.to_string_lossy().into_owned());
+    let keymap = match &options.keymap {
+        Some(path) => Keymap::load(path, rom_name.as_deref()).unwrap_or_else(|err| {
+            eprintln!("Couldn't load keymap {}: {}", path, err);
+            process::exit(1);
+        }),
+        None => Keymap::cosmac(),
+    };
+    let mut frontend = Frontend::new(machine, scheduler, options.fault_policy, options.rom, rewind, keymap, options.show_keypad);
+    if let Some(path) = options.record {
+        let movie = Movie::new(&frontend.machine, options.rng, seed, options.instructions_per_second);
+        frontend.recording = Some((movie, path));
+    }
+    frontend.run();
+}
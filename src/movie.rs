@@ -0,0 +1,183 @@
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+
+use crate::fault::FaultPolicy;
+use crate::headless::{parse_event, Headless, KeyScript};
+use crate::machine::Machine;
+use crate::quirks::Quirks;
+use crate::random::RandomKind;
+use crate::state::MachineState;
+use crate::timers::FrameScheduler;
+
+// First line of every movie file
+const MOVIE_HEADER: &str = "# chip8 movie";
+
+// 64 bit FNV-1a, small and stable across versions and platforms
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3))
+}
+
+/// Hash of everything a save state holds, equal for machines in the same state.
+pub fn state_hash(machine: &Machine) -> u64 {
+    fnv1a(&MachineState::capture(machine).to_bytes())
+}
+
+/// A recorded session: everything needed to play it again exactly.
+///
+/// Keypad changes are kept with the frame they happened before, as in a
+/// `KeyScript`, along with what else decides how a run goes: the random
+/// source and seed, the quirks preset and the instruction rate. The hash of
+/// the loaded ROM catches replays against the wrong program, and the hash of
+/// the final state shows whether a replay ended up where the recording did.
+///
+/// The file is text, header lines like `seed 42` followed by the keypad
+/// changes as `FRAME press|release KEY` lines. Faults are handled by whatever
+/// `--on-fault` the replay is given, so give it the one the recording had.
+#[derive(Debug, Clone)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub rng: RandomKind,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub instructions_per_second: u32,
+    // Frames run while recording
+    pub frames: u64,
+    pub final_hash: u64,
+    // (frame, key, pressed) in the order they happened
+    events: Vec<(u64, u8, bool)>,
+    // Keypad as of the last frame recorded
+    keypad: [bool; 16],
+}
+
+impl Movie {
+    /// Start recording a machine that has its ROM loaded and has not run yet.
+    pub fn new(machine: &Machine, rng: RandomKind, seed: u64, instructions_per_second: u32) -> Movie {
+        Movie {
+            rom_hash: fnv1a(&machine.memory),
+            rng,
+            seed,
+            quirks: machine.quirks,
+            instructions_per_second,
+            frames: 0,
+            final_hash: state_hash(machine),
+            events: Vec::new(),
+            keypad: [false; 16],
+        }
+    }
+
+    // Call before each frame runs, with the keypad the frame runs with
+    pub fn record_frame(&mut self, keypad: &[bool; 16]) {
+        for (key, (&pressed, &was)) in keypad.iter().zip(self.keypad.iter()).enumerate() {
+            if pressed != was {
+                self.events.push((self.frames, key as u8, pressed));
+            }
+        }
+        self.keypad = *keypad;
+        self.frames += 1;
+    }
+
+    // Call once recording stops, with the machine as it ended up
+    pub fn finish(&mut self, machine: &Machine) {
+        // Keys that changed after the last frame never reached the program
+        let mut state = MachineState::capture(machine);
+        state.keypad = self.keypad;
+        self.final_hash = fnv1a(&state.to_bytes());
+    }
+
+    pub fn events(&self) -> &[(u64, u8, bool)] {
+        &self.events
+    }
+
+    /// A headless run that replays the movie when run for its `frames`.
+    ///
+    /// `machine` must have the ROM loaded and nothing run yet. Its quirks and
+    /// random source are replaced with the movie's.
+    pub fn headless(&self, mut machine: Machine, fault_policy: FaultPolicy) -> Result<Headless, String> {
+        if fnv1a(&machine.memory) != self.rom_hash {
+            return Err("the movie was recorded with a different ROM".to_string());
+        }
+        machine.quirks = self.quirks;
+        machine.set_random(self.rng.source(self.seed));
+        let scheduler = FrameScheduler::new(self.instructions_per_second);
+        Ok(Headless::new(machine, scheduler, fault_policy, KeyScript::from_events(self.events.clone())))
+    }
+
+    // Check a replay finished in the state the recording did
+    pub fn verify(&self, machine: &Machine) -> Result<(), String> {
+        let hash = state_hash(machine);
+        if hash != self.final_hash {
+            return Err(format!("final state hash {:016x} does not match the recorded {:016x}", hash, self.final_hash));
+        }
+        Ok(())
+    }
+
+    pub fn to_text(&self) -> Result<String, String> {
+        let quirks = self.quirks.preset_name().ok_or("only quirks presets can be recorded")?;
+        let mut text = format!("{}\n", MOVIE_HEADER);
+        let _ = writeln!(text, "rom {:016x}", self.rom_hash);
+        let _ = writeln!(text, "rng {}", self.rng.name());
+        let _ = writeln!(text, "seed {}", self.seed);
+        let _ = writeln!(text, "quirks {}", quirks);
+        let _ = writeln!(text, "ips {}", self.instructions_per_second);
+        let _ = writeln!(text, "frames {}", self.frames);
+        let _ = writeln!(text, "hash {:016x}", self.final_hash);
+        for &(frame, key, pressed) in self.events.iter() {
+            let _ = writeln!(text, "{} {} {:X}", frame, if pressed { "press" } else { "release" }, key);
+        }
+        Ok(text)
+    }
+
+    pub fn parse(text: &str) -> Result<Movie, String> {
+        if text.lines().next() != Some(MOVIE_HEADER) {
+            return Err("not a movie file".to_string());
+        }
+
+        let mut rom_hash = None;
+        let mut rng = None;
+        let mut seed = None;
+        let mut quirks = None;
+        let mut instructions_per_second = None;
+        let mut frames = None;
+        let mut final_hash = None;
+        let mut events = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let error = |err: String| format!("line {}: {}", index + 1, err);
+            match words[..] {
+                [] => {}
+                ["rom", value] => rom_hash = Some(u64::from_str_radix(value, 16).map_err(|_| error(format!("invalid hash '{}'", value)))?),
+                ["rng", value] => rng = Some(value.parse().map_err(error)?),
+                ["seed", value] => seed = Some(value.parse().map_err(|_| error(format!("invalid seed '{}'", value)))?),
+                ["quirks", value] => quirks = Some(value.parse().map_err(error)?),
+                ["ips", value] => instructions_per_second = Some(value.parse().map_err(|_| error(format!("invalid ips '{}'", value)))?),
+                ["frames", value] => frames = Some(value.parse().map_err(|_| error(format!("invalid frames '{}'", value)))?),
+                ["hash", value] => final_hash = Some(u64::from_str_radix(value, 16).map_err(|_| error(format!("invalid hash '{}'", value)))?),
+                _ => events.push(parse_event(&words).map_err(error)?),
+            }
+        }
+
+        let missing = |name: &str| format!("movie has no {} line", name);
+        Ok(Movie {
+            rom_hash: rom_hash.ok_or_else(|| missing("rom"))?,
+            rng: rng.ok_or_else(|| missing("rng"))?,
+            seed: seed.ok_or_else(|| missing("seed"))?,
+            quirks: quirks.ok_or_else(|| missing("quirks"))?,
+            instructions_per_second: instructions_per_second.ok_or_else(|| missing("ips"))?,
+            frames: frames.ok_or_else(|| missing("frames"))?,
+            final_hash: final_hash.ok_or_else(|| missing("hash"))?,
+            events,
+            keypad: [false; 16],
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_text()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Movie, Box<dyn Error>> {
+        Ok(Movie::parse(&fs::read_to_string(path)?)?)
+    }
+}
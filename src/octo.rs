@@ -0,0 +1,761 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::assembler::AsmError;
+use crate::{MEMORY_SIZE, PROGRAM_START};
+
+// A macro that expands itself forever is caught after this many expansions
+const MAX_MACRO_EXPANSIONS: usize = 100_000;
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+// Octo tokens are separated by whitespace, braces stand alone and # starts a comment
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if c == '#' {
+                break;
+            }
+            if c.is_whitespace() {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            if c == '{' || c == '}' {
+                i += 1;
+            } else {
+                while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '{' && chars[i] != '}' && chars[i] != '#' {
+                    i += 1;
+                }
+            }
+            tokens.push(Token {
+                text: chars[start..i].iter().collect(),
+                line: index + 1,
+                column: start + 1,
+            });
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+// Where a label's address goes once it is known
+#[derive(Debug, Clone, Copy)]
+enum Fixup {
+    // The low 12 bits of the opcode at this address
+    Address(usize),
+    // The 16 bit word after F000
+    Long(usize),
+    // The byte at this address, high nibble kept, for :unpack
+    UnpackHigh(usize),
+    UnpackLow(usize),
+}
+
+// Open loop/again and if/begin/else/end blocks
+enum Block {
+    // Start address and the jumps out of the loop made by while
+    Loop(usize, Vec<usize>),
+    // The jump to patch at else or end
+    If(usize),
+    Else(usize),
+}
+
+// A test for if and while, compiled to the instructions that skip when it is false or true
+struct Condition {
+    // vf work for the comparisons CHIP-8 has no instruction for
+    setup: Vec<u16>,
+    skip_when_false: u16,
+    skip_when_true: u16,
+}
+
+struct Compiler {
+    file: String,
+    tokens: Vec<Token>,
+    position: usize,
+    image: Vec<u8>,
+    address: usize,
+    end: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, (Vec<String>, Vec<Token>)>,
+    expansions: usize,
+    // Uses of labels not defined yet
+    fixups: Vec<(Token, Fixup)>,
+    blocks: Vec<(Token, Block)>,
+}
+
+impl Compiler {
+    fn error(&self, token: &Token, message: String) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: token.line,
+            column: token.column,
+            message,
+        }
+    }
+
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.get(self.position) {
+            Some(token) => {
+                self.position += 1;
+                Ok(token.clone())
+            }
+            None => {
+                let last = self.tokens.last().cloned().unwrap_or(Token { text: String::new(), line: 1, column: 1 });
+                Err(self.error(&last, format!("unexpected end of file after '{}'", last.text)))
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(self.error(&token, format!("expected '{}', found '{}'", text, token.text)));
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, token: &Token, bytes: &[u8]) -> Result<(), AsmError> {
+        if self.address + bytes.len() > MEMORY_SIZE {
+            return Err(self.error(token, format!("past the end of memory at 0x{:X}", MEMORY_SIZE)));
+        }
+        self.image[self.address..self.address + bytes.len()].copy_from_slice(bytes);
+        self.address += bytes.len();
+        self.end = self.end.max(self.address);
+        Ok(())
+    }
+
+    fn emit_opcode(&mut self, token: &Token, opcode: u16) -> Result<(), AsmError> {
+        self.emit(token, &opcode.to_be_bytes())
+    }
+
+    fn register(&self, token: &Token) -> Option<u8> {
+        if let Some(register) = self.aliases.get(&token.text) {
+            return Some(*register);
+        }
+        let text = token.text.to_ascii_lowercase();
+        if text.len() == 2 && text.starts_with('v') {
+            return u8::from_str_radix(&text[1..], 16).ok();
+        }
+        None
+    }
+
+    fn expect_register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        self.register(&token).ok_or_else(|| self.error(&token, format!("expected a register, found '{}'", token.text)))
+    }
+
+    // A number, constant or label that is already defined
+    fn known_value(&self, token: &Token) -> Option<i64> {
+        parse_number(&token.text)
+            .or_else(|| self.constants.get(&token.text).copied())
+            .or_else(|| self.labels.get(&token.text).map(|&address| address as i64))
+    }
+
+    fn value_in(&mut self, min: i64, max: i64) -> Result<i64, AsmError> {
+        let token = self.next()?;
+        let value = self.known_value(&token).ok_or_else(|| self.error(&token, format!("'{}' is not a number or defined name", token.text)))?;
+        if value < min || value > max {
+            return Err(self.error(&token, format!("{} is out of range {} to {}", value, min, max)));
+        }
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        Ok(self.value_in(-128, 255)? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        Ok(self.value_in(0, 15)? as u8)
+    }
+
+    // An address operand, patched later if it is a label defined further on
+    fn address_operand(&mut self, high: u16) -> Result<(), AsmError> {
+        let token = self.next()?;
+        match self.known_value(&token) {
+            Some(value) if (0..=0xFFF).contains(&value) => self.emit_opcode(&token, high << 12 | value as u16),
+            Some(value) => Err(self.error(&token, format!("address {} is out of range 0 to 4095", value))),
+            None if is_name(&token.text) => {
+                self.fixups.push((token.clone(), Fixup::Address(self.address)));
+                self.emit_opcode(&token, high << 12)
+            }
+            None => Err(self.error(&token, format!("expected an address, found '{}'", token.text))),
+        }
+    }
+
+    // Point the jump at `at` to the current address, which has to fit in 12 bits
+    fn patch_jump(&mut self, token: &Token, at: usize) -> Result<(), AsmError> {
+        let target = self.jump_target(token, self.address)?;
+        self.image[at] = (self.image[at] & 0xF0) | (target >> 8) as u8;
+        self.image[at + 1] = target as u8;
+        Ok(())
+    }
+
+    fn jump_target(&self, token: &Token, address: usize) -> Result<u16, AsmError> {
+        if address > 0xFFF {
+            return Err(self.error(token, format!("'{}' jumps to 0x{:X}, which is past 0xFFF", token.text, address)));
+        }
+        Ok(address as u16)
+    }
+
+    fn define_label(&mut self, token: &Token, address: usize) -> Result<(), AsmError> {
+        if self.labels.insert(token.text.clone(), address).is_some() {
+            return Err(self.error(token, format!("label '{}' is already defined", token.text)));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        let text = token.text.as_str();
+
+        match text {
+            ":" => {
+                let name = self.next()?;
+                self.define_label(&name, self.address)?;
+            }
+            ":next" => {
+                // Names the second byte of the next instruction, for self modifying code
+                let name = self.next()?;
+                self.define_label(&name, self.address + 1)?;
+            }
+            ":const" => {
+                let name = self.next()?;
+                if self.constants.contains_key(&name.text) || self.labels.contains_key(&name.text) {
+                    return Err(self.error(&name, format!("'{}' is already defined", name.text)));
+                }
+                let value = self.value_in(i64::MIN, i64::MAX)?;
+                self.constants.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let register = self.expect_register()?;
+                self.aliases.insert(name.text, register);
+            }
+            ":macro" => {
+                let name = self.next()?;
+                let mut arguments = Vec::new();
+                while self.peek() != Some("{") {
+                    arguments.push(self.next()?.text);
+                }
+                let body = self.braced()?;
+                self.macros.insert(name.text, (arguments, body));
+            }
+            ":calc" => {
+                let name = self.next()?;
+                let expression = self.braced()?;
+                let value = self.calc(&token, &expression)?;
+                self.constants.insert(name.text, value);
+            }
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    let expression = self.braced()?;
+                    let value = self.calc(&token, &expression)?;
+                    if !(-128..=255).contains(&value) {
+                        return Err(self.error(&token, format!("byte {} is out of range -128 to 255", value)));
+                    }
+                    value
+                } else {
+                    self.value_in(-128, 255)?
+                };
+                self.emit(&token, &[value as u8])?;
+            }
+            ":org" => {
+                let value = self.value_in(PROGRAM_START as i64, MEMORY_SIZE as i64 - 1)?;
+                self.address = value as usize;
+            }
+            ":call" => self.address_operand(0x2)?,
+            ":unpack" => {
+                let nibble = self.nibble()?;
+                let name = self.next()?;
+                let at = self.address;
+                self.emit_opcode(&token, 0x6000 | (nibble as u16) << 4)?;
+                self.emit_opcode(&token, 0x6100)?;
+                match self.labels.get(&name.text) {
+                    Some(&address) => {
+                        self.image[at + 1] |= (address >> 8) as u8 & 0xF;
+                        self.image[at + 3] = address as u8;
+                    }
+                    None => {
+                        self.fixups.push((name.clone(), Fixup::UnpackHigh(at + 1)));
+                        self.fixups.push((name, Fixup::UnpackLow(at + 3)));
+                    }
+                }
+            }
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            "return" | ";" => self.emit_opcode(&token, 0x00EE)?,
+            "clear" => self.emit_opcode(&token, 0x00E0)?,
+            "hires" => self.emit_opcode(&token, 0x00FF)?,
+            "lores" => self.emit_opcode(&token, 0x00FE)?,
+            "exit" => self.emit_opcode(&token, 0x00FD)?,
+            "scroll-left" => self.emit_opcode(&token, 0x00FC)?,
+            "scroll-right" => self.emit_opcode(&token, 0x00FB)?,
+            "audio" => self.emit_opcode(&token, 0xF002)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit_opcode(&token, 0x00C0 | n as u16)?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit_opcode(&token, 0x00D0 | n as u16)?;
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit_opcode(&token, 0xF001 | (n as u16) << 8)?;
+            }
+            "jump" => self.address_operand(0x1)?,
+            "jump0" => self.address_operand(0xB)?,
+            "native" => self.address_operand(0x0)?,
+            "bcd" => self.register_opcode(&token, 0xF033)?,
+            "saveflags" => self.register_opcode(&token, 0xF075)?,
+            "loadflags" => self.register_opcode(&token, 0xF085)?,
+            "save" | "load" => {
+                let x = self.expect_register()? as u16;
+                if self.peek() == Some("-") {
+                    // save vx - vy is the XO-CHIP range store
+                    self.next()?;
+                    let y = self.expect_register()? as u16;
+                    let low = if text == "save" { 0x2 } else { 0x3 };
+                    self.emit_opcode(&token, 0x5000 | x << 8 | y << 4 | low)?;
+                } else {
+                    let low = if text == "save" { 0x55 } else { 0x65 };
+                    self.emit_opcode(&token, 0xF000 | x << 8 | low)?;
+                }
+            }
+            "sprite" => {
+                let x = self.expect_register()? as u16;
+                let y = self.expect_register()? as u16;
+                let n = self.nibble()? as u16;
+                self.emit_opcode(&token, 0xD000 | x << 8 | y << 4 | n)?;
+            }
+            "i" => {
+                let operator = self.next()?;
+                match operator.text.as_str() {
+                    ":=" => match self.peek() {
+                        Some("hex") => {
+                            self.next()?;
+                            self.register_opcode(&token, 0xF029)?;
+                        }
+                        Some("bighex") => {
+                            self.next()?;
+                            self.register_opcode(&token, 0xF030)?;
+                        }
+                        Some("long") => {
+                            self.next()?;
+                            self.emit_opcode(&token, 0xF000)?;
+                            let target = self.next()?;
+                            match self.known_value(&target) {
+                                Some(value) if (0..=0xFFFF).contains(&value) => self.emit_opcode(&target, value as u16)?,
+                                Some(value) => return Err(self.error(&target, format!("address {} is out of range 0 to 65535", value))),
+                                None => {
+                                    self.fixups.push((target.clone(), Fixup::Long(self.address)));
+                                    self.emit_opcode(&target, 0)?;
+                                }
+                            }
+                        }
+                        _ => self.address_operand(0xA)?,
+                    },
+                    "+=" => self.register_opcode(&token, 0xF01E)?,
+                    _ => return Err(self.error(&operator, format!("can't do 'i {}'", operator.text))),
+                }
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let low = match text {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.register_opcode(&token, 0xF000 | low)?;
+            }
+            "if" => {
+                let condition = self.condition()?;
+                let keyword = self.next()?;
+                for opcode in condition.setup {
+                    self.emit_opcode(&token, opcode)?;
+                }
+                match keyword.text.as_str() {
+                    "then" => self.emit_opcode(&token, condition.skip_when_false)?,
+                    "begin" => {
+                        self.emit_opcode(&token, condition.skip_when_true)?;
+                        self.blocks.push((token.clone(), Block::If(self.address)));
+                        self.emit_opcode(&token, 0x1000)?;
+                    }
+                    _ => return Err(self.error(&keyword, format!("expected then or begin, found '{}'", keyword.text))),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some((opened, Block::If(jump))) => {
+                    let skip_else = self.address;
+                    self.emit_opcode(&token, 0x1000)?;
+                    self.patch_jump(&token, jump)?;
+                    self.blocks.push((opened, Block::Else(skip_else)));
+                }
+                _ => return Err(self.error(&token, "else without if ... begin".to_string())),
+            },
+            "end" => match self.blocks.pop() {
+                Some((_, Block::If(jump))) | Some((_, Block::Else(jump))) => self.patch_jump(&token, jump)?,
+                _ => return Err(self.error(&token, "end without if ... begin".to_string())),
+            },
+            "loop" => self.blocks.push((token.clone(), Block::Loop(self.address, Vec::new()))),
+            "while" => {
+                let condition = self.condition()?;
+                for opcode in condition.setup {
+                    self.emit_opcode(&token, opcode)?;
+                }
+                self.emit_opcode(&token, condition.skip_when_true)?;
+                let exit = self.address;
+                self.emit_opcode(&token, 0x1000)?;
+                match self.blocks.iter_mut().rev().find_map(|(_, block)| match block {
+                    Block::Loop(_, exits) => Some(exits),
+                    _ => None,
+                }) {
+                    Some(exits) => exits.push(exit),
+                    None => return Err(self.error(&token, "while outside of loop ... again".to_string())),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some((_, Block::Loop(start, exits))) => {
+                    let start = self.jump_target(&token, start)?;
+                    self.emit_opcode(&token, 0x1000 | start)?;
+                    for exit in exits {
+                        self.patch_jump(&token, exit)?;
+                    }
+                }
+                _ => return Err(self.error(&token, "again without loop".to_string())),
+            },
+            _ if self.register(&token).is_some() => self.register_statement(&token)?,
+            _ if self.macros.contains_key(text) => self.expand_macro(&token)?,
+            _ => {
+                if let Some(value) = self.known_value(&token).filter(|_| !self.labels.contains_key(text)) {
+                    // Bare numbers are data
+                    if !(-128..=255).contains(&value) {
+                        return Err(self.error(&token, format!("byte {} is out of range -128 to 255", value)));
+                    }
+                    self.emit(&token, &[value as u8])?;
+                } else if is_name(text) {
+                    // Any other name calls the subroutine with that label
+                    self.position -= 1;
+                    self.address_operand(0x2)?;
+                } else {
+                    return Err(self.error(&token, format!("unexpected '{}'", text)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // An FX.. instruction with the register that follows
+    fn register_opcode(&mut self, token: &Token, opcode: u16) -> Result<(), AsmError> {
+        let x = self.expect_register()? as u16;
+        self.emit_opcode(token, opcode | x << 8)
+    }
+
+    // vx op ...
+    fn register_statement(&mut self, token: &Token) -> Result<(), AsmError> {
+        let x = self.register(token).expect("checked by the caller") as u16;
+        let operator = self.next()?;
+        let source = self.next()?;
+        let y = self.register(&source).map(|y| y as u16);
+
+        let opcode = match (operator.text.as_str(), y) {
+            (":=", Some(y)) => 0x8000 | x << 8 | y << 4,
+            (":=", None) => match source.text.as_str() {
+                "key" => 0xF00A | x << 8,
+                "delay" => 0xF007 | x << 8,
+                "random" => 0xC000 | x << 8 | self.byte()? as u16,
+                _ => {
+                    self.position -= 1;
+                    0x6000 | x << 8 | self.byte()? as u16
+                }
+            },
+            ("+=", Some(y)) => 0x8004 | x << 8 | y << 4,
+            ("+=", None) => {
+                self.position -= 1;
+                0x7000 | x << 8 | self.byte()? as u16
+            }
+            ("-=", Some(y)) => 0x8005 | x << 8 | y << 4,
+            ("-=", None) => {
+                self.position -= 1;
+                0x7000 | x << 8 | (self.byte()? as u16).wrapping_neg() & 0xFF
+            }
+            ("=-", Some(y)) => 0x8007 | x << 8 | y << 4,
+            ("|=", Some(y)) => 0x8001 | x << 8 | y << 4,
+            ("&=", Some(y)) => 0x8002 | x << 8 | y << 4,
+            ("^=", Some(y)) => 0x8003 | x << 8 | y << 4,
+            (">>=", Some(y)) => 0x8006 | x << 8 | y << 4,
+            ("<<=", Some(y)) => 0x800E | x << 8 | y << 4,
+            _ => return Err(self.error(&operator, format!("can't do '{} {} {}'", token.text, operator.text, source.text))),
+        };
+        self.emit_opcode(token, opcode)
+    }
+
+    // vx == n, vx != vy, vx key, vx > n and so on
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.expect_register()? as u16;
+        let operator = self.next()?;
+
+        match operator.text.as_str() {
+            "key" => return Ok(Condition { setup: Vec::new(), skip_when_false: 0xE0A1 | x << 8, skip_when_true: 0xE09E | x << 8 }),
+            "-key" => return Ok(Condition { setup: Vec::new(), skip_when_false: 0xE09E | x << 8, skip_when_true: 0xE0A1 | x << 8 }),
+            _ => {}
+        }
+
+        let right = self.next()?;
+        let y = self.register(&right).map(|y| y as u16);
+        let (equal, not_equal) = match y {
+            Some(y) => (0x5000 | x << 8 | y << 4, 0x9000 | x << 8 | y << 4),
+            None => {
+                self.position -= 1;
+                let byte = self.byte()? as u16;
+                (0x3000 | x << 8 | byte, 0x4000 | x << 8 | byte)
+            }
+        };
+        // vf := right
+        let load_vf = match y {
+            Some(y) => 0x8F00 | y << 4,
+            None => 0x6F00 | (equal & 0xFF),
+        };
+
+        // vf -= vx leaves VF 1 when right >= vx, vf =- vx leaves it 1 when vx >= right
+        let (setup, flag_when_true) = match operator.text.as_str() {
+            "==" => return Ok(Condition { setup: Vec::new(), skip_when_false: not_equal, skip_when_true: equal }),
+            "!=" => return Ok(Condition { setup: Vec::new(), skip_when_false: equal, skip_when_true: not_equal }),
+            ">" => (vec![load_vf, 0x8F05 | x << 4], 0),
+            "<" => (vec![load_vf, 0x8F07 | x << 4], 0),
+            ">=" => (vec![load_vf, 0x8F07 | x << 4], 1),
+            "<=" => (vec![load_vf, 0x8F05 | x << 4], 1),
+            _ => return Err(self.error(&operator, format!("unknown comparison '{}'", operator.text))),
+        };
+        Ok(Condition {
+            setup,
+            // SNE VF skips when the flag isn't what the test needs, SE VF when it is
+            skip_when_false: 0x4F00 | flag_when_true,
+            skip_when_true: 0x3F00 | flag_when_true,
+        })
+    }
+
+    // Tokens between { and the matching }
+    fn braced(&mut self) -> Result<Vec<Token>, AsmError> {
+        let open = self.next()?;
+        if open.text != "{" {
+            return Err(self.error(&open, format!("expected '{{', found '{}'", open.text)));
+        }
+        let mut depth = 1;
+        let mut body = Vec::new();
+        loop {
+            let token = self.next().map_err(|_| self.error(&open, "'{' is never closed".to_string()))?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(body);
+                    }
+                }
+                _ => {}
+            }
+            body.push(token);
+        }
+    }
+
+    fn expand_macro(&mut self, name: &Token) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return Err(self.error(name, format!("macro '{}' expands forever", name.text)));
+        }
+        let (parameters, body) = self.macros[&name.text].clone();
+        let mut arguments = HashMap::new();
+        for parameter in parameters {
+            arguments.insert(parameter, self.next()?.text);
+        }
+
+        // Expanded tokens point at the invocation so errors land somewhere useful
+        let expanded: Vec<Token> = body
+            .iter()
+            .map(|token| Token {
+                text: arguments.get(&token.text).cloned().unwrap_or_else(|| token.text.clone()),
+                line: name.line,
+                column: name.column,
+            })
+            .collect();
+        self.tokens.splice(self.position..self.position, expanded);
+        Ok(())
+    }
+
+    /// Evaluate a :calc expression.
+    ///
+    /// Like Octo, operators have no precedence and group from the right, so
+    /// `2 * 3 + 1` is 8. Parentheses group as usual and `HERE` is the
+    /// current address.
+    fn calc(&self, at: &Token, tokens: &[Token]) -> Result<i64, AsmError> {
+        let mut position = 0;
+        let value = self.calc_expression(at, tokens, &mut position)?;
+        match tokens.get(position) {
+            Some(extra) => Err(self.error(extra, format!("unexpected '{}' in expression", extra.text))),
+            None => Ok(value),
+        }
+    }
+
+    fn calc_expression(&self, at: &Token, tokens: &[Token], position: &mut usize) -> Result<i64, AsmError> {
+        let left = self.calc_term(at, tokens, position)?;
+        let operator = match tokens.get(*position) {
+            Some(token) if token.text != ")" => token.clone(),
+            _ => return Ok(left),
+        };
+        *position += 1;
+        let right = self.calc_expression(at, tokens, position)?;
+
+        let value = match operator.text.as_str() {
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "*" => left.wrapping_mul(right),
+            "/" | "%" if right == 0 => return Err(self.error(&operator, "division by zero".to_string())),
+            "/" => left.checked_div(right).ok_or_else(|| self.error(&operator, "division overflows".to_string()))?,
+            "%" => left.checked_rem(right).ok_or_else(|| self.error(&operator, "division overflows".to_string()))?,
+            "&" => left & right,
+            "|" => left | right,
+            "^" => left ^ right,
+            "<<" => left.wrapping_shl(right as u32),
+            ">>" => left.wrapping_shr(right as u32),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            _ => return Err(self.error(&operator, format!("unknown operator '{}'", operator.text))),
+        };
+        Ok(value)
+    }
+
+    fn calc_term(&self, at: &Token, tokens: &[Token], position: &mut usize) -> Result<i64, AsmError> {
+        let token = tokens.get(*position).ok_or_else(|| self.error(at, "expression ends too soon".to_string()))?;
+        *position += 1;
+        match token.text.as_str() {
+            "(" => {
+                let value = self.calc_expression(at, tokens, position)?;
+                match tokens.get(*position) {
+                    Some(close) if close.text == ")" => {
+                        *position += 1;
+                        Ok(value)
+                    }
+                    _ => Err(self.error(token, "unclosed '('".to_string())),
+                }
+            }
+            "-" => self.calc_term(at, tokens, position)?.checked_neg().ok_or_else(|| self.error(token, "negation overflows".to_string())),
+            "~" => Ok(!self.calc_term(at, tokens, position)?),
+            "!" => Ok((self.calc_term(at, tokens, position)? == 0) as i64),
+            "HERE" => Ok(self.address as i64),
+            _ => self.known_value(token).ok_or_else(|| self.error(token, format!("'{}' is not defined", token.text))),
+        }
+    }
+
+    fn resolve_fixups(&mut self) -> Result<(), AsmError> {
+        for (token, fixup) in std::mem::take(&mut self.fixups) {
+            let address = *self.labels.get(&token.text).ok_or_else(|| self.error(&token, format!("undefined label '{}'", token.text)))?;
+            match fixup {
+                Fixup::Address(at) => {
+                    if address > 0xFFF {
+                        return Err(self.error(&token, format!("'{}' at 0x{:X} is past 0xFFF, use i := long", token.text, address)));
+                    }
+                    self.image[at] |= (address >> 8) as u8;
+                    self.image[at + 1] = address as u8;
+                }
+                Fixup::Long(at) => self.image[at..at + 2].copy_from_slice(&(address as u16).to_be_bytes()),
+                Fixup::UnpackHigh(at) => self.image[at] |= (address >> 8) as u8 & 0xF,
+                Fixup::UnpackLow(at) => self.image[at] = address as u8,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Something that could be a label
+fn is_name(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && parse_number(text).is_none()
+}
+
+/// Compile Octo source into a ROM image to load at `PROGRAM_START`.
+///
+/// Covers labels, `:next`, `:const`, `:alias`, `:macro`, `:calc`, `:byte`,
+/// `:org`, `:call`, `:unpack`, the structured `if`/`loop` forms and the
+/// CHIP-8, SUPER-CHIP and XO-CHIP statements. Execution starts at `main`,
+/// reached through a jump in the first two bytes. `name` is only used in
+/// error messages.
+pub fn compile(source: &str, name: &str) -> Result<Vec<u8>, AsmError> {
+    let mut compiler = Compiler {
+        file: name.to_string(),
+        tokens: tokenize(source),
+        position: 0,
+        image: vec![0; MEMORY_SIZE],
+        address: PROGRAM_START,
+        end: PROGRAM_START,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        expansions: 0,
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+    };
+
+    let start = Token { text: "main".to_string(), line: 1, column: 1 };
+    compiler.fixups.push((start.clone(), Fixup::Address(PROGRAM_START)));
+    compiler.emit_opcode(&start, 0x1000)?;
+
+    while compiler.position < compiler.tokens.len() {
+        compiler.statement()?;
+    }
+    if let Some((opened, _)) = compiler.blocks.last() {
+        return Err(compiler.error(opened, format!("'{}' is never closed", opened.text)));
+    }
+    if !compiler.labels.contains_key("main") {
+        return Err(compiler.error(&start, "there is no ': main' to start at".to_string()));
+    }
+    compiler.resolve_fixups()?;
+
+    Ok(compiler.image[PROGRAM_START..compiler.end].to_vec())
+}
+
+pub fn compile_file(path: &str) -> Result<Vec<u8>, AsmError> {
+    let source = fs::read_to_string(path).map_err(|err| AsmError {
+        file: path.to_string(),
+        line: 0,
+        column: 0,
+        message: err.to_string(),
+    })?;
+    compile(&source, path)
+}
@@ -0,0 +1,118 @@
+use std::str::FromStr;
+
+use crate::{CHIP8_MEMORY_SIZE, MEMORY_SIZE};
+
+/// Where I ends up after FX55 and FX65 store or load registers 0 to X.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    // I is left alone (SUPER-CHIP)
+    Unchanged,
+    // I = I + X (CHIP-48)
+    ByX,
+    // I = I + X + 1 (COSMAC VIP, XO-CHIP)
+    ByXPlusOne,
+}
+
+/// Behaviours CHIP-8 interpreters disagree on.
+///
+/// Each field picks one interpretation. The named presets match the
+/// interpreters most ROMs were written for; `Quirks::default()` is the
+/// original COSMAC VIP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // 8XY6 and 8XYE shift VY into VX, rather than shifting VX in place
+    pub shift_uses_vy: bool,
+    // What FX55 and FX65 do to I
+    pub index_increment: IndexIncrement,
+    // BXNN jumps to XNN + VX, rather than BNNN jumping to NNN + V0
+    pub jump_uses_vx: bool,
+    // 8XY1, 8XY2 and 8XY3 set VF to 0
+    pub logic_resets_vf: bool,
+    // Sprites are cut off at the screen edges, rather than wrapping round
+    pub clip_sprites: bool,
+    // DXYN waits for the next frame before the program carries on
+    pub wait_for_vblank: bool,
+    // Programs can use all 64 KiB of memory (XO-CHIP), rather than the first 4 KiB
+    pub extended_memory: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::cosmac_vip()
+    }
+}
+
+impl Quirks {
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            index_increment: IndexIncrement::ByXPlusOne,
+            jump_uses_vx: false,
+            logic_resets_vf: true,
+            clip_sprites: true,
+            wait_for_vblank: true,
+            extended_memory: false,
+        }
+    }
+
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::ByX,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+            wait_for_vblank: false,
+            extended_memory: false,
+        }
+    }
+
+    pub fn superchip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::Unchanged,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+            wait_for_vblank: false,
+            extended_memory: false,
+        }
+    }
+
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            index_increment: IndexIncrement::ByXPlusOne,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            wait_for_vblank: false,
+            extended_memory: true,
+        }
+    }
+
+    // Bytes of memory a program can use
+    pub fn memory_size(&self) -> usize {
+        if self.extended_memory { MEMORY_SIZE } else { CHIP8_MEMORY_SIZE }
+    }
+
+    // The --quirks name of the preset these quirks match, if any
+    pub fn preset_name(&self) -> Option<&'static str> {
+        ["vip", "chip48", "schip", "xochip"].into_iter().find(|name| name.parse() == Ok(*self))
+    }
+}
+
+// Preset names as accepted by --quirks
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vip" | "chip8" => Ok(Quirks::cosmac_vip()),
+            "chip48" => Ok(Quirks::chip48()),
+            "schip" | "superchip" => Ok(Quirks::superchip()),
+            "xochip" => Ok(Quirks::xochip()),
+            _ => Err(format!("unknown quirks preset '{}'", s)),
+        }
+    }
+}
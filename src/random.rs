@@ -0,0 +1,142 @@
+use std::str::FromStr;
+
+use crate::PROGRAM_START;
+
+// Seed of a machine nobody chose a seed for, so runs are repeatable by default
+pub const DEFAULT_SEED: u64 = 0x2F11_C8C8;
+
+/// Where CXNN gets its random bytes from.
+///
+/// Implementations must be deterministic, so that two machines with the same
+/// state and input run the same way. The whole state fits in a u64, which is
+/// what save states store along with the source's kind.
+pub trait RandomSource {
+    // The next random byte, `memory` is the machine's for sources that read it
+    fn next_byte(&mut self, memory: &[u8]) -> u8;
+
+    // The kind of source a save state rebuilds this one as
+    fn kind(&self) -> RandomKind;
+
+    fn state(&self) -> u64;
+
+    fn set_state(&mut self, state: u64);
+}
+
+/// The sources `--rng` can pick, as named in save states and movies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RandomKind {
+    Seeded,
+    CosmacVip,
+}
+
+impl FromStr for RandomKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "seeded" => Ok(RandomKind::Seeded),
+            "vip" => Ok(RandomKind::CosmacVip),
+            _ => Err(format!("unknown random source '{}', expected seeded or vip", s)),
+        }
+    }
+}
+
+impl RandomKind {
+    // The --rng name, also used by movies
+    pub fn name(self) -> &'static str {
+        match self {
+            RandomKind::Seeded => "seeded",
+            RandomKind::CosmacVip => "vip",
+        }
+    }
+
+    pub fn source(self, seed: u64) -> Box<dyn RandomSource> {
+        match self {
+            RandomKind::Seeded => Box::new(SeededRandom::new(seed)),
+            RandomKind::CosmacVip => Box::new(VipRandom::new(seed)),
+        }
+    }
+}
+
+/// xorshift64* from a seed, good quality and the default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> SeededRandom {
+        // splitmix64 spreads the seed out, xorshift never leaves a zero state
+        let mut mixed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        mixed ^= mixed >> 31;
+        SeededRandom { state: if mixed == 0 { 1 } else { mixed } }
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    fn kind(&self) -> RandomKind {
+        RandomKind::Seeded
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = if state == 0 { 1 } else { state };
+    }
+}
+
+/// An approximation of the COSMAC VIP interpreter's CXNN routine.
+///
+/// The VIP had no random hardware. Its CXNN stepped a one byte pointer
+/// through the interpreter's own code, adding the byte it found there to a
+/// running value. The interpreter's code is not in this machine's memory,
+/// so this walks the first page of the program instead. The numbers have
+/// the VIP's short, patterned period, but they are not the sequence a real
+/// VIP gives and no ROM should rely on them matching one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VipRandom {
+    pointer: u8,
+    value: u8,
+}
+
+impl VipRandom {
+    pub fn new(seed: u64) -> VipRandom {
+        VipRandom {
+            pointer: seed as u8,
+            value: (seed >> 8) as u8,
+        }
+    }
+}
+
+impl RandomSource for VipRandom {
+    fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        self.pointer = self.pointer.wrapping_add(1);
+        let code = memory.get(PROGRAM_START + self.pointer as usize).copied().unwrap_or(0);
+        self.value = self.value.wrapping_add(code).wrapping_add(self.pointer).rotate_right(1);
+        self.value
+    }
+
+    fn kind(&self) -> RandomKind {
+        RandomKind::CosmacVip
+    }
+
+    fn state(&self) -> u64 {
+        self.pointer as u64 | (self.value as u64) << 8
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.pointer = state as u8;
+        self.value = (state >> 8) as u8;
+    }
+}
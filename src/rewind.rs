@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+
+use crate::machine::Machine;
+use crate::state::MachineState;
+
+/// Bounded history of per-frame machine states for playing a game backwards.
+///
+/// Only the newest state is kept whole. Each older frame is stored as the
+/// difference between its save state bytes and those of the frame after it,
+/// run length encoded, so a frame that changed a few registers and a sprite
+/// costs a few dozen bytes rather than a full copy of memory and display.
+pub struct RewindBuffer {
+    capacity: usize,
+    // Save state bytes of the most recent frame
+    newest: Option<Vec<u8>>,
+    // Oldest first, each one turns the frame after it back into itself
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    // Keep at most `capacity` frames to rewind through
+    pub fn new(capacity: usize) -> RewindBuffer {
+        RewindBuffer {
+            capacity,
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    // Number of frames that can be rewound
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+    }
+
+    // Bytes used by the stored frames
+    pub fn size(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    // Record the machine as it is at the end of a frame
+    pub fn push(&mut self, machine: &Machine) {
+        let state = machine.save_state().to_bytes();
+        if let Some(previous) = self.newest.take() {
+            if self.capacity > 0 {
+                self.deltas.push_back(encode_delta(&state, &previous));
+            }
+            while self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.newest = Some(state);
+    }
+
+    /// Put the machine back to the frame before the newest one recorded.
+    ///
+    /// That frame becomes the newest, so calling this repeatedly walks
+    /// backwards one frame at a time. Returns false when there is nothing
+    /// older left.
+    pub fn rewind(&mut self, machine: &mut Machine) -> bool {
+        let (newest, delta) = match (self.newest.as_mut(), self.deltas.pop_back()) {
+            (Some(newest), Some(delta)) => (newest, delta),
+            _ => return false,
+        };
+        apply_delta(newest, &delta);
+
+        let state = MachineState::from_bytes(newest).expect("rewind buffer holds valid save states");
+        machine.load_state(&state);
+        true
+    }
+}
+
+/*
+ * Delta format
+ *  XOR of the two states, as a list of runs:
+ *      unchanged byte count u16 LE, changed byte count u16 LE, the changed XORed bytes
+ *  A run may have no changed bytes, to skip more than 65535 unchanged ones
+ */
+
+// The delta that turns `from` into `to`, both the same length
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    debug_assert_eq!(from.len(), to.len());
+    let mut delta = Vec::new();
+    let mut position = 0;
+
+    while position < from.len() {
+        let unchanged = from[position..]
+            .iter()
+            .zip(&to[position..])
+            .take(u16::MAX as usize)
+            .take_while(|(a, b)| a == b)
+            .count();
+        position += unchanged;
+
+        let changed = from[position..]
+            .iter()
+            .zip(&to[position..])
+            .take(u16::MAX as usize)
+            .take_while(|(a, b)| a != b)
+            .count();
+
+        delta.extend_from_slice(&(unchanged as u16).to_le_bytes());
+        delta.extend_from_slice(&(changed as u16).to_le_bytes());
+        delta.extend(from[position..position + changed].iter().zip(&to[position..]).map(|(a, b)| a ^ b));
+        position += changed;
+    }
+    delta
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut position = 0;
+    let mut runs = delta;
+
+    while runs.len() >= 4 {
+        let unchanged = u16::from_le_bytes([runs[0], runs[1]]) as usize;
+        let changed = u16::from_le_bytes([runs[2], runs[3]]) as usize;
+        position += unchanged;
+        for (byte, xor) in state[position..position + changed].iter_mut().zip(&runs[4..4 + changed]) {
+            *byte ^= xor;
+        }
+        position += changed;
+        runs = &runs[4 + changed..];
+    }
+}
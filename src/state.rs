@@ -0,0 +1,351 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use crate::display::{Display, HIRES_HEIGHT, PLANES};
+use crate::machine::Machine;
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::random::{RandomKind, DEFAULT_SEED};
+use crate::MEMORY_SIZE;
+
+// Start of every save state file
+pub const STATE_MAGIC: &[u8; 4] = b"C8ST";
+// Bumped whenever the layout below changes
+pub const STATE_VERSION: u16 = 4;
+
+/// Why a save state could not be restored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    // The data does not start with STATE_MAGIC
+    NotAState,
+    // Written by a newer or older interpreter with a different layout
+    UnsupportedVersion(u16),
+    // The data ends before the state does
+    Truncated,
+    // A field holds a value no machine could have
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::NotAState => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => write!(f, "unsupported save state version {}", version),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid(field) => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+/*
+ * Layout, all numbers little endian
+ *  magic "C8ST", version u16
+ *  memory length u32, memory
+ *  V0 to VF, I u16, PC u16, SP i8, stack 16 x u16
+ *  delay timer, sound timer
+ *  keypad 16 x u8 (0 or 1), RPL flags 16 x u8, exited u8
+ *  audio pattern present u8, pattern 16 x u8, pitch u8
+ *  quirks: shift_uses_vy, index_increment (0 unchanged, 1 by X, 2 by X + 1),
+ *      jump_uses_vx, logic_resets_vf, clip_sprites, wait_for_vblank, extended_memory
+ *  display: hires u8, selected planes u8, PLANES x HIRES_HEIGHT rows of u128
+ *  random source kind u8 (0 seeded, 1 COSMAC VIP), random source state u64
+ */
+
+/// Everything needed to resume a machine exactly where it left off.
+///
+/// The audio backend is the only part of a machine that is not saved,
+/// restoring a state keeps whichever backend the machine already has. The
+/// random source is saved as its kind and state, restoring replaces the
+/// machine's source if it is of a different kind.
+///
+/// States are only taken and restored between frames. How many instructions
+/// the next frame runs is up to the frontend's `FrameScheduler`, whose
+/// carried remainder is not part of the state: a restored machine runs the
+/// same instructions, but they may fall one frame earlier or later than they
+/// did the first time round.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineState {
+    pub memory: Vec<u8>,
+    pub general_registers: [u8; 16],
+    pub memory_register: u16,
+    pub program_counter: u16,
+    pub stack_pointer: i8,
+    pub stack: [u16; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keypad: [bool; 16],
+    pub rpl_flags: [u8; 16],
+    pub exited: bool,
+    pub audio_pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+    pub quirks: Quirks,
+    pub display: Display,
+    pub random_kind: RandomKind,
+    pub random_state: u64,
+}
+
+impl MachineState {
+    pub fn capture(machine: &Machine) -> MachineState {
+        MachineState {
+            memory: machine.memory.clone(),
+            general_registers: machine.general_registers,
+            memory_register: machine.memory_register,
+            program_counter: machine.program_counter,
+            stack_pointer: machine.stack_pointer,
+            stack: machine.stack,
+            delay_timer: machine.delay_timer,
+            sound_timer: machine.sound_timer,
+            keypad: machine.keypad,
+            rpl_flags: machine.rpl_flags,
+            exited: machine.exited,
+            audio_pattern: machine.audio_pattern,
+            pitch: machine.pitch,
+            quirks: machine.quirks,
+            display: machine.display.clone(),
+            random_kind: machine.random.kind(),
+            random_state: machine.random.state(),
+        }
+    }
+
+    pub fn restore(&self, machine: &mut Machine) {
+        machine.memory.clone_from(&self.memory);
+        machine.general_registers = self.general_registers;
+        machine.memory_register = self.memory_register;
+        machine.program_counter = self.program_counter;
+        machine.stack_pointer = self.stack_pointer;
+        machine.stack = self.stack;
+        machine.delay_timer = self.delay_timer;
+        machine.sound_timer = self.sound_timer;
+        machine.keypad = self.keypad;
+        machine.rpl_flags = self.rpl_flags;
+        machine.exited = self.exited;
+        machine.audio_pattern = self.audio_pattern;
+        machine.pitch = self.pitch;
+        machine.quirks = self.quirks;
+        machine.display.clone_from(&self.display);
+        if machine.random.kind() != self.random_kind {
+            machine.random = self.random_kind.source(DEFAULT_SEED);
+        }
+        machine.random.set_state(self.random_state);
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MEMORY_SIZE + 2048);
+        out.extend_from_slice(STATE_MAGIC);
+        out.extend_from_slice(&STATE_VERSION.to_le_bytes());
+
+        out.extend_from_slice(&(self.memory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.memory);
+        out.extend_from_slice(&self.general_registers);
+        out.extend_from_slice(&self.memory_register.to_le_bytes());
+        out.extend_from_slice(&self.program_counter.to_le_bytes());
+        out.push(self.stack_pointer as u8);
+        for address in self.stack.iter() {
+            out.extend_from_slice(&address.to_le_bytes());
+        }
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+
+        out.extend(self.keypad.iter().map(|&pressed| pressed as u8));
+        out.extend_from_slice(&self.rpl_flags);
+        out.push(self.exited as u8);
+        out.push(self.audio_pattern.is_some() as u8);
+        out.extend_from_slice(&self.audio_pattern.unwrap_or([0; 16]));
+        out.push(self.pitch);
+
+        out.push(self.quirks.shift_uses_vy as u8);
+        out.push(match self.quirks.index_increment {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::ByX => 1,
+            IndexIncrement::ByXPlusOne => 2,
+        });
+        out.push(self.quirks.jump_uses_vx as u8);
+        out.push(self.quirks.logic_resets_vf as u8);
+        out.push(self.quirks.clip_sprites as u8);
+        out.push(self.quirks.wait_for_vblank as u8);
+        out.push(self.quirks.extended_memory as u8);
+
+        out.push(self.display.hires as u8);
+        out.push(self.display.selected);
+        for plane in self.display.planes.iter() {
+            for row in plane.iter() {
+                out.extend_from_slice(&row.to_le_bytes());
+            }
+        }
+        out.push(match self.random_kind {
+            RandomKind::Seeded => 0,
+            RandomKind::CosmacVip => 1,
+        });
+        out.extend_from_slice(&self.random_state.to_le_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<MachineState, StateError> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err(StateError::NotAState);
+        }
+        let version = reader.u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let memory_size = reader.u32()? as usize;
+        if memory_size != MEMORY_SIZE {
+            return Err(StateError::Invalid("memory size"));
+        }
+        let memory = reader.take(memory_size)?.to_vec();
+        let general_registers = reader.array()?;
+        let memory_register = reader.u16()?;
+        let program_counter = reader.u16()?;
+        let stack_pointer = reader.u8()? as i8;
+        if !(-1..16).contains(&stack_pointer) {
+            return Err(StateError::Invalid("stack pointer"));
+        }
+        let mut stack = [0; 16];
+        for address in stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+
+        let mut keypad = [false; 16];
+        for pressed in keypad.iter_mut() {
+            *pressed = reader.bool()?;
+        }
+        let rpl_flags = reader.array()?;
+        let exited = reader.bool()?;
+        let has_pattern = reader.bool()?;
+        let pattern = reader.array()?;
+        let audio_pattern = if has_pattern { Some(pattern) } else { None };
+        let pitch = reader.u8()?;
+
+        let shift_uses_vy = reader.bool()?;
+        let index_increment = match reader.u8()? {
+            0 => IndexIncrement::Unchanged,
+            1 => IndexIncrement::ByX,
+            2 => IndexIncrement::ByXPlusOne,
+            _ => return Err(StateError::Invalid("index increment quirk")),
+        };
+        let quirks = Quirks {
+            shift_uses_vy,
+            index_increment,
+            jump_uses_vx: reader.bool()?,
+            logic_resets_vf: reader.bool()?,
+            clip_sprites: reader.bool()?,
+            wait_for_vblank: reader.bool()?,
+            extended_memory: reader.bool()?,
+        };
+
+        let mut display = Display::new();
+        display.hires = reader.bool()?;
+        display.selected = reader.u8()?;
+        if display.selected > 0b11 {
+            return Err(StateError::Invalid("plane selection"));
+        }
+        for plane in 0..PLANES {
+            for y in 0..HIRES_HEIGHT {
+                display.planes[plane][y] = u128::from_le_bytes(reader.array()?);
+            }
+        }
+        let random_kind = match reader.u8()? {
+            0 => RandomKind::Seeded,
+            1 => RandomKind::CosmacVip,
+            _ => return Err(StateError::Invalid("random source")),
+        };
+        let random_state = reader.u64()?;
+
+        if reader.position != bytes.len() {
+            return Err(StateError::Invalid("length"));
+        }
+
+        Ok(MachineState {
+            memory,
+            general_registers,
+            memory_register,
+            program_counter,
+            stack_pointer,
+            stack,
+            delay_timer,
+            sound_timer,
+            keypad,
+            rpl_flags,
+            exited,
+            audio_pattern,
+            pitch,
+            quirks,
+            display,
+            random_kind,
+            random_state,
+        })
+    }
+}
+
+// Reads the fields of a save state in order
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], StateError> {
+        let end = self.position + count;
+        let taken = self.bytes.get(self.position..end).ok_or(StateError::Truncated)?;
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("flag")),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+}
+
+impl Machine {
+    pub fn save_state(&self) -> MachineState {
+        MachineState::capture(self)
+    }
+
+    pub fn load_state(&mut self, state: &MachineState) {
+        state.restore(self);
+    }
+
+    // Writes a save state file for load_state_file
+    pub fn save_state_file(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.save_state().to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load_state_file(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        let state = MachineState::from_bytes(&fs::read(path)?)?;
+        self.load_state(&state);
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+/// Rate the delay and sound timers count down at, one tick per frame.
+pub const TIMER_HZ: u32 = 60;
+
+/// Seconds of emulated time in one frame.
+pub const FRAME_SECONDS: f64 = 1.0 / TIMER_HZ as f64;
+
+/// Spreads an instruction rate over 60 Hz frames.
+///
+/// Rates that don't divide evenly by 60 carry the remainder into the next
+/// frame, so 700 instructions per second runs 11 or 12 instructions a frame
+/// and exactly 700 over 60 frames. Only frames are counted, never wall clock
+/// time, which keeps headless runs deterministic.
+///
+/// The scheduler belongs to the frontend rather than the machine, so save
+/// states don't include the remainder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameScheduler {
+    instructions_per_second: u32,
+    remainder: u32,
+}
+
+impl FrameScheduler {
+    pub fn new(instructions_per_second: u32) -> FrameScheduler {
+        FrameScheduler {
+            instructions_per_second,
+            remainder: 0,
+        }
+    }
+
+    pub fn instructions_per_second(&self) -> u32 {
+        self.instructions_per_second
+    }
+
+    // Number of instructions to execute in the next frame
+    pub fn next_frame(&mut self) -> u32 {
+        let total = self.instructions_per_second + self.remainder;
+        self.remainder = total % TIMER_HZ;
+        total / TIMER_HZ
+    }
+}
@@ -1 +1,35 @@
-// Adds comments to each line of hex text to decribe what they do
\ No newline at end of file
+// Adds comments to each line of hex text to decribe what they do
+//
+// chip8-describe FILE: FILE is either a listing with one hex opcode per line,
+// which is printed back with a comment on each line, or a raw ROM, which is
+// listed an opcode per line. --rom treats FILE as a ROM whatever it looks like.
+use std::env;
+use std::fs;
+use std::process;
+
+use chip8::describe::{describe_listing, describe_rom, is_listing};
+
+const USAGE: &str = "Usage: chip8-describe [--rom] FILE";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (force_rom, path) = match args.as_slice() {
+        [flag, path] if flag == "--rom" => (true, path),
+        [path] if !path.starts_with("--") => (false, path),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    let bytes = fs::read(path).unwrap_or_else(|err| {
+        eprintln!("Couldn't read {}: {}", path, err);
+        process::exit(1);
+    });
+
+    if !force_rom && is_listing(&bytes) {
+        print!("{}", describe_listing(&String::from_utf8_lossy(&bytes)));
+    } else {
+        print!("{}", describe_rom(&bytes));
+    }
+}
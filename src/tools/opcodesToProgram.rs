@@ -1,2 +1,33 @@
 // Converts opcodes (1 per line) to binary
-// allow comments 
\ No newline at end of file
+// allow comments
+//
+// chip8-asm SOURCE [OUT]: assembles SOURCE into a ROM at OUT, by default
+// SOURCE with a .ch8 extension. See chip8::assembler for the syntax.
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "Usage: chip8-asm SOURCE [OUT]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.len() > 2 || args[0].starts_with("--") {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let rom = chip8::assemble_file(&args[0]).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let out = match args.get(1) {
+        Some(path) => path.clone(),
+        None => Path::new(&args[0]).with_extension("ch8").to_string_lossy().into_owned(),
+    };
+    if let Err(err) = fs::write(&out, rom) {
+        eprintln!("Couldn't write {}: {}", out, err);
+        process::exit(1);
+    }
+}
@@ -1 +1,33 @@
 // Converts raw binary to text representation of hex
+//
+// chip8-disasm ROM [OUT]: writes an annotated listing of ROM to OUT, or to
+// standard output without one.
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "Usage: chip8-disasm ROM [OUT]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.len() > 2 || args[0].starts_with("--") {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let rom = fs::read(&args[0]).unwrap_or_else(|err| {
+        eprintln!("Couldn't read {}: {}", args[0], err);
+        process::exit(1);
+    });
+    let listing = chip8::disassemble(&rom);
+
+    match args.get(1) {
+        Some(path) => {
+            if let Err(err) = fs::write(path, listing) {
+                eprintln!("Couldn't write {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+        None => print!("{}", listing),
+    }
+}
@@ -0,0 +1,91 @@
+//! Assembling Cowgod mnemonics.
+
+use std::fs;
+
+use chip8::{assemble, assemble_file, AsmError};
+
+fn error(source: &str) -> AsmError {
+    assemble(source, "test.asm").unwrap_err()
+}
+
+#[test]
+fn labels_can_be_used_before_and_after() {
+    let source = "start: jp end\n  ld v0, 1\nend: jp start";
+    assert_eq!(assemble(source, "test.asm").unwrap(), [0x12, 0x04, 0x60, 0x01, 0x12, 0x00]);
+}
+
+#[test]
+fn constants() {
+    let source = "SPEED = 3\nDOUBLE equ SPEED + SPEED\nld v1, DOUBLE - ( 1 - -2 )\nld v2, SPEED";
+    assert_eq!(assemble(source, "test.asm").unwrap(), [0x61, 0x03, 0x62, 0x03]);
+    assert_eq!(error("A = B\nB = 1").message, "'B' must be defined before it is used here");
+    assert_eq!(error("A = 1\nA = 2").message, "'A' is already defined");
+}
+
+#[test]
+fn db_and_dw() {
+    let source = "db 1, -1, 0b1010_0000, \"Hi\"\ndw 0x1234, -2";
+    assert_eq!(assemble(source, "test.asm").unwrap(), [0x01, 0xFF, 0xA0, b'H', b'i', 0x12, 0x34, 0xFF, 0xFE]);
+    assert_eq!(error("db 256").message, "byte 256 is out of range -128 to 255");
+    assert_eq!(error("dw 0x10000").message, "word 65536 is out of range -32768 to 65535");
+}
+
+#[test]
+fn org_and_align() {
+    let rom = assemble("db 1\nalign 4\ndb 2\norg 0x208\ndb 3", "test.asm").unwrap();
+    assert_eq!(rom, [1, 0, 0, 0, 2, 0, 0, 0, 3]);
+    assert_eq!(error("org 0x100").message, "org 0x100 is outside 0x200 to 0xFFFF");
+    assert_eq!(error("db 1\norg 0x200\ndb 2").message, "overwrites 0x200, already written");
+    assert_eq!(error("align 0").message, "align needs a positive number");
+}
+
+#[test]
+fn align_past_the_end_of_memory() {
+    let found = error("db 1\nalign 0x7FFFFFFFFFFFFFFF");
+    assert_eq!(found.message, "align 9223372036854775807 pads past the end of memory at 0x10000");
+    assert_eq!((found.line, found.column), (2, 7));
+}
+
+#[test]
+fn overflowing_expressions_are_errors() {
+    assert_eq!(error("A = 0x7FFFFFFFFFFFFFFF + 1").message, "value overflows");
+    assert_eq!(error("A = -0x7FFFFFFFFFFFFFFF - 2").message, "value overflows");
+    assert_eq!(error("A = -0x7FFFFFFFFFFFFFFF - 1\nB = -A").message, "value overflows");
+}
+
+#[test]
+fn includes_are_relative_to_the_including_file() {
+    let directory = std::env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+    fs::create_dir_all(directory.join("lib")).unwrap();
+    fs::write(directory.join("main.asm"), "include \"lib/sprites.asm\"\nld i, digit").unwrap();
+    fs::write(directory.join("lib/sprites.asm"), "jp skip\ndigit: db 0xF0\nskip:\ninclude \"broken.asm\"").unwrap();
+    fs::write(directory.join("lib/broken.asm"), "\n  ld v0, v1, v2").unwrap();
+
+    let found = assemble_file(&directory.join("main.asm").to_string_lossy()).unwrap_err();
+    assert_eq!(found.file, directory.join("lib/broken.asm").to_string_lossy());
+    assert_eq!((found.line, found.column, found.message.as_str()), (2, 3, "invalid operands for LD"));
+
+    fs::write(directory.join("lib/broken.asm"), "").unwrap();
+    let rom = assemble_file(&directory.join("main.asm").to_string_lossy()).unwrap();
+    assert_eq!(rom, [0x12, 0x03, 0xF0, 0xA2, 0x02]);
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn string_escapes() {
+    assert_eq!(assemble(r#"db "a\nb\\\"""#, "test.asm").unwrap(), b"a\nb\\\"");
+    assert_eq!(error(r#"db "a\tb""#).to_string(), "test.asm:1:6: unknown escape '\\t'");
+    assert_eq!(error("db \"caf\u{e9}\"").to_string(), "test.asm:1:8: '\u{e9}' is not ASCII");
+    // Escaping one doesn't truncate it to a byte either
+    assert_eq!(error("db \"\\\u{e9}\"").to_string(), "test.asm:1:5: unknown escape '\\\u{e9}'");
+    assert_eq!(error("db \"x\\").to_string(), "test.asm:1:4: unterminated string");
+}
+
+#[test]
+fn errors_point_at_file_line_and_column() {
+    let found = error("cls\n\n  ld v0, undefined");
+    assert_eq!(found.to_string(), "test.asm:3:10: undefined name 'undefined'");
+    assert_eq!(error("  jp 0x1000").to_string(), "test.asm:1:6: address 4096 is out of range 0 to 4095");
+    assert_eq!(error("ld v0, \"x").to_string(), "test.asm:1:8: unterminated string");
+    assert_eq!(error("  frob v0").to_string(), "test.asm:1:3: unknown instruction 'frob'");
+}
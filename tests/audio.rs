@@ -0,0 +1,82 @@
+//! The beeper written to WAV, which is how headless runs hear a program.
+
+use std::cell::RefCell;
+use std::io::{self, Cursor, Seek, SeekFrom, Write};
+use std::rc::Rc;
+
+use chip8::{AudioBackend, Machine, NullAudio, SoundFrame, ToneSettings, WavAudio, TIMER_HZ};
+
+const SAMPLES_PER_FRAME: usize = 44_100 / TIMER_HZ as usize;
+const HEADER_SIZE: usize = 44;
+
+// A cursor the test keeps hold of while the machine owns the backend
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Cursor<Vec<u8>>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+impl Seek for Shared {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.borrow_mut().seek(pos)
+    }
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn samples(bytes: &[u8]) -> Vec<i16> {
+    bytes[HEADER_SIZE..].chunks(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])).collect()
+}
+
+#[test]
+fn sound_timer_frames_are_audible() {
+    let shared = Shared::default();
+    let mut machine = Machine::new();
+    machine.set_audio(Box::new(WavAudio::new(shared.clone(), ToneSettings::default()).unwrap()));
+    machine.sound_timer = 3;
+    for _ in 0..5 {
+        machine.tick_timers();
+    }
+    // Dropping the backend fixes up the header
+    drop(machine.set_audio(Box::new(NullAudio)));
+
+    let bytes = shared.0.borrow().get_ref().clone();
+    let samples = samples(&bytes);
+    assert_eq!(samples.len(), 5 * SAMPLES_PER_FRAME);
+    let (beep, silence) = samples.split_at(3 * SAMPLES_PER_FRAME);
+    assert!(beep.iter().all(|&sample| sample != 0));
+    assert!(silence.iter().all(|&sample| sample == 0));
+}
+
+#[test]
+fn finish_writes_the_sizes() {
+    let mut wav = WavAudio::new(Cursor::new(Vec::new()), ToneSettings::default()).unwrap();
+    let beep = SoundFrame { on: true, ..SoundFrame::silent() };
+    for sound in [SoundFrame::silent(), beep, SoundFrame::silent()] {
+        wav.frame(&sound);
+    }
+    assert_eq!(wav.samples_written() as usize, 3 * SAMPLES_PER_FRAME);
+
+    let bytes = wav.finish().unwrap().into_inner();
+    let data_size = 3 * SAMPLES_PER_FRAME * 2;
+    assert_eq!(bytes.len(), HEADER_SIZE + data_size);
+    assert_eq!(&bytes[0..4], b"RIFF");
+    assert_eq!(u32_at(&bytes, 4) as usize, HEADER_SIZE - 8 + data_size);
+    assert_eq!(&bytes[8..16], b"WAVEfmt ");
+    assert_eq!(&bytes[36..40], b"data");
+    assert_eq!(u32_at(&bytes, 40) as usize, data_size);
+
+    let samples = samples(&bytes);
+    assert!(samples[..SAMPLES_PER_FRAME].iter().all(|&sample| sample == 0));
+    assert!(samples[SAMPLES_PER_FRAME..2 * SAMPLES_PER_FRAME].iter().all(|&sample| sample != 0));
+    assert!(samples[2 * SAMPLES_PER_FRAME..].iter().all(|&sample| sample == 0));
+}
@@ -0,0 +1,93 @@
+//! Runs test ROMs without a window and compares the final screen with a
+//! golden copy in tests/golden.
+//!
+//! tests/roms holds small self checking Octo programs written for this
+//! repository. The community suites (Timendus' corax+, flags, quirks and
+//! keypad tests and BC_test) aren't checked in and have no goldens yet, so
+//! their cases are ignored. `tests/roms/community/fetch.sh` downloads them
+//! and says how to bless the goldens, after which the ROMs, goldens and the
+//! removal of the ignores go in together.
+//!
+//! Set CHIP8_BLESS=1 to write the current screen as the golden instead of
+//! comparing against it. Check the .txt by eye before committing it.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use chip8::{screen_ascii, FaultPolicy, FrameScheduler, Headless, KeyScript, Machine, Quirks};
+
+const INSTRUCTIONS_PER_SECOND: u32 = 1000;
+
+// Run a ROM for some frames, feeding it a key script, and check its screen
+fn check(name: &str, rom: &str, quirks: Quirks, frames: u64, keys: &str) {
+    let rom = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms").join(rom);
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.txt", name));
+    assert!(rom.exists(), "{} needs {}", name, rom.display());
+
+    let mut machine = Machine::with_quirks(quirks);
+    machine.load_from_file(rom.to_str().unwrap()).unwrap();
+    let keys = KeyScript::parse(keys).unwrap();
+    let mut headless = Headless::new(machine, FrameScheduler::new(INSTRUCTIONS_PER_SECOND), FaultPolicy::default(), keys);
+    headless.run(frames, None, None).unwrap();
+    let screen = screen_ascii(&headless.machine.display);
+
+    if env::var_os("CHIP8_BLESS").is_some() {
+        fs::write(&golden, &screen).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden).unwrap_or_else(|_| panic!("no golden {}, run with CHIP8_BLESS=1 to write it", golden.display()));
+    assert!(screen == expected, "{} screen differs from {}\nexpected:\n{}\nfound:\n{}", name, golden.display(), expected, screen);
+}
+
+#[test]
+fn opcodes() {
+    check("opcodes", "opcodes.8o", Quirks::cosmac_vip(), 120, "");
+}
+
+#[test]
+fn memory() {
+    check("memory", "memory.8o", Quirks::cosmac_vip(), 60, "");
+}
+
+#[test]
+fn font() {
+    check("font", "font.8o", Quirks::cosmac_vip(), 60, "");
+}
+
+#[test]
+fn keypad() {
+    check("keypad", "keypad.8o", Quirks::cosmac_vip(), 60, "5 press 5\n10 release 5\n20 press 7\n30 release 7\n");
+}
+
+#[test]
+#[ignore = "run tests/roms/community/fetch.sh for 3-corax+.ch8"]
+fn corax_plus() {
+    check("corax-plus", "community/3-corax+.ch8", Quirks::cosmac_vip(), 120, "");
+}
+
+#[test]
+#[ignore = "run tests/roms/community/fetch.sh for 4-flags.ch8"]
+fn flags() {
+    check("flags", "community/4-flags.ch8", Quirks::cosmac_vip(), 120, "");
+}
+
+#[test]
+#[ignore = "run tests/roms/community/fetch.sh for 5-quirks.ch8"]
+fn quirks() {
+    // The menu asks which platform to test, 1 is the COSMAC VIP
+    check("quirks", "community/5-quirks.ch8", Quirks::cosmac_vip(), 600, "30 press 1\n40 release 1\n");
+}
+
+#[test]
+#[ignore = "run tests/roms/community/fetch.sh for 6-keypad.ch8"]
+fn keypad_community() {
+    // Pick the EX9E test from the menu, then hold A so it lights up
+    check("keypad-community", "community/6-keypad.ch8", Quirks::cosmac_vip(), 180, "30 press 1\n40 release 1\n60 press A\n");
+}
+
+#[test]
+#[ignore = "run tests/roms/community/fetch.sh for BC_test.ch8"]
+fn bc_test() {
+    check("bc-test", "community/BC_test.ch8", Quirks::cosmac_vip(), 120, "");
+}
@@ -0,0 +1,106 @@
+//! The debugger's commands, typed into `repl` as a user would.
+
+use chip8::{Debugger, Machine};
+
+// Calls a subroutine setting V1, adds to V0, then jumps to itself
+const PROGRAM: [u8; 12] = [0x60, 0x05, 0x22, 0x08, 0x70, 0x01, 0x12, 0x06, 0x61, 0x07, 0x00, 0xEE];
+
+// What the debugger printed after each command, starting with the banner
+fn session(debugger: &mut Debugger, commands: &str) -> Vec<String> {
+    let mut output = Vec::new();
+    debugger.repl(commands.as_bytes(), &mut output).unwrap();
+    String::from_utf8(output).unwrap().split("(chip8) ").map(str::to_string).collect()
+}
+
+fn debugger() -> Debugger {
+    let mut machine = Machine::new();
+    machine.load_rom(&PROGRAM).unwrap();
+    Debugger::new(machine, 700)
+}
+
+#[test]
+fn breakpoints() {
+    let mut debugger = debugger();
+    let output = session(&mut debugger, "break 0x204\nb op 6XKK\nbreaks\ndelete 1\ndelete 1\nbreaks\ncontinue\n");
+    assert_eq!(output[0], "0x200: 6005  LD V0, 0x05\n");
+    assert_eq!(output[1], "breakpoint 0: address 0x204\n");
+    assert_eq!(output[2], "breakpoint 1: opcode 6???\n");
+    assert_eq!(output[3], "0: address 0x204\n1: opcode 6???\n");
+    assert_eq!(output[4], "");
+    assert_eq!(output[5], "error: no breakpoint 1\n");
+    assert_eq!(output[6], "0: address 0x204\n");
+    assert_eq!(output[7], "breakpoint 0\n0x204: 7001  ADD V0, 0x01\n");
+}
+
+#[test]
+fn breakpoints_past_the_end_of_memory() {
+    let mut debugger = debugger();
+    let output = session(&mut debugger, "break 0xFFF\nbreak 0x1000\nbreak 0x10200\nbreaks\n");
+    assert_eq!(output[1], "breakpoint 0: address 0xFFF\n");
+    assert_eq!(output[2], "error: 0x1000 is past the end of memory\n");
+    assert_eq!(output[3], "error: 0x10200 is past the end of memory\n");
+    assert_eq!(output[4], "0: address 0xFFF\n");
+}
+
+#[test]
+fn stepping() {
+    let mut debugger = debugger();
+    let output = session(&mut debugger, "step\nnext\nregs\nstep 2\nfinish\n");
+    assert_eq!(output[1], "0x202: 2208  CALL 0x208\n");
+    assert_eq!(output[2], "0x204: 7001  ADD V0, 0x01\n");
+    assert_eq!(
+        output[3],
+        "V0=0x05 V1=0x07 V2=0x00 V3=0x00 V4=0x00 V5=0x00 V6=0x00 V7=0x00\n\
+         V8=0x00 V9=0x00 VA=0x00 VB=0x00 VC=0x00 VD=0x00 VE=0x00 VF=0x00\n\
+         I=0x000 PC=0x204 SP=-1 DT=0 ST=0\nstack:\n"
+    );
+    assert_eq!(output[4], "program is jumping to itself\n0x206: 1206  JP 0x206\n");
+    // Nothing to finish outside a subroutine
+    assert_eq!(output[5], "0x206: 1206  JP 0x206\n");
+}
+
+#[test]
+fn finish_returns_from_a_subroutine() {
+    let mut debugger = debugger();
+    let output = session(&mut debugger, "s 2\nregs\nf\n");
+    assert!(output[2].ends_with("I=0x000 PC=0x208 SP=0 DT=0 ST=0\nstack: 0x204\n"));
+    assert_eq!(output[3], "0x204: 7001  ADD V0, 0x01\n");
+}
+
+#[test]
+fn setting_registers_and_memory() {
+    let mut debugger = debugger();
+    let output = session(&mut debugger, "set v3 0x42\nset 0x300 1 2\nx 0x300 4\nset sp 16\nset v0 256\nset 0xFFF 1 2\n");
+    assert_eq!(debugger.machine.general_registers[3], 0x42);
+    assert_eq!(output[3], "0x0300: 01 02 00 00                                      ....\n");
+    assert_eq!(output[4], "error: the stack pointer goes from -1 to 15\n");
+    assert_eq!(output[5], "error: 0x100 doesn't fit in v0\n");
+    // Past the end of the 4 KiB nothing is written, not even the byte that fits
+    assert_eq!(output[6], "error: can't write 2 to 0x1000\n");
+    assert_eq!(debugger.machine.memory[0xFFF], 0);
+}
+
+#[test]
+fn pressing_keys() {
+    let mut debugger = debugger();
+    let output = session(&mut debugger, "press a
+press 3
+release A
+press 16
+press 0x1
+");
+    assert_eq!(output[4], "error: invalid key '16'\n");
+    assert_eq!(output[5], "error: invalid key '0x1'\n");
+    // 16 isn't key 0
+    let held: Vec<usize> = (0..16).filter(|&key| debugger.machine.keypad[key]).collect();
+    assert_eq!(held, [3]);
+}
+
+#[test]
+fn quit_stops_reading() {
+    let mut debugger = debugger();
+    let output = session(&mut debugger, "frob\nquit\nstep\n");
+    assert_eq!(output[1], "error: unknown command 'frob', try help\n");
+    assert_eq!(output.len(), 3);
+    assert_eq!(debugger.machine.program_counter, 0x200);
+}
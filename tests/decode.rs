@@ -0,0 +1,20 @@
+//! Decoding opcodes and encoding them back.
+
+use chip8::{decode, encode, Instruction};
+
+#[test]
+fn every_opcode_round_trips() {
+    for opcode in 0..=u16::MAX {
+        if let Ok(instruction) = decode(opcode) {
+            assert_eq!(encode(&instruction), opcode, "{:04X} decoded to {:?}", opcode, instruction);
+        }
+    }
+}
+
+#[test]
+fn sys_addresses_of_other_instructions_encode_as_them() {
+    assert_eq!(decode(encode(&Instruction::Sys(0x0E0))), Ok(Instruction::Cls));
+    assert_eq!(decode(encode(&Instruction::Sys(0x0EE))), Ok(Instruction::Ret));
+    assert_eq!(decode(encode(&Instruction::Sys(0x0C4))), Ok(Instruction::ScrollDown(4)));
+    assert_eq!(decode(encode(&Instruction::Sys(0x0E1))), Ok(Instruction::Sys(0x0E1)));
+}
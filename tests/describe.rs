@@ -0,0 +1,48 @@
+//! Plain English descriptions of opcodes, listings and ROMs.
+
+use chip8::describe::{describe, describe_listing, describe_rom, is_listing};
+use chip8::{decode, Instruction};
+
+fn described(opcode: u16) -> String {
+    describe(&decode(opcode).unwrap())
+}
+
+#[test]
+fn original_wording() {
+    assert_eq!(described(0x00E0), "Clear display");
+    assert_eq!(described(0x1ABC), "JUMP TO ABC");
+    assert_eq!(described(0x2ABC), "CALLING SUBROUTING AT ABC");
+    assert_eq!(described(0x3A12), "SKIP IF Register A == 12");
+    assert_eq!(described(0x6B2F), "SET Register B to 2F");
+    assert_eq!(described(0x8AB4), "Add values of Registers A and B and store in A");
+    assert_eq!(described(0x8AB5), "Subtract the value of Register B from A and store in A");
+    assert_eq!(described(0xC30F), "Set Reg 3 to random byte AND 1111");
+    assert_eq!(described(0xD125), "Draw sprite of size 5 stored in Reg I at coords Reg 1, Reg 2");
+    assert_eq!(described(0xF433), "Store BCD representation of Reg 4 at I, I+1, I+2");
+}
+
+#[test]
+fn copies_name_the_source_first() {
+    assert_eq!(describe(&Instruction::LdVxVy { x: 0xA, y: 0x3 }), "Copy value in Register 3 to Register A");
+}
+
+#[test]
+fn listings_get_a_comment_per_opcode() {
+    let listing = "// title\n00E0\n0xF000\n0x1234\n\nnot an opcode\n";
+    assert_eq!(
+        describe_listing(listing),
+        "// title\n00E0  // Clear display\n0xF000  // Set Reg I to the address in the next 2 bytes\n\
+         0x1234  // Address 1234 for the instruction above\n\nnot an opcode\n"
+    );
+    assert!(is_listing(b"; comment\n00E0\n1200 loop\n"));
+    assert!(!is_listing(b"00E0\nhello\n"));
+    assert!(!is_listing(&[0x00, 0xE0]));
+}
+
+#[test]
+fn roms_get_addresses() {
+    assert_eq!(
+        describe_rom(&[0x00, 0xE0, 0x5A, 0xB1, 0x12]),
+        "0x200: 00E0  // Clear display\n0x202: 5AB1  // invalid opcode 5AB1\n0x204: 12    // Odd byte at the end of the ROM\n"
+    );
+}
@@ -0,0 +1,48 @@
+//! Disassembling ROMs into labelled listings.
+
+use chip8::disassemble;
+
+#[test]
+fn labels_and_sprite_previews() {
+    // Calls a subroutine, draws a 2 row sprite, loops, with unreachable bytes in between
+    let rom = [0x22, 0x0A, 0xA2, 0x0E, 0xD0, 0x12, 0x12, 0x06, 0x12, 0x34, 0x60, 0x01, 0x00, 0xEE, 0xF0, 0x90, 0x07];
+    assert_eq!(
+        disassemble(&rom),
+        "\
+0x200: 220A      CALL sub_20A
+0x202: A20E      LD I, data_20E
+0x204: D012      DRW V0, V1, 2
+
+label_206:
+0x206: 1206      JP label_206
+0x208: 12        db 0x12
+0x209: 34        db 0x34
+
+sub_20A:
+0x20A: 6001      LD V0, 0x01
+0x20C: 00EE      RET
+
+data_20E:
+0x20E: F0        db 0xF0  ; ####....
+0x20F: 90        db 0x90  ; #..#....
+0x210: 07        db 0x07  ; .....###
+"
+    );
+}
+
+#[test]
+fn sixteen_pixel_sprites_keep_their_rows_together() {
+    let rom = [0xA2, 0x06, 0xD0, 0x10, 0x12, 0x04, 0xFF, 0x00, 0x81, 0x42];
+    let listing = disassemble(&rom);
+    assert!(listing.contains("data_206:\n0x206: FF00      db 0xFF, 0x00  ; ########........\n"));
+    assert!(listing.ends_with("0x208: 8142      db 0x81, 0x42  ; #......#.#....#.\n"));
+}
+
+#[test]
+fn skips_follow_both_paths() {
+    // SE V0, 0 skips a jump over the code after it
+    let rom = [0x30, 0x00, 0x12, 0x08, 0x61, 0x02, 0x12, 0x06, 0x00, 0xFD];
+    let listing = disassemble(&rom);
+    assert!(listing.contains("0x204: 6102      LD V1, 0x02\n"));
+    assert!(listing.contains("label_208:\n0x208: 00FD      EXIT\n"));
+}
@@ -0,0 +1,105 @@
+//! The DXYN blitter, straight on the framebuffer and through the machine.
+
+use chip8::{Display, Machine, Quirks, LORES_HEIGHT, LORES_WIDTH, PROGRAM_START};
+
+const BLOCK: [u16; 2] = [0xFF, 0xFF];
+
+// Coordinates of every lit pixel
+fn lit(display: &Display) -> Vec<(usize, usize)> {
+    let mut pixels = Vec::new();
+    for y in 0..display.height() {
+        for x in 0..display.width() {
+            if display.pixel(x, y) {
+                pixels.push((x, y));
+            }
+        }
+    }
+    pixels
+}
+
+#[test]
+fn draws_at_start_coordinates() {
+    let mut display = Display::new();
+    assert!(!display.draw_sprite(0, 3, 4, &[0x81], 8, true));
+    assert_eq!(lit(&display), [(3, 4), (10, 4)]);
+}
+
+#[test]
+fn start_coordinates_wrap() {
+    let mut display = Display::new();
+    display.draw_sprite(0, LORES_WIDTH + 3, LORES_HEIGHT + 4, &[0x80], 8, true);
+    assert_eq!(lit(&display), [(3, 4)]);
+}
+
+#[test]
+fn clips_at_right_and_bottom_edges() {
+    let mut display = Display::new();
+    display.draw_sprite(0, LORES_WIDTH - 4, LORES_HEIGHT - 1, &BLOCK, 8, true);
+    let expected: Vec<(usize, usize)> = (LORES_WIDTH - 4..LORES_WIDTH).map(|x| (x, LORES_HEIGHT - 1)).collect();
+    assert_eq!(lit(&display), expected);
+}
+
+#[test]
+fn wraps_at_right_and_bottom_edges() {
+    let mut display = Display::new();
+    display.draw_sprite(0, LORES_WIDTH - 4, LORES_HEIGHT - 1, &BLOCK, 8, false);
+    let mut expected = Vec::new();
+    for y in [0, LORES_HEIGHT - 1] {
+        expected.extend((0..4).map(|x| (x, y)));
+        expected.extend((LORES_WIDTH - 4..LORES_WIDTH).map(|x| (x, y)));
+    }
+    assert_eq!(lit(&display), expected);
+}
+
+#[test]
+fn wide_sprites_wrap_in_hires() {
+    let mut display = Display::new();
+    display.set_hires(true);
+    display.draw_sprite(0, 120, 0, &[0xFFFF], 16, false);
+    assert_eq!(lit(&display).len(), 16);
+    assert!(display.pixel(127, 0) && display.pixel(0, 0) && display.pixel(7, 0));
+}
+
+#[test]
+fn collision_from_any_row_is_kept() {
+    let mut display = Display::new();
+    display.draw_sprite(0, 0, 0, &[0x80], 8, true);
+    // Only the first row hits, the second row draws on empty screen
+    assert!(display.draw_sprite(0, 0, 0, &[0x80, 0x80], 8, true));
+    assert_eq!(lit(&display), [(0, 1)]);
+}
+
+#[test]
+fn no_collision_on_clipped_pixels() {
+    let mut display = Display::new();
+    display.draw_sprite(0, 0, 0, &[0x80], 8, true);
+    assert!(!display.draw_sprite(0, LORES_WIDTH - 4, 0, &[0x0F], 8, true));
+}
+
+#[test]
+fn drw_sets_vf_across_rows() {
+    // Draw the 0 digit, then two rows of it again starting on its bottom row,
+    // so only the first of those rows hits anything
+    let program: [u16; 4] = [0xA000, 0xD015, 0x6104, 0xD012];
+    let bytes: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    let mut machine = Machine::with_quirks(Quirks::cosmac_vip());
+    machine.load_rom(&bytes).unwrap();
+    for _ in 0..program.len() {
+        machine.execute_cycle().unwrap();
+    }
+    assert_eq!(machine.program_counter as usize, PROGRAM_START + 8);
+    assert_eq!(machine.general_registers[0xF], 1);
+}
+
+#[test]
+fn drw_wraps_start_coordinates() {
+    // V0 = 70 and V1 = 40 are off screen in lores, so the sprite lands at (6, 8)
+    let program: [u16; 4] = [0x6046, 0x6128, 0xA000, 0xD011];
+    let bytes: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    let mut machine = Machine::with_quirks(Quirks::cosmac_vip());
+    machine.load_rom(&bytes).unwrap();
+    for _ in 0..program.len() {
+        machine.execute_cycle().unwrap();
+    }
+    assert!(machine.display.pixel(6, 8));
+}
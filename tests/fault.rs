@@ -0,0 +1,53 @@
+//! Fault policies as given to --on-fault.
+
+use chip8::{FaultAction, FaultKind, FaultPolicy, MachineFault};
+
+#[test]
+fn defaults_log_invalid_opcodes_and_halt_otherwise() {
+    let policy = FaultPolicy::default();
+    assert_eq!(policy.action_for(&MachineFault::InvalidOpcode { pc: 0x200, opcode: 0xFFFF }), FaultAction::Log);
+    assert_eq!(policy.action_for(&MachineFault::StackOverflow { pc: 0x200 }), FaultAction::Halt);
+    assert_eq!(policy.action_for(&MachineFault::StackUnderflow { pc: 0x200 }), FaultAction::Halt);
+    assert_eq!(policy.action_for(&MachineFault::MemoryOutOfBounds { pc: 0x200, address: 0x1000 }), FaultAction::Halt);
+}
+
+#[test]
+fn settings_apply_in_order() {
+    let mut policy = FaultPolicy::default();
+    policy.apply("ignore").unwrap();
+    assert_eq!(policy, FaultPolicy::all(FaultAction::Ignore));
+
+    policy.apply("stack-overflow=halt").unwrap();
+    policy.apply("memory=log").unwrap();
+    assert_eq!(
+        policy,
+        FaultPolicy {
+            stack_overflow: FaultAction::Halt,
+            stack_underflow: FaultAction::Ignore,
+            invalid_opcode: FaultAction::Ignore,
+            memory_out_of_bounds: FaultAction::Log,
+        }
+    );
+
+    // A bare action replaces every earlier setting
+    policy.apply("log").unwrap();
+    assert_eq!(policy, FaultPolicy::all(FaultAction::Log));
+}
+
+#[test]
+fn roms_that_are_too_large_always_halt() {
+    let mut policy = FaultPolicy::all(FaultAction::Ignore);
+    policy.set(FaultKind::RomTooLarge, FaultAction::Ignore);
+    assert_eq!(policy.action_for(&MachineFault::RomTooLarge { size: 5000, max: 3584 }), FaultAction::Halt);
+}
+
+#[test]
+fn bad_settings_are_errors() {
+    let mut policy = FaultPolicy::default();
+    assert_eq!(policy.apply("panic"), Err("unknown fault action 'panic'".to_string()));
+    assert_eq!(policy.apply("display=halt"), Err("unknown fault 'display'".to_string()));
+    assert_eq!(policy.apply("memory=explode"), Err("unknown fault action 'explode'".to_string()));
+    assert_eq!(policy, FaultPolicy::default());
+    assert_eq!("stack-underflow".parse(), Ok(FaultKind::StackUnderflow));
+    assert_eq!("invalid-opcode".parse(), Ok(FaultKind::InvalidOpcode));
+}
@@ -0,0 +1,123 @@
+//! The GDB remote serial protocol stub, driven over in-memory streams.
+
+use chip8::{Debugger, GdbStub, Machine};
+
+// A stub for a machine with the opcodes loaded at PROGRAM_START
+fn stub(program: &[u16]) -> GdbStub {
+    let bytes: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    let mut machine = Machine::new();
+    machine.load_rom(&bytes).unwrap();
+    GdbStub::new(Debugger::new(machine, 700))
+}
+
+// Send the packets and return the replies, checking each is acked and checksummed
+fn session(stub: &mut GdbStub, packets: &[&str]) -> Vec<String> {
+    let input: String = packets.iter().map(|packet| packet_bytes(packet)).collect();
+    let mut output = Vec::new();
+    stub.serve_connection(input.as_bytes(), &mut output).unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let mut replies = Vec::new();
+    for reply in output.split('+').skip(1) {
+        let (data, checksum) = reply.strip_prefix('$').unwrap().split_once('#').unwrap();
+        assert_eq!(checksum, &packet_bytes(data)[data.len() + 2..]);
+        replies.push(data.to_string());
+    }
+    assert_eq!(replies.len(), packets.len());
+    replies
+}
+
+fn packet_bytes(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${}#{:02x}", data, checksum)
+}
+
+#[test]
+fn reads_registers() {
+    let mut stub = stub(&[0x6A42, 0xA123]);
+    let replies = session(&mut stub, &["s", "s", "g", "p11"]);
+    assert_eq!(replies[..2], ["S05", "S05"]);
+    // V0 to VF, then I, PC and SP
+    assert_eq!(replies[2], format!("{}42{}2301{}ff", "00".repeat(10), "00".repeat(5), "0402"));
+    assert_eq!(replies[3], "0402");
+}
+
+#[test]
+fn writes_registers() {
+    let mut stub = stub(&[]);
+    let registers = format!("{}{}{}{}", "01".repeat(16), "3412", "0003", "00");
+    let replies = session(&mut stub, &[&format!("G{}", registers), "P10=cdab", "P12=0f"]);
+    assert_eq!(replies, ["OK", "OK", "OK"]);
+
+    let machine = &stub.debugger.machine;
+    assert_eq!(machine.general_registers, [1; 16]);
+    assert_eq!(machine.memory_register, 0xABCD);
+    assert_eq!(machine.program_counter, 0x300);
+    assert_eq!(machine.stack_pointer, 15);
+}
+
+#[test]
+fn rejects_stack_pointers_off_the_stack() {
+    let mut stub = stub(&[]);
+    let registers = format!("{}{}{}{}", "00".repeat(16), "0000", "0002", "10");
+    let replies = session(&mut stub, &[&format!("G{}", registers), "P12=10", "P12=80", "P12=ff"]);
+    assert_eq!(replies, ["E01", "E01", "E01", "OK"]);
+    assert_eq!(stub.debugger.machine.stack_pointer, -1);
+}
+
+#[test]
+fn reads_and_writes_memory() {
+    let mut stub = stub(&[0x1234]);
+    let replies = session(&mut stub, &["m200,2", "M300,3:0a0b0c", "m300,3", "mffff,2", "m1,ffffffffffffffff", "M1,ffffffffffffffff:00"]);
+    assert_eq!(replies, ["1234", "OK", "0a0b0c", "E01", "E01", "E01"]);
+}
+
+#[test]
+fn continues_to_a_breakpoint() {
+    let mut stub = stub(&[0x6001, 0x6002, 0x6003, 0x1206]);
+    let replies = session(&mut stub, &["Z0,204,2", "c", "g", "z0,204,2", "Z1,204,2"]);
+    assert_eq!(replies[..2], ["OK", "S05"]);
+    assert!(replies[2].starts_with("02"));
+    assert_eq!(replies[3..], ["OK", ""]);
+    assert_eq!(stub.debugger.machine.program_counter, 0x204);
+}
+
+#[test]
+fn continue_stops_on_ctrl_c() {
+    let mut stub = stub(&[0x1200]);
+    let mut input = packet_bytes("c").into_bytes();
+    input.push(0x03);
+    let mut output = Vec::new();
+    stub.serve_connection(&input[..], &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), format!("+{}", packet_bytes("S02")));
+}
+
+#[test]
+fn bad_checksums_are_nacked() {
+    let mut stub = stub(&[]);
+    let mut input = "$g#00".to_string();
+    input += &packet_bytes("?");
+    let mut output = Vec::new();
+    stub.serve_connection(input.as_bytes(), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), format!("-+{}", packet_bytes("S05")));
+}
+
+#[test]
+fn ctrl_c_while_stopped_is_not_acked() {
+    let mut stub = stub(&[]);
+    let mut output = Vec::new();
+    stub.serve_connection(&[0x03][..], &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), packet_bytes("S05"));
+}
+
+#[test]
+fn monitor_presses_keys() {
+    let mut stub = stub(&[]);
+    let monitor = |command: &str| format!("qRcmd,{}", hex::encode(command));
+    let replies = session(&mut stub, &[&monitor("press a"), &monitor("press 10"), &monitor("release a"), &monitor("press 3")]);
+    assert_eq!(replies[0], "OK");
+    assert!(replies[1].starts_with(&hex::encode("monitor commands")));
+    assert_eq!(replies[2..], ["OK", "OK"]);
+    let held: Vec<usize> = (0..16).filter(|&key| stub.debugger.machine.keypad[key]).collect();
+    assert_eq!(held, [3]);
+}
@@ -0,0 +1,80 @@
+//! Headless runs: scripted keys and screen dumps.
+
+use chip8::headless::{dump_screen, screen_pbm};
+use std::fs;
+use chip8::{screen_ascii, Display, FaultPolicy, FrameScheduler, Headless, KeyScript, Machine};
+
+#[test]
+fn key_scripts_parse() {
+    let script = KeyScript::parse("# title\n4 release a\n\n2 press A  # comment\n2 press 3\n").unwrap();
+    let mut machine = Machine::new();
+    // Jumps to itself
+    machine.load_rom(&[0x12, 0x00]).unwrap();
+    let mut headless = Headless::new(machine, FrameScheduler::new(60), FaultPolicy::default(), script);
+
+    let mut held = Vec::new();
+    for _ in 0..5 {
+        headless.step_frame().unwrap();
+        held.push((headless.machine.keypad[0xA], headless.machine.keypad[0x3]));
+    }
+    assert_eq!(held, [(false, false), (false, false), (true, true), (true, true), (false, true)]);
+}
+
+#[test]
+fn key_script_errors_name_the_line() {
+    let error = |text: &str| KeyScript::parse(text).unwrap_err();
+    assert_eq!(error("1 press 1\n2 press G"), "line 2: invalid key 'G'");
+    assert_eq!(error("1 press 10"), "line 1: invalid key '10'");
+    assert_eq!(error("soon press 1"), "line 1: invalid frame 'soon'");
+    assert_eq!(error("1 tap 1"), "line 1: expected press or release, found 'tap'");
+    assert_eq!(error("\n\n1 press"), "line 3: expected FRAME press|release KEY");
+}
+
+#[test]
+fn pbm_packs_eight_pixels_a_byte() {
+    let mut display = Display::new();
+    display.draw_sprite(0, 0, 0, &[0b1000_0001], 8, true);
+    display.draw_sprite(0, 60, 1, &[0b1111_0000], 8, true);
+
+    let pbm = screen_pbm(&display);
+    let header = b"P4\n64 32\n";
+    assert_eq!(&pbm[..header.len()], header);
+    let rows = &pbm[header.len()..];
+    assert_eq!(rows.len(), 32 * 8);
+    assert_eq!(rows[..8], [0x81, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(rows[8..16], [0, 0, 0, 0, 0, 0, 0, 0x0F]);
+    assert!(rows[16..].iter().all(|&byte| byte == 0));
+
+    let ascii = screen_ascii(&display);
+    assert!(ascii.starts_with(&format!("#......#{}\n{}####\n", ".".repeat(56), ".".repeat(60))));
+}
+
+#[test]
+fn hires_pbm() {
+    let mut display = Display::new();
+    display.set_hires(true);
+    display.draw_sprite(0, 127, 63, &[0b1000_0000], 8, true);
+    let pbm = screen_pbm(&display);
+    assert!(pbm.starts_with(b"P4\n128 64\n"));
+    assert_eq!(pbm.len(), b"P4\n128 64\n".len() + 64 * 16);
+    assert_eq!(pbm.last(), Some(&0x01));
+}
+
+#[test]
+fn dumps_pick_the_format_from_the_extension() {
+    let directory = std::env::temp_dir().join(format!("chip8-dump-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let path = |name: &str| directory.join(name).to_string_lossy().into_owned();
+    let mut display = Display::new();
+    display.draw_sprite(0, 0, 0, &[0b1000_0001], 8, true);
+
+    dump_screen(&display, &path("screen.pbm")).unwrap();
+    assert_eq!(fs::read(path("screen.pbm")).unwrap(), screen_pbm(&display));
+    assert_eq!(fs::read_to_string(path("screen.txt")).unwrap(), screen_ascii(&display));
+
+    // A .txt path is the text alone rather than an image overwritten by its own text
+    dump_screen(&display, &path("only.txt")).unwrap();
+    assert_eq!(fs::read_to_string(path("only.txt")).unwrap(), screen_ascii(&display));
+    assert_eq!(fs::read_dir(&directory).unwrap().count(), 3);
+    fs::remove_dir_all(&directory).unwrap();
+}
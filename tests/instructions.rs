@@ -0,0 +1,387 @@
+//! One test per original CHIP-8 opcode, run with the COSMAC VIP quirks.
+
+use chip8::{FaultAction, FaultPolicy, Machine, MachineFault, Quirks, StepOutcome, CHIP8_MEMORY_SIZE, MEMORY_SIZE, PROGRAM_START, SPRITE_START};
+
+// A machine with the opcodes loaded at PROGRAM_START
+fn machine(program: &[u16]) -> Machine {
+    let bytes: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    let mut machine = Machine::new();
+    machine.load_rom(&bytes).unwrap();
+    machine
+}
+
+fn run(program: &[u16]) -> Machine {
+    let mut machine = machine(program);
+    for _ in program {
+        machine.execute_cycle().unwrap();
+    }
+    machine
+}
+
+fn pc(machine: &Machine) -> usize {
+    machine.program_counter as usize
+}
+
+#[test]
+fn cls_00e0() {
+    let mut machine = machine(&[0x00E0]);
+    machine.display.xor_sprite_row(0, 0, 0, 0xFF, 8, true);
+    machine.execute_cycle().unwrap();
+    assert!(!machine.display.pixel(0, 0));
+}
+
+#[test]
+fn ret_00ee() {
+    let mut machine = machine(&[0x2204, 0x0000, 0x00EE]);
+    machine.execute_cycle().unwrap();
+    machine.execute_cycle().unwrap();
+    assert_eq!(pc(&machine), PROGRAM_START + 2);
+    assert_eq!(machine.stack_pointer, -1);
+}
+
+#[test]
+fn sys_0nnn_is_ignored() {
+    let machine = run(&[0x0123]);
+    assert_eq!(pc(&machine), PROGRAM_START + 2);
+}
+
+#[test]
+fn jp_1nnn() {
+    let machine = run(&[0x1ABC]);
+    assert_eq!(pc(&machine), 0xABC);
+}
+
+#[test]
+fn call_2nnn() {
+    let machine = run(&[0x2ABC]);
+    assert_eq!(pc(&machine), 0xABC);
+    assert_eq!(machine.stack[machine.stack_pointer as usize] as usize, PROGRAM_START + 2);
+}
+
+#[test]
+fn se_3xnn() {
+    assert_eq!(pc(&run(&[0x6012, 0x3012])), PROGRAM_START + 6);
+    assert_eq!(pc(&run(&[0x6012, 0x3013])), PROGRAM_START + 4);
+}
+
+#[test]
+fn sne_4xnn() {
+    assert_eq!(pc(&run(&[0x6012, 0x4013])), PROGRAM_START + 6);
+    assert_eq!(pc(&run(&[0x6012, 0x4012])), PROGRAM_START + 4);
+}
+
+#[test]
+fn se_5xy0() {
+    assert_eq!(pc(&run(&[0x6012, 0x6112, 0x5010])), PROGRAM_START + 8);
+    assert_eq!(pc(&run(&[0x6012, 0x6113, 0x5010])), PROGRAM_START + 6);
+}
+
+#[test]
+fn ld_6xnn() {
+    assert_eq!(run(&[0x6A42]).general_registers[0xA], 0x42);
+}
+
+#[test]
+fn add_7xnn_wraps_and_leaves_vf() {
+    let machine = run(&[0x6F05, 0x60FF, 0x7002]);
+    assert_eq!(machine.general_registers[0], 0x01);
+    assert_eq!(machine.general_registers[0xF], 5);
+}
+
+#[test]
+fn ld_8xy0() {
+    assert_eq!(run(&[0x6142, 0x8010]).general_registers[0], 0x42);
+}
+
+#[test]
+fn or_8xy1() {
+    let machine = run(&[0x600C, 0x610A, 0x6F05, 0x8011]);
+    assert_eq!(machine.general_registers[0], 0x0E);
+    assert_eq!(machine.general_registers[0xF], 0);
+}
+
+#[test]
+fn and_8xy2() {
+    let machine = run(&[0x600C, 0x610A, 0x6F05, 0x8012]);
+    assert_eq!(machine.general_registers[0], 0x08);
+    assert_eq!(machine.general_registers[0xF], 0);
+}
+
+#[test]
+fn xor_8xy3() {
+    let machine = run(&[0x600C, 0x610A, 0x6F05, 0x8013]);
+    assert_eq!(machine.general_registers[0], 0x06);
+    assert_eq!(machine.general_registers[0xF], 0);
+}
+
+#[test]
+fn add_8xy4() {
+    let machine = run(&[0x600A, 0x6114, 0x8014]);
+    assert_eq!(machine.general_registers[0], 30);
+    assert_eq!(machine.general_registers[0xF], 0);
+
+    let machine = run(&[0x60C8, 0x6164, 0x8014]);
+    assert_eq!(machine.general_registers[0], 44);
+    assert_eq!(machine.general_registers[0xF], 1);
+
+    // VF as VX ends up holding the carry
+    let machine = run(&[0x6FC8, 0x6164, 0x8F14]);
+    assert_eq!(machine.general_registers[0xF], 1);
+}
+
+#[test]
+fn sub_8xy5() {
+    let machine = run(&[0x6032, 0x6114, 0x8015]);
+    assert_eq!(machine.general_registers[0], 30);
+    assert_eq!(machine.general_registers[0xF], 1);
+
+    // Equal values don't borrow
+    let machine = run(&[0x6014, 0x6114, 0x8015]);
+    assert_eq!(machine.general_registers[0], 0);
+    assert_eq!(machine.general_registers[0xF], 1);
+
+    let machine = run(&[0x600A, 0x6114, 0x8015]);
+    assert_eq!(machine.general_registers[0], 246);
+    assert_eq!(machine.general_registers[0xF], 0);
+
+    let machine = run(&[0x6F32, 0x6114, 0x8F15]);
+    assert_eq!(machine.general_registers[0xF], 1);
+}
+
+#[test]
+fn shr_8xy6() {
+    let machine = run(&[0x6181, 0x8016]);
+    assert_eq!(machine.general_registers[0], 0x40);
+    assert_eq!(machine.general_registers[0xF], 1);
+
+    let machine = run(&[0x6102, 0x8F16]);
+    assert_eq!(machine.general_registers[0xF], 0);
+}
+
+#[test]
+fn subn_8xy7() {
+    let machine = run(&[0x600A, 0x611E, 0x8017]);
+    assert_eq!(machine.general_registers[0], 20);
+    assert_eq!(machine.general_registers[0xF], 1);
+
+    let machine = run(&[0x601E, 0x610A, 0x8017]);
+    assert_eq!(machine.general_registers[0], 236);
+    assert_eq!(machine.general_registers[0xF], 0);
+
+    let machine = run(&[0x6F0A, 0x611E, 0x8F17]);
+    assert_eq!(machine.general_registers[0xF], 1);
+}
+
+#[test]
+fn shl_8xye() {
+    let machine = run(&[0x6181, 0x801E]);
+    assert_eq!(machine.general_registers[0], 0x02);
+    assert_eq!(machine.general_registers[0xF], 1);
+
+    let machine = run(&[0x6140, 0x8F1E]);
+    assert_eq!(machine.general_registers[0xF], 0);
+}
+
+#[test]
+fn sne_9xy0() {
+    assert_eq!(pc(&run(&[0x6012, 0x6113, 0x9010])), PROGRAM_START + 8);
+    assert_eq!(pc(&run(&[0x6012, 0x6112, 0x9010])), PROGRAM_START + 6);
+}
+
+#[test]
+fn ld_annn() {
+    assert_eq!(run(&[0xA123]).memory_register, 0x123);
+}
+
+#[test]
+fn jp_bnnn() {
+    assert_eq!(pc(&run(&[0x6010, 0xB300])), 0x310);
+}
+
+#[test]
+fn rnd_cxnn() {
+    // Only the bits in the mask can be set
+    for _ in 0..20 {
+        assert_eq!(run(&[0xC00F]).general_registers[0] & 0xF0, 0);
+    }
+    assert_eq!(run(&[0xC000]).general_registers[0], 0);
+}
+
+#[test]
+fn drw_dxyn() {
+    // The 0 digit sprite, drawn twice
+    let mut machine = machine(&[0xA000, 0x6002, 0x6103, 0xD015, 0xD015]);
+    for _ in 0..4 {
+        machine.execute_cycle().unwrap();
+    }
+    assert!(machine.display.pixel(2, 3));
+    assert!(!machine.display.pixel(3, 4));
+    assert_eq!(machine.general_registers[0xF], 0);
+
+    machine.execute_cycle().unwrap();
+    assert!(!machine.display.pixel(2, 3));
+    assert_eq!(machine.general_registers[0xF], 1);
+}
+
+#[test]
+fn skp_ex9e() {
+    let mut machine = machine(&[0x6005, 0xE09E]);
+    machine.press_key(5);
+    machine.execute_cycle().unwrap();
+    machine.execute_cycle().unwrap();
+    assert_eq!(pc(&machine), PROGRAM_START + 6);
+
+    assert_eq!(pc(&run(&[0x6005, 0xE09E])), PROGRAM_START + 4);
+}
+
+#[test]
+fn sknp_exa1() {
+    let mut machine = machine(&[0x6005, 0xE0A1]);
+    machine.press_key(5);
+    machine.execute_cycle().unwrap();
+    machine.execute_cycle().unwrap();
+    assert_eq!(pc(&machine), PROGRAM_START + 4);
+
+    assert_eq!(pc(&run(&[0x6005, 0xE0A1])), PROGRAM_START + 6);
+}
+
+#[test]
+fn ld_fx07() {
+    let mut machine = machine(&[0xF307]);
+    machine.delay_timer = 42;
+    machine.execute_cycle().unwrap();
+    assert_eq!(machine.general_registers[3], 42);
+}
+
+#[test]
+fn ld_fx0a() {
+    let mut machine = machine(&[0xF30A]);
+    assert_eq!(machine.execute_cycle(), Ok(StepOutcome::WaitingForKey));
+    assert_eq!(pc(&machine), PROGRAM_START);
+
+    machine.press_key(0xB);
+    machine.execute_cycle().unwrap();
+    assert_eq!(machine.general_registers[3], 0xB);
+    assert_eq!(pc(&machine), PROGRAM_START + 2);
+}
+
+#[test]
+fn ld_fx15() {
+    assert_eq!(run(&[0x6342, 0xF315]).delay_timer, 0x42);
+}
+
+#[test]
+fn ld_fx18() {
+    assert_eq!(run(&[0x6342, 0xF318]).sound_timer, 0x42);
+}
+
+#[test]
+fn add_fx1e() {
+    assert_eq!(run(&[0xA100, 0x6320, 0xF31E]).memory_register, 0x120);
+}
+
+#[test]
+fn ld_fx29() {
+    assert_eq!(run(&[0x630A, 0xF329]).memory_register as usize, SPRITE_START + 5 * 0xA);
+    assert_eq!(run(&[0x6000, 0xF029]).memory_register as usize, SPRITE_START);
+}
+
+#[test]
+fn bcd_fx33() {
+    let machine = run(&[0xA300, 0x6389, 0xF333]);
+    assert_eq!(machine.memory[0x300..0x303], [1, 3, 7]);
+    assert_eq!(machine.memory_register, 0x300);
+}
+
+#[test]
+fn save_fx55() {
+    let machine = run(&[0x6001, 0x6102, 0x6203, 0xA300, 0xF255]);
+    assert_eq!(machine.memory[0x300..0x304], [1, 2, 3, 0]);
+    assert_eq!(machine.memory_register, 0x303);
+}
+
+#[test]
+fn load_fx65() {
+    let mut machine = machine(&[0xA300, 0xF265]);
+    machine.memory[0x300..0x304].copy_from_slice(&[1, 2, 3, 4]);
+    machine.execute_cycle().unwrap();
+    machine.execute_cycle().unwrap();
+    assert_eq!(machine.general_registers[..4], [1, 2, 3, 0]);
+    assert_eq!(machine.memory_register, 0x303);
+}
+
+#[test]
+fn pc_faults_at_end_of_memory() {
+    let mut machine = Machine::new();
+    machine.program_counter = (CHIP8_MEMORY_SIZE - 2) as u16;
+    assert_eq!(
+        machine.execute_cycle(),
+        Err(MachineFault::MemoryOutOfBounds { pc: (CHIP8_MEMORY_SIZE - 2) as u16, address: CHIP8_MEMORY_SIZE })
+    );
+
+    let mut machine = Machine::with_quirks(Quirks::xochip());
+    machine.program_counter = (CHIP8_MEMORY_SIZE - 2) as u16;
+    assert!(machine.execute_cycle().is_ok());
+    machine.program_counter = (MEMORY_SIZE - 2) as u16;
+    assert_eq!(
+        machine.execute_cycle(),
+        Err(MachineFault::MemoryOutOfBounds { pc: (MEMORY_SIZE - 2) as u16, address: MEMORY_SIZE })
+    );
+}
+
+#[test]
+fn jumps_from_the_last_word_of_memory() {
+    let last = CHIP8_MEMORY_SIZE - 2;
+    let mut jumping = Machine::new();
+    jumping.memory[last..CHIP8_MEMORY_SIZE].copy_from_slice(&[0x12, 0x00]);
+    jumping.program_counter = last as u16;
+    jumping.execute_cycle().unwrap();
+    assert_eq!(pc(&jumping), PROGRAM_START);
+
+    // Returning from there too, after a call into it
+    let mut machine = machine(&[0x2FFE]);
+    machine.memory[last..CHIP8_MEMORY_SIZE].copy_from_slice(&[0x00, 0xEE]);
+    machine.execute_cycle().unwrap();
+    machine.execute_cycle().unwrap();
+    assert_eq!(pc(&machine), PROGRAM_START + 2);
+}
+
+#[test]
+fn pc_stays_put_when_faults_are_ignored() {
+    let mut machine = Machine::new();
+    machine.program_counter = (CHIP8_MEMORY_SIZE - 2) as u16;
+    machine.run_frame(10, &FaultPolicy::all(FaultAction::Ignore)).unwrap();
+    assert_eq!(pc(&machine), CHIP8_MEMORY_SIZE - 2);
+
+    // Skipping over the last instruction in memory doesn't wrap either
+    let mut machine = Machine::new();
+    machine.memory[CHIP8_MEMORY_SIZE - 4..CHIP8_MEMORY_SIZE].copy_from_slice(&[0x30, 0x00, 0x00, 0x00]);
+    machine.program_counter = (CHIP8_MEMORY_SIZE - 4) as u16;
+    machine.run_frame(1, &FaultPolicy::all(FaultAction::Ignore)).unwrap();
+    assert_eq!(pc(&machine), CHIP8_MEMORY_SIZE - 2);
+}
+
+#[test]
+fn memory_is_4k_unless_xochip() {
+    // FX55 with I at 0x1000
+    let program = [0xA000 | 0xFFF, 0xF01E, 0xF055];
+    let mut machine = machine(&program);
+    machine.general_registers[0] = 1;
+    machine.execute_cycle().unwrap();
+    machine.execute_cycle().unwrap();
+    assert_eq!(machine.execute_cycle(), Err(MachineFault::MemoryOutOfBounds { pc: PROGRAM_START as u16 + 4, address: 0x1000 }));
+
+    let mut machine = Machine::new();
+    let rom = vec![0; CHIP8_MEMORY_SIZE - PROGRAM_START + 1];
+    assert_eq!(machine.load_rom(&rom), Err(MachineFault::RomTooLarge { size: rom.len(), max: rom.len() - 1 }));
+    assert!(Machine::with_quirks(Quirks::xochip()).load_rom(&rom).is_ok());
+}
+
+#[test]
+fn key_checks_are_recorded() {
+    assert_eq!(run(&[0x6005, 0xE09E, 0x6103, 0xE1A1]).keys_polled, 1 << 5 | 1 << 3);
+
+    let mut waiting = machine(&[0xF30A]);
+    waiting.execute_cycle().unwrap();
+    assert_eq!(waiting.keys_polled, 0xFFFF);
+}
@@ -0,0 +1,72 @@
+//! Keymap defaults and config files.
+
+use chip8::keymap::{axis_inputs, button_input};
+use chip8::Keymap;
+
+const CONFIG: &str = "
+# Arrows as well as WASD
+[default]
+5 = w up
+8 = s down
+
+[pong.ch8]
+1 = 1 q   # left paddle up
+4 = a
+";
+
+#[test]
+fn cosmac_layout() {
+    let keymap = Keymap::cosmac();
+    assert_eq!(keymap.key("1"), Some(0x1));
+    assert_eq!(keymap.key("4"), Some(0xC));
+    assert_eq!(keymap.key("w"), Some(0x5));
+    assert_eq!(keymap.key("x"), Some(0x0));
+    assert_eq!(keymap.key("v"), Some(0xF));
+    assert_eq!(keymap.key("0"), None);
+}
+
+#[test]
+fn controller_defaults_move_with_2468() {
+    let keymap = Keymap::cosmac();
+    assert_eq!(keymap.inputs(0x2), ["2", "axis1-", "hat-up"]);
+    assert_eq!(keymap.inputs(0x4), ["axis0-", "hat-left", "q"]);
+    assert_eq!(keymap.inputs(0x6), ["axis0+", "e", "hat-right"]);
+    assert_eq!(keymap.inputs(0x8), ["axis1+", "hat-down", "s"]);
+    assert_eq!(keymap.key(&button_input(0)), Some(0x5));
+}
+
+#[test]
+fn sticks_press_past_the_threshold() {
+    assert_eq!(axis_inputs(1, -0.9), [("axis1-".to_string(), true), ("axis1+".to_string(), false)]);
+    assert_eq!(axis_inputs(0, 0.2), [("axis0-".to_string(), false), ("axis0+".to_string(), false)]);
+}
+
+#[test]
+fn rom_profile_for_a_pad() {
+    let keymap = Keymap::parse("[snake.ch8]\n5 = w button0 button1\n", Some("snake.ch8")).unwrap();
+    assert_eq!(keymap.inputs(0x5), ["button0", "button1", "w"]);
+}
+
+#[test]
+fn default_section_adds_inputs() {
+    let keymap = Keymap::parse(CONFIG, Some("tetris.ch8")).unwrap();
+    assert_eq!(keymap.inputs(0x5), ["up", "w"]);
+    assert_eq!(keymap.key("down"), Some(0x8));
+    // Untouched keys keep the COSMAC layout, and other ROMs' sections are left out
+    assert_eq!(keymap.key("q"), Some(0x4));
+    assert_eq!(keymap.inputs(0x1), ["1"]);
+}
+
+#[test]
+fn rom_section_overrides_default() {
+    let keymap = Keymap::parse(CONFIG, Some("pong.ch8")).unwrap();
+    assert_eq!(keymap.inputs(0x1), ["1", "q"]);
+    assert_eq!(keymap.inputs(0x4), ["a"]);
+    assert_eq!(keymap.key("up"), Some(0x5));
+}
+
+#[test]
+fn bad_lines_are_reported() {
+    assert_eq!(Keymap::parse("[default]\nG = q\n", None), Err("line 2: invalid key 'G'".to_string()));
+    assert_eq!(Keymap::parse("[other.ch8]\nq\n", None), Err("line 2: expected KEY = INPUTS, found 'q'".to_string()));
+}
@@ -0,0 +1,101 @@
+//! Recording movies and replaying them headless.
+
+use chip8::{FaultPolicy, FrameScheduler, Machine, Movie, Quirks, RandomKind};
+
+const IPS: u32 = 700;
+
+// Draws random numbers and counts in V2 the instructions key 5 is held for
+const PROGRAM: [u8; 12] = [0xC0, 0xFF, 0x61, 0x05, 0xE1, 0x9E, 0x12, 0x00, 0x72, 0x01, 0x12, 0x00];
+
+fn machine(program: &[u8]) -> Machine {
+    let mut machine = Machine::with_quirks(Quirks::chip48());
+    machine.load_rom(program).unwrap();
+    machine
+}
+
+fn record(frames: u64) -> (Movie, Machine) {
+    record_with(RandomKind::Seeded, frames)
+}
+
+// What a frontend does: hold key 5 over frames 10 to 30, recording every frame
+fn record_with(rng: RandomKind, frames: u64) -> (Movie, Machine) {
+    let mut machine = machine(&PROGRAM);
+    machine.set_random(rng.source(99));
+    let mut movie = Movie::new(&machine, rng, 99, IPS);
+    let mut scheduler = FrameScheduler::new(IPS);
+    for frame in 0..frames {
+        match frame {
+            10 => machine.press_key(5),
+            30 => machine.release_key(5),
+            _ => {}
+        }
+        movie.record_frame(&machine.keypad);
+        machine.run_frame(scheduler.next_frame(), &FaultPolicy::default()).unwrap();
+    }
+    movie.finish(&machine);
+    (movie, machine)
+}
+
+fn replay(movie: &Movie) -> Machine {
+    let mut headless = movie.headless(machine(&PROGRAM), FaultPolicy::default()).unwrap();
+    headless.run(movie.frames, None, None).unwrap();
+    headless.machine
+}
+
+#[test]
+fn records_key_changes() {
+    let (movie, machine) = record(40);
+    assert_eq!(movie.events(), [(10, 5, true), (30, 5, false)]);
+    assert_eq!(movie.frames, 40);
+    assert_ne!(machine.general_registers[2], 0);
+}
+
+#[test]
+fn replay_matches_the_recording() {
+    let (movie, recorded) = record(40);
+    let movie = Movie::parse(&movie.to_text().unwrap()).unwrap();
+    assert_eq!(movie.quirks, Quirks::chip48());
+
+    let replayed = replay(&movie);
+    assert_eq!(replayed.general_registers, recorded.general_registers);
+    assert_eq!(movie.verify(&replayed), Ok(()));
+}
+
+#[test]
+fn replay_uses_the_recorded_random_source() {
+    for rng in [RandomKind::Seeded, RandomKind::CosmacVip] {
+        let (movie, recorded) = record_with(rng, 40);
+        let text = movie.to_text().unwrap();
+        assert!(text.contains(&format!("rng {}\n", rng.name())));
+
+        let movie = Movie::parse(&text).unwrap();
+        assert_eq!(movie.rng, rng);
+        let replayed = replay(&movie);
+        assert_eq!(replayed.general_registers, recorded.general_registers);
+        assert_eq!(movie.verify(&replayed), Ok(()));
+    }
+}
+
+#[test]
+fn changed_input_fails_verification() {
+    let (movie, _) = record(40);
+    let text = movie.to_text().unwrap().replace("30 release 5", "31 release 5");
+    let movie = Movie::parse(&text).unwrap();
+    assert!(movie.verify(&replay(&movie)).is_err());
+}
+
+#[test]
+fn other_roms_are_refused() {
+    let (movie, _) = record(1);
+    let mut program = PROGRAM;
+    program[9] = 0x02;
+    assert!(movie.headless(machine(&program), FaultPolicy::default()).is_err());
+}
+
+#[test]
+fn parse_errors() {
+    assert!(Movie::parse("seed 1\n").is_err());
+    let text = record(1).0.to_text().unwrap();
+    assert!(Movie::parse(&text.replace("seed 99", "seed x")).unwrap_err().starts_with("line 4:"));
+    assert!(Movie::parse(&text.replace("quirks chip48\n", "")).is_err());
+}
@@ -0,0 +1,112 @@
+//! Compiling Octo source directly, without going through the sample ROMs.
+
+use chip8::{compile_octo, Machine};
+
+// The words compiled after the jump to main
+fn words(source: &str) -> Vec<u16> {
+    let rom = compile_octo(source, "test.8o").unwrap();
+    rom[2..].chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect()
+}
+
+fn error(source: &str) -> String {
+    compile_octo(source, "test.8o").unwrap_err().message
+}
+
+#[test]
+fn next_names_the_following_byte() {
+    assert_eq!(words(": main :next target v0 := 5 i := target"), [0x6005, 0xA203]);
+}
+
+#[test]
+fn unpack_loads_a_label_into_v0_and_v1() {
+    let rom = compile_octo(": main :unpack 0xA data : data 0x12", "test.8o").unwrap();
+    assert_eq!(rom[2..], [0x60, 0xA2, 0x61, 0x06, 0x12]);
+}
+
+#[test]
+fn macros_substitute_their_arguments() {
+    assert_eq!(words(":macro twice reg { reg += 1 reg += 1 } : main twice v3 twice v4"), [0x7301, 0x7301, 0x7401, 0x7401]);
+}
+
+#[test]
+fn constants_are_defined_once() {
+    assert_eq!(words(":const speed 3 : main v0 := speed"), [0x6003]);
+    assert_eq!(error(":const speed 3 :const speed 4 : main"), "'speed' is already defined");
+    assert_eq!(error(": main :const main 4"), "'main' is already defined");
+}
+
+#[test]
+fn calc_groups_from_the_right() {
+    let source = ":calc right { 2 * 3 + 1 } :calc left { ( 2 * 3 ) + 1 } :calc mixed { 10 - 2 - 3 }
+        : main v0 := right v1 := left v2 := mixed";
+    assert_eq!(words(source), [0x6008, 0x6107, 0x620B]);
+    assert_eq!(words(": main :calc here { HERE + 2 } i := here"), [0xA204]);
+}
+
+#[test]
+fn calc_overflow_is_an_error() {
+    assert_eq!(error(":calc x { - ( 1 << 63 ) }"), "negation overflows");
+    assert_eq!(error(":calc x { ( 1 << 63 ) / -1 }"), "division overflows");
+    assert_eq!(error(":calc x { ( 1 << 63 ) % -1 }"), "division overflows");
+    assert_eq!(error(":calc x { 1 / 0 }"), "division by zero");
+}
+
+#[test]
+fn byte_expressions_are_range_checked() {
+    let rom = compile_octo(": main :byte { 0x80 + 0x7F } :byte { -128 }", "test.8o").unwrap();
+    assert_eq!(rom[2..], [0xFF, 0x80]);
+    assert_eq!(error(": main :byte { 256 }"), "byte 256 is out of range -128 to 255");
+    assert_eq!(error(": main :byte { -129 }"), "byte -129 is out of range -128 to 255");
+}
+
+// An Octo comparison operator and what it should mean
+type Comparison = (&'static str, fn(u8, u8) -> bool);
+
+// V0 after `if v1 OPERATOR RIGHT then v0 := 1` with V1 and V2 as given
+fn condition_holds(operator: &str, right: &str, v1: u8, v2: u8) -> bool {
+    let source = format!(": main v1 := {} v2 := {} v0 := 0 if v1 {} {} then v0 := 1 loop again", v1, v2, operator, right);
+    let mut machine = Machine::new();
+    machine.load_rom(&compile_octo(&source, "test.8o").unwrap()).unwrap();
+    for _ in 0..12 {
+        machine.execute_cycle().unwrap();
+    }
+    machine.general_registers[0] == 1
+}
+
+#[test]
+fn if_comparisons() {
+    let comparisons: [Comparison; 6] = [
+        ("==", |a, b| a == b),
+        ("!=", |a, b| a != b),
+        (">", |a, b| a > b),
+        ("<", |a, b| a < b),
+        (">=", |a, b| a >= b),
+        ("<=", |a, b| a <= b),
+    ];
+    for (operator, expected) in comparisons {
+        for (v1, v2) in [(3, 5), (5, 5), (5, 3), (0, 255), (255, 0)] {
+            assert_eq!(condition_holds(operator, "v2", v1, v2), expected(v1, v2), "{} v2 with {} and {}", operator, v1, v2);
+            assert_eq!(condition_holds(operator, &v2.to_string(), v1, v2), expected(v1, v2), "{} {} with {}", operator, v2, v1);
+        }
+    }
+}
+
+#[test]
+fn if_key() {
+    assert_eq!(words(": main if v1 key then v0 := 1"), [0xE1A1, 0x6001]);
+    assert_eq!(words(": main if v1 -key then v0 := 1"), [0xE19E, 0x6001]);
+    assert_eq!(words(": main if v1 key begin v0 := 1 else v0 := 2 end"), [0xE19E, 0x120A, 0x6001, 0x120C, 0x6002]);
+}
+
+#[test]
+fn structured_jumps_stay_below_0x1000() {
+    let error = |source: &str| compile_octo(source, "test.8o").unwrap_err();
+    assert_eq!(error(": main exit :org 0x1000 loop again").message, "'again' jumps to 0x1000, which is past 0xFFF");
+    assert_eq!(error(": main exit :org 0x2000 loop again").message, "'again' jumps to 0x2000, which is past 0xFFF");
+    assert!(error(": main exit :org 0xFFC loop while v0 == 1 again").message.contains("past 0xFFF"));
+    assert!(error(": main exit :org 0xFFC if v0 == 1 begin v1 := 2 end").message.contains("past 0xFFF"));
+    assert!(error(": main exit :org 0xFFA if v0 == 1 begin v1 := 2 else v1 := 3 end").message.contains("past 0xFFF"));
+
+    let found = error(": main\n  exit\n  :org 0x1000\n  loop\n  again");
+    assert_eq!((found.line, found.column), (5, 3));
+}
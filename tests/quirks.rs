@@ -0,0 +1,97 @@
+//! Where the quirks presets disagree, run under each of them.
+
+use chip8::{FaultPolicy, IndexIncrement, Machine, Quirks};
+
+const PRESETS: [&str; 4] = ["vip", "chip48", "schip", "xochip"];
+
+// A machine per preset after running `cycles` instructions of `program`
+fn run(program: &[u16], cycles: usize) -> Vec<Machine> {
+    let bytes: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    PRESETS
+        .iter()
+        .map(|preset| {
+            let mut machine = Machine::with_quirks(preset.parse().unwrap());
+            machine.load_rom(&bytes).unwrap();
+            for _ in 0..cycles {
+                machine.execute_cycle().unwrap();
+            }
+            machine
+        })
+        .collect()
+}
+
+#[test]
+fn preset_names() {
+    for preset in PRESETS {
+        let quirks: Quirks = preset.parse().unwrap();
+        assert_eq!(quirks.preset_name(), Some(preset));
+    }
+    assert_eq!("chip8".parse(), Ok(Quirks::cosmac_vip()));
+    assert_eq!("superchip".parse(), Ok(Quirks::superchip()));
+    assert_eq!(Quirks::default(), Quirks::cosmac_vip());
+    assert_eq!("octo".parse::<Quirks>(), Err("unknown quirks preset 'octo'".to_string()));
+
+    let custom = Quirks { clip_sprites: false, ..Quirks::cosmac_vip() };
+    assert_eq!(custom.preset_name(), None);
+}
+
+#[test]
+fn shifts() {
+    // V1 = 0x81, V2 = 0x06, V1 >>= ?, V3 = 0x81, V3 <<= V2
+    let machines = run(&[0x6181, 0x6206, 0x8126, 0x6381, 0x832E], 5);
+    let results: Vec<(u8, u8)> = machines.iter().map(|m| (m.general_registers[1], m.general_registers[3])).collect();
+    // VIP and XO-CHIP shift VY, the others shift VX in place
+    assert_eq!(results, [(0x03, 0x0C), (0x40, 0x02), (0x40, 0x02), (0x03, 0x0C)]);
+}
+
+#[test]
+fn load_and_store_move_i() {
+    // I = 0x300, store V0 to V2
+    let machines = run(&[0xA300, 0xF255], 2);
+    let indexes: Vec<u16> = machines.iter().map(|m| m.memory_register).collect();
+    assert_eq!(indexes, [0x303, 0x302, 0x300, 0x303]);
+    assert_eq!(Quirks::chip48().index_increment, IndexIncrement::ByX);
+}
+
+#[test]
+fn jump_with_offset() {
+    // V0 = 0x10, V3 = 0x20, B302
+    let machines = run(&[0x6010, 0x6320, 0xB302], 3);
+    let targets: Vec<u16> = machines.iter().map(|m| m.program_counter).collect();
+    // BNNN adds V0 to NNN, BXNN adds VX to XNN
+    assert_eq!(targets, [0x312, 0x322, 0x322, 0x312]);
+}
+
+#[test]
+fn logic_resets_vf() {
+    // VF = 5, V1 |= V2
+    let machines = run(&[0x6F05, 0x8121], 2);
+    let flags: Vec<u8> = machines.iter().map(|m| m.general_registers[0xF]).collect();
+    assert_eq!(flags, [0, 5, 5, 5]);
+}
+
+#[test]
+fn sprites_clip_or_wrap() {
+    // V0 = 62, I = font 0, draw 5 rows at (62, 0) so the top row runs off the right edge
+    let machines = run(&[0x603E, 0xA000, 0xD015], 3);
+    let wrapped: Vec<bool> = machines.iter().map(|m| m.display.pixel(0, 0)).collect();
+    assert_eq!(wrapped, [false, false, false, true]);
+    assert!(machines.iter().all(|m| m.display.pixel(62, 0)));
+}
+
+#[test]
+fn draws_wait_for_vblank() {
+    // Draw, then count in V1
+    let program: Vec<u8> = [0xD015u16, 0x7101, 0x1202].iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    let counts: Vec<u8> = PRESETS
+        .iter()
+        .map(|preset| {
+            let mut machine = Machine::with_quirks(preset.parse().unwrap());
+            machine.load_rom(&program).unwrap();
+            machine.run_frame(11, &FaultPolicy::default()).unwrap();
+            machine.general_registers[1]
+        })
+        .collect();
+    // The VIP's frame ends at the draw, the others run the rest of it
+    assert_eq!(counts, [0, 5, 5, 5]);
+}
@@ -0,0 +1,82 @@
+//! CXNN random sources and their place in save states.
+
+use chip8::{Machine, MachineState, RandomKind, RandomSource, SeededRandom, StateError, VipRandom, PROGRAM_START};
+
+// A machine looping over CXFF into V0 with the given source
+fn machine(kind: RandomKind, seed: u64) -> Machine {
+    let mut machine = Machine::new();
+    machine.load_rom(&[0xC0, 0xFF, 0x12, 0x00]).unwrap();
+    machine.set_random(kind.source(seed));
+    machine
+}
+
+// The next `count` values CXFF gives
+fn numbers(machine: &mut Machine, count: usize) -> Vec<u8> {
+    (0..count)
+        .map(|_| {
+            machine.execute_cycle().unwrap();
+            machine.execute_cycle().unwrap();
+            machine.general_registers[0]
+        })
+        .collect()
+}
+
+#[test]
+fn same_seed_same_numbers() {
+    for kind in [RandomKind::Seeded, RandomKind::CosmacVip] {
+        let first = numbers(&mut machine(kind, 42), 64);
+        assert_eq!(first, numbers(&mut machine(kind, 42), 64));
+        assert_ne!(first, numbers(&mut machine(kind, 43), 64));
+    }
+}
+
+#[test]
+fn seeded_numbers_spread() {
+    let mut random = SeededRandom::new(0);
+    let mut seen = [false; 256];
+    for _ in 0..4096 {
+        seen[random.next_byte(&[]) as usize] = true;
+    }
+    assert!(seen.iter().filter(|&&seen| seen).count() > 240);
+}
+
+#[test]
+fn vip_walks_the_program() {
+    let mut memory = vec![0; PROGRAM_START + 256];
+    let mut random = VipRandom::new(0);
+    let empty = random.next_byte(&memory);
+
+    memory[PROGRAM_START + 1] = 0x40;
+    random.set_state(0);
+    assert_ne!(random.next_byte(&memory), empty);
+}
+
+#[test]
+fn save_states_keep_the_sequence() {
+    for kind in [RandomKind::Seeded, RandomKind::CosmacVip] {
+        let mut saved = machine(kind, 7);
+        numbers(&mut saved, 10);
+        let state = MachineState::from_bytes(&saved.save_state().to_bytes()).unwrap();
+        assert_eq!(state.random_kind, kind);
+        let expected = numbers(&mut saved, 10);
+
+        saved.load_state(&state);
+        assert_eq!(numbers(&mut saved, 10), expected);
+
+        // A machine with the other kind of source switches to the saved one
+        let other = if kind == RandomKind::Seeded { RandomKind::CosmacVip } else { RandomKind::Seeded };
+        let mut restored = machine(other, 1);
+        restored.load_state(&state);
+        assert_eq!(numbers(&mut restored, 10), expected);
+    }
+}
+
+#[test]
+fn save_states_name_the_source() {
+    let mut bytes = machine(RandomKind::Seeded, 7).save_state().to_bytes();
+    let kind = bytes.len() - 9;
+    assert_eq!(bytes[kind], 0);
+    assert_eq!(machine(RandomKind::CosmacVip, 7).save_state().to_bytes()[kind], 1);
+    bytes[kind] = 9;
+    assert_eq!(MachineState::from_bytes(&bytes), Err(StateError::Invalid("random source")));
+}
@@ -0,0 +1,74 @@
+//! Rewinding through the delta encoded frame history.
+
+use chip8::{FaultPolicy, Machine, MachineState, RewindBuffer};
+
+// Counts in V0, drawing the low digit each time round
+const PROGRAM: [u8; 10] = [0x70, 0x01, 0xF0, 0x29, 0x00, 0xE0, 0xD1, 0x15, 0x12, 0x00];
+
+fn machine() -> Machine {
+    let mut machine = Machine::new();
+    machine.load_rom(&PROGRAM).unwrap();
+    machine
+}
+
+#[test]
+fn rewinding_gives_back_every_frame() {
+    let mut machine = machine();
+    let mut buffer = RewindBuffer::new(100);
+    let mut history = Vec::new();
+    for _ in 0..20 {
+        machine.run_frame(11, &FaultPolicy::default()).unwrap();
+        history.push(machine.save_state());
+        buffer.push(&machine);
+    }
+    assert_eq!(buffer.len(), 19);
+
+    history.pop();
+    while let Some(expected) = history.pop() {
+        assert!(buffer.rewind(&mut machine));
+        assert_eq!(machine.save_state(), expected);
+    }
+    assert!(!buffer.rewind(&mut machine));
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn long_runs_of_changes_round_trip() {
+    let mut machine = machine();
+    let mut buffer = RewindBuffer::new(4);
+    buffer.push(&machine);
+    let before = machine.save_state();
+
+    // More than 65535 changed bytes, and more than 65535 unchanged ones in the next frame
+    for byte in machine.memory.iter_mut() {
+        *byte = !*byte;
+    }
+    buffer.push(&machine);
+    machine.general_registers[5] = 9;
+    buffer.push(&machine);
+    let changed = MachineState::from_bytes(&machine.save_state().to_bytes()).unwrap();
+
+    assert!(buffer.rewind(&mut machine));
+    assert_eq!(machine.general_registers[5], 0);
+    assert_eq!(machine.memory, changed.memory);
+    assert!(buffer.rewind(&mut machine));
+    assert_eq!(machine.save_state(), before);
+}
+
+#[test]
+fn capacity_drops_the_oldest_frames() {
+    let mut machine = machine();
+    let mut buffer = RewindBuffer::new(3);
+    for _ in 0..10 {
+        machine.run_frame(11, &FaultPolicy::default()).unwrap();
+        buffer.push(&machine);
+    }
+    assert_eq!(buffer.len(), 3);
+    // Small changes cost far less than a whole state
+    assert!(buffer.size() < machine.save_state().to_bytes().len() + 3 * 1024);
+
+    for _ in 0..3 {
+        assert!(buffer.rewind(&mut machine));
+    }
+    assert!(!buffer.rewind(&mut machine));
+}
@@ -0,0 +1,19 @@
+#!/bin/sh
+# Download the community test ROMs the ignored cases in tests/conformance.rs
+# need into this directory. Timendus' suite is GPL-3 and BC_test comes from
+# the AC8E emulator's ROM collection, check their licences before
+# committing the ROMs.
+#
+# Then write and check the goldens:
+#   tests/roms/community/fetch.sh
+#   CHIP8_BLESS=1 cargo test --test conformance -- --ignored
+#   cargo test --test conformance -- --ignored
+set -eu
+
+cd "$(dirname "$0")"
+
+TIMENDUS=https://github.com/Timendus/chip8-test-suite/raw/main/bin
+for rom in 3-corax+.ch8 4-flags.ch8 5-quirks.ch8 6-keypad.ch8; do
+    curl -fsSL -o "$rom" "$TIMENDUS/$rom"
+done
+curl -fsSL -o BC_test.ch8 https://github.com/daniel5151/AC8E/raw/master/roms/bc_test.ch8
@@ -0,0 +1,65 @@
+//! Save states: restoring one, and rejecting data that isn't one.
+
+use chip8::{FaultPolicy, FrameScheduler, Machine, MachineState, StateError, STATE_VERSION};
+
+// Sets both timers, then loops drawing a random digit down the screen
+// through a subroutine
+const PROGRAM: [u8; 20] = [
+    0x6A, 0x05, 0xFA, 0x15, 0xFA, 0x18, 0xC0, 0xFF, 0xF0, 0x29, 0xD0, 0x15, 0x22, 0x10, 0x12, 0x06, 0x71, 0x03,
+    0x00, 0xEE,
+];
+
+fn run(machine: &mut Machine, scheduler: &mut FrameScheduler, frames: u32) {
+    for _ in 0..frames {
+        machine.run_frame(scheduler.next_frame(), &FaultPolicy::default()).unwrap();
+    }
+}
+
+fn saved() -> Vec<u8> {
+    let mut machine = Machine::new();
+    machine.load_rom(&PROGRAM).unwrap();
+    machine.press_key(3);
+    run(&mut machine, &mut FrameScheduler::new(700), 7);
+    machine.save_state().to_bytes()
+}
+
+#[test]
+fn restored_machines_run_the_same() {
+    let mut machine = Machine::new();
+    machine.load_rom(&PROGRAM).unwrap();
+    machine.press_key(3);
+    let mut scheduler = FrameScheduler::new(700);
+    run(&mut machine, &mut scheduler, 7);
+    let state = MachineState::from_bytes(&machine.save_state().to_bytes()).unwrap();
+    assert_eq!(state, machine.save_state());
+
+    let mut restored = Machine::new();
+    restored.load_state(&state);
+    let mut restored_scheduler = scheduler;
+    run(&mut machine, &mut scheduler, 30);
+    run(&mut restored, &mut restored_scheduler, 30);
+    assert_eq!(restored.save_state(), machine.save_state());
+}
+
+#[test]
+fn bad_magic_is_rejected() {
+    let mut bytes = saved();
+    bytes[0] = b'X';
+    assert_eq!(MachineState::from_bytes(&bytes), Err(StateError::NotAState));
+    assert_eq!(MachineState::from_bytes(b"C8"), Err(StateError::Truncated));
+}
+
+#[test]
+fn other_versions_are_rejected() {
+    let mut bytes = saved();
+    for version in [STATE_VERSION - 1, STATE_VERSION + 1] {
+        bytes[4..6].copy_from_slice(&version.to_le_bytes());
+        assert_eq!(MachineState::from_bytes(&bytes), Err(StateError::UnsupportedVersion(version)));
+    }
+}
+
+#[test]
+fn truncated_states_are_rejected() {
+    let bytes = saved();
+    assert_eq!(MachineState::from_bytes(&bytes[..bytes.len() - 1]), Err(StateError::Truncated));
+}
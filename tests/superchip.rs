@@ -0,0 +1,101 @@
+//! SUPER-CHIP: hires mode, scrolling, 16x16 sprites, the big font and RPL flags.
+
+use chip8::{Machine, Quirks, StepOutcome, BIG_SPRITES, BIG_SPRITE_START};
+
+fn machine(program: &[u16]) -> Machine {
+    let bytes: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    let mut machine = Machine::with_quirks(Quirks::superchip());
+    machine.load_rom(&bytes).unwrap();
+    machine
+}
+
+fn run(machine: &mut Machine, cycles: usize) {
+    for _ in 0..cycles {
+        machine.execute_cycle().unwrap();
+    }
+}
+
+fn lit(machine: &Machine) -> Vec<(usize, usize)> {
+    let display = &machine.display;
+    (0..display.height()).flat_map(|y| (0..display.width()).map(move |x| (x, y))).filter(|&(x, y)| display.pixel(x, y)).collect()
+}
+
+#[test]
+fn hires_and_lores() {
+    let mut machine = machine(&[0x00FF, 0x00FE]);
+    assert_eq!((machine.display.width(), machine.display.height()), (64, 32));
+    run(&mut machine, 1);
+    assert!(machine.display.is_hires());
+    assert_eq!((machine.display.width(), machine.display.height()), (128, 64));
+    run(&mut machine, 1);
+    assert_eq!((machine.display.width(), machine.display.height()), (64, 32));
+}
+
+#[test]
+fn scrolling() {
+    // Hires, the single pixel of the 1's top row (0x20) drawn at (8, 8), then scroll down 3, right 4, left 4 and right 4
+    let mut machine = machine(&[0x00FF, 0x6008, 0xA005, 0xD001, 0x00C3, 0x00FB, 0x00FC, 0x00FB]);
+    run(&mut machine, 4);
+    assert_eq!(lit(&machine), [(10, 8)]);
+    run(&mut machine, 1);
+    assert_eq!(lit(&machine), [(10, 11)]);
+    run(&mut machine, 1);
+    assert_eq!(lit(&machine), [(14, 11)]);
+    run(&mut machine, 1);
+    assert_eq!(lit(&machine), [(10, 11)]);
+    run(&mut machine, 1);
+    assert_eq!(lit(&machine), [(14, 11)]);
+}
+
+#[test]
+fn scrolling_drops_pixels_off_the_edges() {
+    // Hires, a pixel at (125, 61), scroll down 2, left 4, then down 1 off the bottom
+    let mut down = machine(&[0x00FF, 0x607B, 0x613D, 0xA005, 0xD011, 0x00C2, 0x00FC, 0x00C1]);
+    run(&mut down, 5);
+    assert_eq!(lit(&down), [(125, 61)]);
+    run(&mut down, 2);
+    assert_eq!(lit(&down), [(121, 63)]);
+    run(&mut down, 1);
+    assert!(lit(&down).is_empty());
+
+    // The same pixel scrolled right 4 goes off the right edge
+    let mut right = machine(&[0x00FF, 0x607B, 0x613D, 0xA005, 0xD011, 0x00FB]);
+    run(&mut right, 6);
+    assert!(lit(&right).is_empty());
+}
+
+#[test]
+fn big_sprites() {
+    // Hires, 16x16 sprite of all lit pixels from the two bytes at 0x300 onwards
+    let mut machine = machine(&[0x00FF, 0xA300, 0xD010]);
+    machine.memory[0x300..0x320].fill(0xFF);
+    run(&mut machine, 3);
+    let pixels = lit(&machine);
+    assert_eq!(pixels.len(), 256);
+    assert!(pixels.iter().all(|&(x, y)| x < 16 && y < 16));
+    assert_eq!(machine.general_registers[0xF], 0);
+}
+
+#[test]
+fn big_font() {
+    // V0 = 9, I = big digit 9, draw its 10 rows
+    let mut machine = machine(&[0x6009, 0xF030, 0xD11A]);
+    run(&mut machine, 2);
+    assert_eq!(machine.memory_register as usize, BIG_SPRITE_START + 90);
+    assert_eq!(machine.memory[BIG_SPRITE_START + 90..BIG_SPRITE_START + 100], BIG_SPRITES[90..100]);
+    run(&mut machine, 1);
+    for (y, row) in BIG_SPRITES[90..100].iter().enumerate() {
+        let drawn = (0..8).fold(0u8, |byte, x| byte << 1 | machine.display.pixel(x, y) as u8);
+        assert_eq!(drawn, *row);
+    }
+}
+
+#[test]
+fn rpl_flags_and_exit() {
+    // V0 to V2 = 1, 2, 3, save them as flags, clear V0 to V2, load 2 flags back, exit
+    let mut machine = machine(&[0x6001, 0x6102, 0x6203, 0xF275, 0x6000, 0x6100, 0x6200, 0xF185, 0x00FD]);
+    run(&mut machine, 8);
+    assert_eq!(machine.general_registers[..3], [1, 2, 0]);
+    assert_eq!(machine.execute_cycle(), Ok(StepOutcome::Exited));
+    assert!(machine.exited);
+}
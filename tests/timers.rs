@@ -0,0 +1,41 @@
+//! Spreading instructions over 60 Hz frames and ticking the timers.
+
+use chip8::{FaultPolicy, FrameScheduler, Machine, TIMER_HZ};
+
+#[test]
+fn seven_hundred_a_second_spreads_over_sixty_frames() {
+    let mut scheduler = FrameScheduler::new(700);
+    let frames: Vec<u32> = (0..TIMER_HZ).map(|_| scheduler.next_frame()).collect();
+    assert_eq!(frames.iter().sum::<u32>(), 700);
+    assert!(frames.iter().all(|&count| count == 11 || count == 12));
+    // 700 = 60 x 11 + 40, so 40 frames run an extra instruction
+    assert_eq!(frames.iter().filter(|&&count| count == 12).count(), 40);
+
+    // And the next second is the same again
+    let next: Vec<u32> = (0..TIMER_HZ).map(|_| scheduler.next_frame()).collect();
+    assert_eq!(next, frames);
+}
+
+#[test]
+fn rates_that_divide_evenly() {
+    let mut scheduler = FrameScheduler::new(600);
+    assert!((0..120).all(|_| scheduler.next_frame() == 10));
+    let mut slow = FrameScheduler::new(30);
+    let frames: Vec<u32> = (0..4).map(|_| slow.next_frame()).collect();
+    assert_eq!(frames, [0, 1, 0, 1]);
+    assert_eq!(slow.instructions_per_second(), 30);
+}
+
+#[test]
+fn timers_count_down_once_a_frame() {
+    let mut machine = Machine::new();
+    // LD V0, 30; LD DT, V0; LD ST, V0; JP self
+    machine.load_rom(&[0x60, 0x1E, 0xF0, 0x15, 0xF0, 0x18, 0x12, 0x06]).unwrap();
+    let mut scheduler = FrameScheduler::new(700);
+    machine.run_frame(scheduler.next_frame(), &FaultPolicy::default()).unwrap();
+    assert_eq!((machine.delay_timer, machine.sound_timer), (29, 29));
+    for _ in 0..40 {
+        machine.run_frame(scheduler.next_frame(), &FaultPolicy::default()).unwrap();
+    }
+    assert_eq!((machine.delay_timer, machine.sound_timer), (0, 0));
+}